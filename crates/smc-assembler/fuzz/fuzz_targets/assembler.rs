@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use smc_assembler::{
+    assembler::{Assembler, backends::Backend},
+    lexer::Lexer,
+    parser::Parser,
+};
+
+fuzz_target!(|source: &str| {
+    for target in [Backend::BatPU2, Backend::TauAnalyzersNone] {
+        let parsed = Parser::new(Lexer::new(source).collect()).parse();
+        let _ = Assembler::new(target, parsed).assemble();
+    }
+});
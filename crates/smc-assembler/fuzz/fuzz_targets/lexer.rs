@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use smc_assembler::lexer::Lexer;
+
+fuzz_target!(|source: &str| {
+    // Spans must land on character boundaries for errors to show them
+    for token in Lexer::new(source) {
+        let (span, message) = match token {
+            Ok(token) => (token.span, format!("{:?}", token.token)),
+            Err(err) => (err.span().clone(), err.to_string()),
+        };
+        span.format_error("fuzz.smc", source, &message);
+    }
+});
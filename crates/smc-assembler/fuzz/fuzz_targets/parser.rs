@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use smc_assembler::{lexer::Lexer, parser::Parser};
+
+fuzz_target!(|source: &str| {
+    Parser::new(Lexer::new(source).collect()).parse();
+});
@@ -5,10 +5,10 @@ use crate::{
         AssemblerError, LabelMap, backends::Backend, get_address_value, get_immediate_value,
         get_offset_value,
     },
-    lexer::token::{Condition, Register, Span},
+    lexer::token::{Condition, Operation, Register, Span},
     parser::{
         DefineMap,
-        operations::{Immediate, OperationWithArgs},
+        operations::{Address, Immediate, Offset, OperationWithArgs},
     },
 };
 
@@ -38,22 +38,37 @@ pub fn insert_before(defines: &mut DefineMap) -> Result<(), AssemblerError> {
     Ok(())
 }
 
-pub fn instruction_byte_size(op: &OperationWithArgs) -> usize {
-    match op {
-        _ => 1,
-    }
+/// Whether a mnemonic assembles for the BatPU-2, natively or as a pseudo-instruction
+pub fn supports(op: &Operation) -> bool {
+    use Operation::*;
+    !matches!(
+        op,
+        Or | Cpy | Adc | Mld | Mst | Pld | Pst | Inv | Cpi | Ani | Bkl | Bkr | Skp | Clr
+    )
 }
 
-pub fn assemble_operation(
-    defines: &DefineMap,
-    labels: &LabelMap,
-    operation: OperationWithArgs,
-    span: Span,
-) -> Result<u16, AssemblerError> {
-    let backend = Backend::BatPU2;
+/// Rewrite an operation the BatPU-2 has no encoding for into ones it supports
+pub fn lower_unsupported(op: &OperationWithArgs) -> Option<Vec<OperationWithArgs>> {
+    use OperationWithArgs::*;
+
+    // Two operand forms from other ISAs write their result to the first register
+    Some(match op.clone() {
+        Add2(a, b) => vec![Add3(a, b, a)],
+        Sub2(a, b) => vec![Sub3(a, b, a)],
+        And2(a, b) => vec![And3(a, b, a)],
+        Xor2(a, b) => vec![Xor3(a, b, a)],
+        Or2(a, b) => vec![Nor3(a, b, a), Nor3(a, Register::R0, a)],
+        Cpy2(dest, source) => vec![Add3(source, Register::R0, dest)],
+        Rsh1(a) => vec![Rsh2(a, a)],
+        Inv1(a) => vec![Nor3(a, Register::R0, a)],
+        Clr1(a) => vec![Add3(Register::R0, Register::R0, a)],
+        _ => return None,
+    })
+}
 
-    // Instruction lowering
-    let operation = match operation {
+/// The instruction a pseudo-instruction is encoded as
+pub fn expand_pseudo(operation: OperationWithArgs) -> OperationWithArgs {
+    match operation {
         OperationWithArgs::Cmp2(r1, r2) => OperationWithArgs::Sub3(r1, r2, Register::R0),
         OperationWithArgs::Mov2(r1, r2) => OperationWithArgs::Add3(r1, Register::R0, r2),
         OperationWithArgs::Lsh2(r1, r2) => OperationWithArgs::Add3(r1, r1, r2),
@@ -62,7 +77,27 @@ pub fn assemble_operation(
         OperationWithArgs::Inc1(r1) => OperationWithArgs::Adi2(r1, Immediate::Value(1)),
         OperationWithArgs::Dec1(r1) => OperationWithArgs::Adi2(r1, Immediate::Value(-1)),
         _ => operation,
-    };
+    }
+}
+
+pub fn instruction_byte_size(_op: &OperationWithArgs) -> usize {
+    1
+}
+
+/// Clock ticks an instruction takes, the BatPU-2 completes one instruction every tick
+pub fn cycle_cost(_op: &OperationWithArgs) -> u64 {
+    1
+}
+
+pub fn assemble_operation(
+    defines: &DefineMap,
+    labels: &LabelMap,
+    operation: OperationWithArgs,
+    span: Span,
+) -> Result<u16, AssemblerError> {
+    let backend = Backend::BatPU2;
+
+    let operation = expand_pseudo(operation);
 
     match operation {
         OperationWithArgs::Nop => Ok(0b0000 << 12),
@@ -145,8 +180,57 @@ pub fn assemble_operation(
     }
 }
 
+pub fn disassemble_operation(bytes: &[u8]) -> (Option<OperationWithArgs>, usize) {
+    use OperationWithArgs::*;
+
+    let word = match bytes {
+        [upper, lower, ..] => u16::from_be_bytes([*upper, *lower]),
+        _ => return (None, bytes.len()),
+    };
+
+    let register = |shift: u16| Register(((word >> shift) & 0xF) as u8);
+    let (a, b, c) = (register(8), register(4), register(0));
+    let immediate = (word & 0xFF) as i128;
+    let address = Address::Value((word & 0b0011_1111_1111) as i128);
+    // Offsets are signed 4 bit values
+    let offset = match ((word & 0xF) as i8) << 4 >> 4 {
+        0 => None,
+        offset => Some(Offset::Value(offset as i128)),
+    };
+
+    let op = match word >> 12 {
+        0b0000 => Nop,
+        0b0001 => Hlt,
+        0b0010 => Add3(a, b, c),
+        0b0011 => Sub3(a, b, c),
+        0b0100 => Nor3(a, b, c),
+        0b0101 => And3(a, b, c),
+        0b0110 => Xor3(a, b, c),
+        0b0111 => Rsh2(a, c),
+        0b1000 => Ldi2(a, Immediate::Value(immediate)),
+        // Adding is the same either way, negative values read better
+        0b1001 => Adi2(a, Immediate::Value(immediate as u8 as i8 as i128)),
+        0b1010 => Jmp(address),
+        0b1011 => {
+            let condition = match (word >> 10) & 0b11 {
+                0b00 => Condition::Equal,
+                0b01 => Condition::NotEqual,
+                0b10 => Condition::GreaterEqual,
+                _ => Condition::Less,
+            };
+            Brh(condition, address)
+        }
+        0b1100 => Cal(address),
+        0b1101 => Ret,
+        0b1110 => Lod(a, b, offset),
+        _ => Str(a, b, offset),
+    };
+
+    (Some(op), 2)
+}
+
 fn check_immediate_value(immediate: i128, span: &Span) -> Result<u16, AssemblerError> {
-    if immediate < -128 || immediate > 255 {
+    if !(-128..=255).contains(&immediate) {
         return Err(AssemblerError::ImmediateOutOfRange(span.clone(), immediate));
     }
 
@@ -174,5 +258,5 @@ fn check_address_value(address: i128, span: &Span) -> Result<u16, AssemblerError
         .map_err(|_| AssemblerError::AddressOutOfRange(span.clone(), address))?
         .value();
 
-    Ok((val as u16) & 0b0011_1111_1111)
+    Ok(val & 0b0011_1111_1111)
 }
@@ -4,13 +4,25 @@ use strum_macros::VariantArray;
 
 use crate::{
     assembler::{AssemblerError, LabelMap},
-    lexer::token::{Register, Span},
+    lexer::token::{Operation, Register, Span},
     parser::{DefineMap, operations::OperationWithArgs},
 };
 
 pub mod batpu2_mattbatwings_none;
 pub mod tau_analyzers_none;
 
+/// A memory instructions are placed in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub name: &'static str,
+    /// First address, in the units of jumps and labels
+    pub start: usize,
+    /// Number of addresses
+    pub size: usize,
+    /// What a single address holds
+    pub unit: &'static str,
+}
+
 #[derive(Debug, Clone, PartialEq, VariantArray)]
 pub enum Backend {
     BatPU2,
@@ -44,12 +56,119 @@ impl Backend {
         }
     }
 
+    /// The instruction an operation is encoded as, differing from it for pseudo-instructions
+    /// such as `CMP` on the BatPU-2
+    pub fn expand_pseudo(&self, op: OperationWithArgs) -> OperationWithArgs {
+        match self {
+            Backend::BatPU2 => batpu2_mattbatwings_none::expand_pseudo(op),
+            Backend::TauAnalyzersNone => tau_analyzers_none::expand_pseudo(op),
+        }
+    }
+
+    /// Whether a mnemonic assembles for the target, whatever operands it is given.
+    ///
+    /// Operations with only a [`Backend::lower_unsupported`] rewrite are not supported.
+    pub fn supports(&self, op: &Operation) -> bool {
+        match self {
+            Backend::BatPU2 => batpu2_mattbatwings_none::supports(op),
+            Backend::TauAnalyzersNone => tau_analyzers_none::supports(op),
+        }
+    }
+
+    /// Rewrite an operation the target cannot encode into a sequence it can, if one exists
+    pub fn lower_unsupported(&self, op: &OperationWithArgs) -> Option<Vec<OperationWithArgs>> {
+        match self {
+            Backend::BatPU2 => batpu2_mattbatwings_none::lower_unsupported(op),
+            Backend::TauAnalyzersNone => tau_analyzers_none::lower_unsupported(op),
+        }
+    }
+
+    /// Number of general purpose registers addressable on the target
+    pub fn register_count(&self) -> u8 {
+        match self {
+            Backend::BatPU2 => 16,
+            Backend::TauAnalyzersNone => 4,
+        }
+    }
+
+    /// Width in bytes of a word of instruction memory, words are stored big-endian.
+    ///
+    /// Jumps, labels and instruction addresses count words.
+    pub fn word_size(&self) -> usize {
+        match self {
+            Backend::BatPU2 => 2,
+            Backend::TauAnalyzersNone => 1,
+        }
+    }
+
+    /// Decode the instruction at the start of `bytes`, returning it and its size in bytes.
+    ///
+    /// The operation is `None` if the bytes encode no instruction.
+    pub fn disassemble_operation(&self, bytes: &[u8]) -> (Option<OperationWithArgs>, usize) {
+        match self {
+            Backend::BatPU2 => batpu2_mattbatwings_none::disassemble_operation(bytes),
+            Backend::TauAnalyzersNone => tau_analyzers_none::disassemble_operation(bytes),
+        }
+    }
+
+    /// Memories a program is placed in, in address order
+    pub fn segments(&self) -> &'static [Segment] {
+        match self {
+            Backend::BatPU2 => &[Segment {
+                name: "instruction memory",
+                start: 0,
+                size: 1024,
+                unit: "word",
+            }],
+            Backend::TauAnalyzersNone => &[Segment {
+                name: "program memory",
+                start: 0,
+                size: 1024,
+                unit: "byte",
+            }],
+        }
+    }
+
+    /// Number of addresses a program may use
+    pub fn capacity(&self) -> usize {
+        self.segments()
+            .iter()
+            .map(|segment| segment.start + segment.size)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Bytes of data memory, counting every bank
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Backend::BatPU2 => 256,
+            // Two banks, selected with `BKL` and `BKR`
+            Backend::TauAnalyzersNone => 512,
+        }
+    }
+
+    /// Number of return addresses the call stack holds
+    pub fn call_stack_depth(&self) -> usize {
+        16
+    }
+
+    /// Addresses taken by the instruction an operation is encoded as
     pub fn instruction_byte_size(&self, op: &OperationWithArgs) -> usize {
+        let op = &self.expand_pseudo(op.clone());
         match self {
             Backend::BatPU2 => batpu2_mattbatwings_none::instruction_byte_size(op),
             Backend::TauAnalyzersNone => tau_analyzers_none::instruction_byte_size(op),
         }
     }
+
+    /// Clock cycles the instruction an operation is encoded as takes to execute
+    pub fn cycle_cost(&self, op: &OperationWithArgs) -> u64 {
+        let op = &self.expand_pseudo(op.clone());
+        match self {
+            Backend::BatPU2 => batpu2_mattbatwings_none::cycle_cost(op),
+            Backend::TauAnalyzersNone => tau_analyzers_none::cycle_cost(op),
+        }
+    }
 }
 
 impl ValueEnum for Backend {
@@ -63,6 +182,14 @@ impl ValueEnum for Backend {
 }
 
 impl Backend {
+    /// Look up a backend by the name accepted by `--target`
+    pub fn from_name(name: &str) -> Option<Backend> {
+        Backend::VARIANTS
+            .iter()
+            .find(|backend| backend.to_str().eq_ignore_ascii_case(name))
+            .cloned()
+    }
+
     pub fn to_str(&self) -> &'static str {
         match self {
             Backend::BatPU2 => "batpu2-mattbatwings-none",
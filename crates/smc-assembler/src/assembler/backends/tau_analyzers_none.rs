@@ -4,13 +4,72 @@ use crate::{
     assembler::{
         AssemblerError, LabelMap, backends::Backend, get_address_value, get_immediate_value,
     },
-    lexer::token::{Register, Span},
+    lexer::token::{Condition, Operation, Register, Span},
     parser::{
         DefineMap,
         operations::{Address, Immediate, OperationWithArgs, SkipFlag},
     },
 };
 
+/// Whether a mnemonic assembles for Tau, natively or as a pseudo-instruction
+pub fn supports(op: &Operation) -> bool {
+    use Operation::*;
+    !matches!(op, Nop | Nor | Brh | Lod | Str | Mov | Lsh | Not | Neg)
+}
+
+/// Rewrite an operation Tau has no encoding for into ones it supports
+pub fn lower_unsupported(op: &OperationWithArgs) -> Option<Vec<OperationWithArgs>> {
+    use OperationWithArgs::*;
+
+    // Three operand forms write `a op b` into `c`, Tau always writes to the first register
+    let three = |a: Register,
+                 b: Register,
+                 c: Register,
+                 f: fn(Register, Register) -> OperationWithArgs,
+                 commutative: bool| {
+        if c == a {
+            Some(vec![f(a, b)])
+        } else if c == b && commutative {
+            Some(vec![f(b, a)])
+        } else if c != b {
+            Some(vec![Cpy2(c, a), f(c, b)])
+        } else {
+            None
+        }
+    };
+
+    let copy_then = |source: Register, dest: Register, then: OperationWithArgs| {
+        if source == dest {
+            vec![then]
+        } else {
+            vec![Cpy2(dest, source), then]
+        }
+    };
+
+    match op.clone() {
+        Nop => Some(vec![Skp(SkipFlag::Never)]),
+        Add3(a, b, c) => three(a, b, c, Add2, true),
+        Sub3(a, b, c) => three(a, b, c, Sub2, false),
+        And3(a, b, c) => three(a, b, c, And2, true),
+        Xor3(a, b, c) => three(a, b, c, Xor2, true),
+        Mov2(source, dest) => Some(vec![Cpy2(dest, source)]),
+        Rsh2(source, dest) => Some(copy_then(source, dest, Rsh1(dest))),
+        Not2(source, dest) => Some(copy_then(source, dest, Inv1(dest))),
+        Lsh2(source, dest) => Some(copy_then(source, dest, Add2(dest, dest))),
+        Brh(Condition::Equal, address) => Some(vec![Skp(SkipFlag::IfNotZero), Jmp(address)]),
+        Brh(Condition::NotEqual, address) => Some(vec![Skp(SkipFlag::IfZero), Jmp(address)]),
+        _ => None,
+    }
+}
+
+/// The instruction a pseudo-instruction is encoded as
+pub fn expand_pseudo(operation: OperationWithArgs) -> OperationWithArgs {
+    match operation {
+        OperationWithArgs::Clr1(r1) => OperationWithArgs::Ani2(r1, Immediate::Value(0)),
+        _ => operation,
+    }
+}
+
 pub fn instruction_byte_size(op: &OperationWithArgs) -> usize {
     use OperationWithArgs::*;
     match op {
@@ -19,6 +78,72 @@ pub fn instruction_byte_size(op: &OperationWithArgs) -> usize {
     }
 }
 
+/// Clock cycles an instruction takes, one for every byte fetched
+pub fn cycle_cost(op: &OperationWithArgs) -> u64 {
+    instruction_byte_size(op) as u64
+}
+
+pub fn disassemble_operation(bytes: &[u8]) -> (Option<OperationWithArgs>, usize) {
+    use OperationWithArgs::*;
+
+    let Some(&byte) = bytes.first() else {
+        return (None, 0);
+    };
+
+    let destination = Register((byte >> 2) & 0b11);
+    let source = Register(byte & 0b11);
+
+    let op = match byte >> 4 {
+        opcode @ 0b0000..=0b1011 => [
+            Add2, Sub2, Xor2, And2, Or2, Cmp2, Cpy2, Adc2, Mld2, Mst2, Pld2, Pst2,
+        ][opcode as usize](destination, source),
+        0b1100 => [Rsh1, Inv1, Inc1, Dec1][(byte & 0b11) as usize](destination),
+        0b1101 => {
+            let Some(&immediate) = bytes.get(1) else {
+                return (None, 1);
+            };
+            let immediate = Immediate::Value(immediate as i128);
+            return (
+                Some([Ldi2, Adi2, Cpi2, Ani2][(byte & 0b11) as usize](
+                    destination,
+                    immediate,
+                )),
+                2,
+            );
+        }
+        0b1110 => {
+            let Some(&lower) = bytes.get(1) else {
+                return (None, 1);
+            };
+            let address = Address::Value(((((byte >> 2) & 0b11) as i128) << 8) | lower as i128);
+            let op = match byte & 0b11 {
+                0b00 => Some(Jmp(address)),
+                0b01 => Some(Cal(address)),
+                _ => None,
+            };
+            return (op, 2);
+        }
+        _ if byte & 0b1000 == 0 => match byte & 0b111 {
+            0b000 => Bkl,
+            0b001 => Bkr,
+            0b010 => Hlt,
+            0b011 => Ret,
+            _ => return (None, 1),
+        },
+        _ => match byte & 0b111 {
+            0b000 => Skp(SkipFlag::Never),
+            0b001 => Skp(SkipFlag::IfZero),
+            0b010 => Skp(SkipFlag::IfNotZero),
+            0b011 => Skp(SkipFlag::IfNegative),
+            0b100 => Skp(SkipFlag::IfNotNegative),
+            0b101 => Skp(SkipFlag::Always),
+            _ => return (None, 1),
+        },
+    };
+
+    (Some(op), 1)
+}
+
 fn assemble_2reg(
     span: &Span,
     opcode: u8,
@@ -29,8 +154,8 @@ fn assemble_2reg(
     let backend = Backend::TauAnalyzersNone;
 
     Ok(opcode << 4
-        | (register.check(&backend, &span)? & 0b11) << 2
-        | (register1.check(&backend, &span)? & 0b11))
+        | (register.check(&backend, span)? & 0b11) << 2
+        | (register1.check(&backend, span)? & 0b11))
 }
 
 fn assemble_1reg(span: &Span, operation: u8, register: Register) -> Result<u8, AssemblerError> {
@@ -52,7 +177,7 @@ fn assemble_immediate(
         0b1101 << 4 | (register.check(&backend, span)? & 0b11) << 2 | (operation & 0b11);
 
     let immediate = get_immediate_value(span, defines, immediate)?;
-    if immediate < -128 || immediate > 255 {
+    if !(-128..=255).contains(&immediate) {
         return Err(AssemblerError::ImmediateOutOfRange(span.clone(), immediate));
     }
 
@@ -70,7 +195,7 @@ fn check_address_value(address: i128, span: &Span) -> Result<u16, AssemblerError
         .map_err(|_| AssemblerError::AddressOutOfRange(span.clone(), address))?
         .value();
 
-    Ok((val as u16) & 0b0011_1111_1111)
+    Ok(val & 0b0011_1111_1111)
 }
 
 fn assemble_branch(
@@ -111,7 +236,7 @@ fn assemble_skip(flag: SkipFlag) -> u8 {
         SkipFlag::IfNotNegative => 0b100,
         SkipFlag::Always => 0b101,
     };
-    0b1111_1 << 3 | (flag_bits & 0b111)
+    0b11111 << 3 | (flag_bits & 0b111)
 }
 
 pub fn assemble_operation(
@@ -122,10 +247,7 @@ pub fn assemble_operation(
 ) -> Result<Vec<u8>, AssemblerError> {
     let mut result = Vec::new();
 
-    let operation = match operation {
-        OperationWithArgs::Clr1(r1) => OperationWithArgs::Ani2(r1, Immediate::Value(0)),
-        _ => operation,
-    };
+    let operation = expand_pseudo(operation);
 
     match operation {
         // 2 operand instructions
@@ -50,11 +50,47 @@ pub enum AssemblerError {
 
     #[error("AssemblerError: Immediate out of range {1}")]
     ImmediateOutOfRange(Span, i128),
+
+    #[error("AssemblerError: Program does not fit in memory, it needs {1} addresses of {2}")]
+    ProgramTooLarge(Span, usize, usize),
+}
+
+impl AssemblerError {
+    pub fn span(&self) -> &Span {
+        match self {
+            AssemblerError::ParserError(parser_error) => parser_error.span(),
+            AssemblerError::DefineNotFound(span, _)
+            | AssemblerError::LabelNotFound(span, _)
+            | AssemblerError::UnsupportedOperation(span, _)
+            | AssemblerError::InvalidRegister(span, _)
+            | AssemblerError::AddressOutOfRange(span, _)
+            | AssemblerError::OffsetOutOfRange(span, _)
+            | AssemblerError::InvalidCondition(span, _)
+            | AssemblerError::ImmediateOutOfRange(span, _)
+            | AssemblerError::ProgramTooLarge(span, _, _) => span,
+        }
+    }
+}
+
+/// An operation together with where it was placed and what it encoded to
+#[derive(Debug, Clone)]
+pub struct AssembledInstruction {
+    pub address: usize,
+    pub span: Span,
+    /// The operation as written
+    pub op: OperationWithArgs,
+    /// The instruction encoded for it, differing for pseudo-instructions such as `CMP`
+    pub encoded: OperationWithArgs,
+    /// Encoded bytes, empty if the operation failed to assemble
+    pub bytes: Vec<u8>,
 }
 
 #[derive(Debug)]
 pub struct AssemblerResult {
     pub result: Result<Vec<u8>, Vec<AssemblerError>>,
+    /// Every operation by address, resolved to lines with
+    /// [`SourceMap`](crate::sources::map::SourceMap)
+    pub instructions: Vec<AssembledInstruction>,
     pub defines: DefineMap,
     pub labels: LabelMap,
     pub define_spans: DefineSpanMap,
@@ -96,25 +132,52 @@ impl Assembler {
                 }
                 ParsedItem::Operation(spanned_op) => {
                     let byte_size = self.target.instruction_byte_size(&spanned_op.op);
+                    operations.push((instruction_count, spanned_op));
                     instruction_count += byte_size;
-                    operations.push(spanned_op);
                 }
             }
         }
 
-        for SpannedOperation { op, span } in operations {
+        // Point at the first instruction past the end, later ones only repeat the error
+        let capacity = self.target.capacity();
+        if let Some((_, overflowing)) = operations.iter().find(|(address, spanned_op)| {
+            address + self.target.instruction_byte_size(&spanned_op.op) > capacity
+        }) {
+            errors.push(AssemblerError::ProgramTooLarge(
+                overflowing.span.clone(),
+                instruction_count,
+                capacity,
+            ));
+        }
+
+        let mut instructions = Vec::with_capacity(operations.len());
+        for (address, SpannedOperation { op, span }) in operations {
+            let mut instruction = AssembledInstruction {
+                address,
+                span: span.clone(),
+                op: op.clone(),
+                encoded: self.target.expand_pseudo(op.clone()),
+                bytes: Vec::new(),
+            };
+
             match self
                 .target
                 .assemble_operation(&self.parser_results.defines, &labels, op, span)
             {
-                Ok(mut word) => bytes.append(&mut word),
+                Ok(word) => {
+                    bytes.extend_from_slice(&word);
+                    instruction.bytes = word;
+                }
                 Err(e) => errors.push(e),
             }
+
+            instructions.push(instruction);
         }
 
         if errors.is_empty() {
             AssemblerResult {
                 result: Ok(bytes),
+                instructions,
                 defines: self.parser_results.defines,
                 labels,
                 define_spans: self.parser_results.define_spans,
@@ -125,6 +188,7 @@ impl Assembler {
         } else {
             AssemblerResult {
                 result: Err(errors),
+                instructions,
                 defines: self.parser_results.defines,
                 labels,
                 define_spans: self.parser_results.define_spans,
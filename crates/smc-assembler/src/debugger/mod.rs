@@ -0,0 +1,762 @@
+use std::{fmt::Write as _, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    CompiledProgram,
+    assembler::backends::Backend,
+    emulator::{Access, AccessKind, Emulator, EmulatorError, Io},
+    lexer::token::SourceId,
+    parser::operations::{Address, OperationWithArgs},
+    sources::map::SourceMap,
+};
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum DebuggerError {
+    #[error("Unknown command `{0}`, try `help`")]
+    UnknownCommand(String),
+
+    #[error("Missing {0}")]
+    MissingArgument(&'static str),
+
+    #[error("Invalid number `{0}`")]
+    InvalidNumber(String),
+
+    #[error("Unknown label `{0}`")]
+    UnknownLabel(String),
+
+    #[error("Unknown source file `{0}`")]
+    UnknownFile(String),
+
+    #[error("No code at or after {0}")]
+    NoCode(String),
+
+    #[error("Unknown address `{0}`, expected a number or define")]
+    UnknownAddress(String),
+
+    #[error("No breakpoint or watchpoint {0}")]
+    UnknownId(usize),
+}
+
+/// Accesses a watchpoint stops at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub address: usize,
+    /// How the breakpoint was given
+    pub location: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub id: usize,
+    /// Data address, or port on targets with separate ports
+    pub address: u8,
+    pub kind: WatchKind,
+    /// How the address was given
+    pub name: String,
+}
+
+/// Why execution paused
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    /// The requested steps were taken
+    Stepped,
+    Breakpoint(usize),
+    Watchpoint(usize, Access),
+    Halted,
+    Error(EmulatorError),
+    /// `continue` or `next` ran for the maximum number of cycles
+    Limit,
+}
+
+/// A debugger command, as typed at the prompt
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Break(String),
+    Delete(usize),
+    Watch(String, WatchKind),
+    Step(usize),
+    Next(usize),
+    Finish,
+    Continue,
+    Registers,
+    Flags,
+    Memory(String, usize),
+    Ports,
+    Screen,
+    Stack,
+    Info,
+    List,
+    Reset,
+    Help,
+    Quit,
+}
+
+pub const HELP: &str = "\
+break LOCATION         (b)  pause before the code at `.label`, `LINE`, `FILE:LINE` or `*ADDRESS`
+delete ID              (d)  remove a breakpoint or watchpoint
+watch ADDRESS          (w)  pause after writes to a data address or define such as `pixel_x`
+rwatch ADDRESS         (rw) pause after reads, e.g. of `rng`
+awatch ADDRESS         (aw) pause after reads and writes
+step [N]               (s)  execute N instructions
+next [N]               (n)  like step, running called subroutines to their return
+finish                 (f)  run until the current subroutine returns
+continue               (c)  run until a breakpoint, watchpoint or halt
+registers              (r)  show the registers
+flags                       show the flags
+memory ADDRESS [COUNT] (x)  show data memory
+ports                       show the last value read or written at every port
+screen                      show the screen and displays
+stack                  (bt) show the call stack
+info                        list breakpoints and watchpoints
+list                   (l)  show the source around the current instruction
+reset                       start the program over, keeping breakpoints
+quit                   (q)  exit";
+
+impl FromStr for Command {
+    type Err = DebuggerError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let mut argument = |what| {
+            words
+                .next()
+                .map(str::to_string)
+                .ok_or(DebuggerError::MissingArgument(what))
+        };
+
+        Ok(match name {
+            "break" | "b" => Command::Break(argument("location")?),
+            "delete" | "d" => Command::Delete(parse_number(&argument("id")?)?),
+            "watch" | "w" => Command::Watch(argument("address")?, WatchKind::Write),
+            "rwatch" | "rw" => Command::Watch(argument("address")?, WatchKind::Read),
+            "awatch" | "aw" => Command::Watch(argument("address")?, WatchKind::Access),
+            "step" | "s" => Command::Step(argument("count").map_or(Ok(1), |n| parse_number(&n))?),
+            "next" | "n" => Command::Next(argument("count").map_or(Ok(1), |n| parse_number(&n))?),
+            "finish" | "f" => Command::Finish,
+            "continue" | "c" => Command::Continue,
+            "registers" | "r" => Command::Registers,
+            "flags" => Command::Flags,
+            "memory" | "x" => {
+                let address = argument("address")?;
+                let count = argument("count").map_or(Ok(1), |n| parse_number(&n))?;
+                Command::Memory(address, count)
+            }
+            "ports" => Command::Ports,
+            "screen" => Command::Screen,
+            "stack" | "bt" => Command::Stack,
+            "info" => Command::Info,
+            "list" | "l" => Command::List,
+            "reset" => Command::Reset,
+            "help" | "h" => Command::Help,
+            "quit" | "q" => Command::Quit,
+            _ => return Err(DebuggerError::UnknownCommand(name.to_string())),
+        })
+    }
+}
+
+/// Parse a decimal, `0x` hex or `0b` binary number
+pub fn parse_number(text: &str) -> Result<usize, DebuggerError> {
+    let parsed = if let Some(hex) = text.strip_prefix("0x") {
+        usize::from_str_radix(hex, 16)
+    } else if let Some(binary) = text.strip_prefix("0b") {
+        usize::from_str_radix(binary, 2)
+    } else {
+        text.parse()
+    };
+
+    parsed.map_err(|_| DebuggerError::InvalidNumber(text.to_string()))
+}
+
+/// Runs a compiled program on the emulator, pausing at breakpoints and watchpoints
+#[derive(Debug)]
+pub struct Debugger<I> {
+    program: CompiledProgram,
+    source_map: SourceMap,
+    pub emulator: Emulator<I>,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    next_id: usize,
+    /// Last value read or written at every port
+    ports: Vec<Option<u8>>,
+    /// Most instructions `continue` and `next` run before pausing
+    pub max_cycles: u64,
+}
+
+impl<I: Io> Debugger<I> {
+    pub fn new(program: CompiledProgram, io: I) -> Self {
+        Debugger {
+            emulator: Emulator::with_io(program.target.clone(), &program.bytes, io),
+            source_map: program.source_map(),
+            program,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_id: 1,
+            ports: vec![None; 256],
+            max_cycles: 10_000_000,
+        }
+    }
+
+    pub fn program(&self) -> &CompiledProgram {
+        &self.program
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Run a command, returning what to show for it
+    pub fn execute(&mut self, command: Command) -> Result<String, DebuggerError> {
+        Ok(match command {
+            Command::Break(location) => {
+                let id = self.add_breakpoint(&location)?;
+                let address = self.breakpoints.last().map_or(0, |b| b.address);
+                format!("Breakpoint {} at {}", id, self.describe_address(address))
+            }
+            Command::Delete(id) => {
+                self.remove(id)?;
+                format!("Deleted {}", id)
+            }
+            Command::Watch(address, kind) => {
+                let id = self.add_watchpoint(&address, kind)?;
+                format!("Watchpoint {} on {}", id, address)
+            }
+            Command::Step(count) => {
+                let stop = self.step(count);
+                self.describe_stop(&stop)
+            }
+            Command::Next(count) => {
+                let stop = self.next(count);
+                self.describe_stop(&stop)
+            }
+            Command::Finish => {
+                let stop = self.finish();
+                self.describe_stop(&stop)
+            }
+            Command::Continue => {
+                let stop = self.run();
+                self.describe_stop(&stop)
+            }
+            Command::Registers => self.describe_registers(),
+            Command::Flags => self.describe_flags(),
+            Command::Memory(address, count) => {
+                let address = self.resolve_data_address(&address)?;
+                self.describe_memory(address, count)
+            }
+            Command::Ports => self.describe_ports(),
+            Command::Screen => match self.emulator.io.display() {
+                Some(display) => display,
+                None => "No display on this target".to_string(),
+            },
+            Command::Stack => self.describe_stack(),
+            Command::Info => self.describe_points(),
+            Command::List => self.describe_source(),
+            Command::Reset => {
+                self.emulator.reset();
+                self.ports.fill(None);
+                format!("Reset\n{}", self.describe_address(self.emulator.pc))
+            }
+            Command::Help => HELP.to_string(),
+            Command::Quit => String::new(),
+        })
+    }
+
+    /// Add a breakpoint at a location as accepted by `break`, returning its id
+    pub fn add_breakpoint(&mut self, location: &str) -> Result<usize, DebuggerError> {
+        let address = self.resolve_location(location)?;
+        Ok(self.add_breakpoint_at(address, location))
+    }
+
+    /// Add a breakpoint at an already resolved address, returning its id
+    pub fn add_breakpoint_at(&mut self, address: usize, location: &str) -> usize {
+        let id = self.take_id();
+        self.breakpoints.push(Breakpoint {
+            id,
+            address,
+            location: location.to_string(),
+        });
+        id
+    }
+
+    /// Add a watchpoint on a data address or define, returning its id
+    pub fn add_watchpoint(
+        &mut self,
+        address: &str,
+        kind: WatchKind,
+    ) -> Result<usize, DebuggerError> {
+        let resolved = self.resolve_data_address(address)?;
+        let id = self.take_id();
+        self.watchpoints.push(Watchpoint {
+            id,
+            address: resolved as u8,
+            kind,
+            name: address.to_string(),
+        });
+        Ok(id)
+    }
+
+    /// Remove the breakpoint or watchpoint with `id`
+    pub fn remove(&mut self, id: usize) -> Result<(), DebuggerError> {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        self.watchpoints.retain(|watchpoint| watchpoint.id != id);
+
+        if self.breakpoints.len() + self.watchpoints.len() == count {
+            return Err(DebuggerError::UnknownId(id));
+        }
+        Ok(())
+    }
+
+    /// Remove every breakpoint
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    fn take_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// Address of the code at `.label`, `LINE` of the entry file, `FILE:LINE` or `*ADDRESS`
+    pub fn resolve_location(&self, location: &str) -> Result<usize, DebuggerError> {
+        if let Some(label) = location.strip_prefix('.') {
+            return self
+                .program
+                .labels
+                .get(label)
+                .copied()
+                .ok_or_else(|| DebuggerError::UnknownLabel(location.to_string()));
+        }
+        if let Some(address) = location.strip_prefix('*') {
+            return parse_number(address);
+        }
+
+        let (source, line) = match location.rsplit_once(':') {
+            Some((file, line)) => (self.find_source(file)?, parse_number(line)?),
+            None => (0, parse_number(location)?),
+        };
+        self.line_address(source, line)
+            .ok_or_else(|| DebuggerError::NoCode(location.to_string()))
+    }
+
+    /// Index of the source file whose path ends with `file`
+    pub fn find_source(&self, file: &str) -> Result<SourceId, DebuggerError> {
+        self.program
+            .sources
+            .files
+            .iter()
+            .position(|source| source.path.ends_with(file))
+            .ok_or_else(|| DebuggerError::UnknownFile(file.to_string()))
+    }
+
+    /// Address of the first instruction on `line`, or on the closest line after it with code
+    pub fn line_address(&self, source: SourceId, line: usize) -> Option<usize> {
+        self.source_map.line_address(source, line)
+    }
+
+    /// Source file and line of the instruction at `address`
+    pub fn source_line(&self, address: usize) -> Option<(SourceId, usize)> {
+        self.source_map
+            .entry(address)
+            .filter(|entry| entry.address == address)
+            .map(|entry| (entry.file, entry.line))
+    }
+
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    /// Closest label at or before `address`
+    pub fn label_before(&self, address: usize) -> Option<&str> {
+        self.program
+            .labels
+            .iter()
+            .filter(|(_, label)| **label <= address)
+            .max_by_key(|(name, label)| (**label, std::cmp::Reverse(name.as_str())))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// A data address, as a number or define
+    pub fn resolve_data_address(&self, address: &str) -> Result<usize, DebuggerError> {
+        let value = match self.program.defines.get(address) {
+            Some(value) => *value as usize,
+            None => parse_number(address)
+                .map_err(|_| DebuggerError::UnknownAddress(address.to_string()))?,
+        };
+
+        if value > u8::MAX as usize {
+            return Err(DebuggerError::UnknownAddress(address.to_string()));
+        }
+        Ok(value)
+    }
+
+    /// Execute `count` instructions, stopping early at breakpoints, watchpoints and halts
+    pub fn step(&mut self, count: usize) -> Stop {
+        for i in 0..count {
+            let stop = self.step_once();
+            if stop != Stop::Stepped {
+                return stop;
+            }
+            if i + 1 < count
+                && let Some(id) = self.breakpoint_at(self.emulator.pc)
+            {
+                return Stop::Breakpoint(id);
+            }
+        }
+        Stop::Stepped
+    }
+
+    /// Like [`Debugger::step`], running subroutines called by `CAL` until they return
+    pub fn next(&mut self, count: usize) -> Stop {
+        for i in 0..count {
+            let stop = match self.call_return() {
+                Some((depth, return_address)) => self.run_until(|emulator| {
+                    emulator.call_stack.len() == depth && emulator.pc == return_address
+                }),
+                None => self.step_once(),
+            };
+
+            if stop != Stop::Stepped {
+                return stop;
+            }
+            if i + 1 < count
+                && let Some(id) = self.breakpoint_at(self.emulator.pc)
+            {
+                return Stop::Breakpoint(id);
+            }
+        }
+        Stop::Stepped
+    }
+
+    /// Call stack depth and address execution returns to, if the current instruction is a `CAL`
+    pub fn call_return(&self) -> Option<(usize, usize)> {
+        let Some(OperationWithArgs::Cal(_)) = self.emulator.current() else {
+            return None;
+        };
+        let (_, size) = self.emulator.instruction_at(self.emulator.pc);
+
+        Some((
+            self.emulator.call_stack.len(),
+            self.emulator.pc + size / self.emulator.target().word_size(),
+        ))
+    }
+
+    /// Run until a breakpoint, watchpoint, halt or error
+    pub fn run(&mut self) -> Stop {
+        self.run_until(|_| false)
+    }
+
+    /// Run until the current subroutine returns
+    pub fn finish(&mut self) -> Stop {
+        let depth = self.emulator.call_stack.len();
+        self.run_until(|emulator| emulator.call_stack.len() < depth)
+    }
+
+    /// Run until `done` holds, or something else stops execution.
+    ///
+    /// A breakpoint at the current instruction is passed, as execution is paused on it.
+    pub fn run_until(&mut self, done: impl Fn(&Emulator<I>) -> bool) -> Stop {
+        let start = self.emulator.cycles;
+        loop {
+            let stop = self.step_once();
+            if stop != Stop::Stepped || done(&self.emulator) {
+                return stop;
+            }
+            if let Some(id) = self.breakpoint_at(self.emulator.pc) {
+                return Stop::Breakpoint(id);
+            }
+            if self.emulator.cycles - start >= self.max_cycles {
+                return Stop::Limit;
+            }
+        }
+    }
+
+    fn step_once(&mut self) -> Stop {
+        let step = match self.emulator.step() {
+            Ok(step) => step,
+            Err(EmulatorError::Halted) => return Stop::Halted,
+            Err(error) => return Stop::Error(error),
+        };
+
+        let mut stop = Stop::Stepped;
+        for access in step.accesses {
+            if access.port {
+                self.ports[access.address as usize] = Some(access.value);
+            }
+
+            let hit = self.watchpoints.iter().find(|watchpoint| {
+                watchpoint.address == access.address
+                    && match watchpoint.kind {
+                        WatchKind::Read => access.kind == AccessKind::Read,
+                        WatchKind::Write => access.kind == AccessKind::Write,
+                        WatchKind::Access => true,
+                    }
+            });
+            if let (Stop::Stepped, Some(watchpoint)) = (&stop, hit) {
+                stop = Stop::Watchpoint(watchpoint.id, access);
+            }
+        }
+
+        if stop == Stop::Stepped && self.emulator.halted() {
+            return Stop::Halted;
+        }
+        stop
+    }
+
+    fn breakpoint_at(&self, address: usize) -> Option<usize> {
+        self.breakpoints
+            .iter()
+            .find(|breakpoint| breakpoint.address == address)
+            .map(|breakpoint| breakpoint.id)
+    }
+
+    /// Last value read or written at `port`
+    pub fn port(&self, port: u8) -> Option<u8> {
+        self.ports[port as usize]
+    }
+
+    /// Defines naming a port of the target, in port order
+    pub fn port_names(&self) -> Vec<(&str, u8)> {
+        let mut names: Vec<_> = match self.program.target {
+            Backend::BatPU2 => self
+                .program
+                .defines
+                .iter()
+                .filter(|(_, value)| (240.0..=255.0).contains(*value))
+                .map(|(name, value)| (name.as_str(), *value as u8))
+                .collect(),
+            Backend::TauAnalyzersNone => Vec::new(),
+        };
+        names.sort_by_key(|(name, port)| (*port, *name));
+        names
+    }
+
+    /// Address, source location and code of the instruction at `address`
+    pub fn describe_address(&self, address: usize) -> String {
+        let location = self.source_line(address).and_then(|(source, line)| {
+            let file = self.program.sources.get(source)?;
+            let name = file.path.file_name().unwrap_or(file.path.as_os_str());
+            let code = file.text.lines().nth(line - 1).unwrap_or_default().trim();
+            Some(format!("{}:{}  {}", name.to_string_lossy(), line, code))
+        });
+
+        match location {
+            Some(location) => format!("{:04x}  {}", address, location),
+            None => match self.emulator.instruction_at(address).0 {
+                Some(op) => format!("{:04x}  {}", address, op),
+                None => format!("{:04x}  invalid instruction", address),
+            },
+        }
+    }
+
+    pub fn describe_stop(&self, stop: &Stop) -> String {
+        let here = self.describe_address(self.emulator.pc);
+        match stop {
+            Stop::Stepped => here,
+            Stop::Breakpoint(id) => format!("Breakpoint {}\n{}", id, here),
+            Stop::Watchpoint(id, access) => {
+                let name = self
+                    .watchpoints
+                    .iter()
+                    .find(|watchpoint| watchpoint.id == *id)
+                    .map_or("", |watchpoint| watchpoint.name.as_str());
+                let action = match access.kind {
+                    AccessKind::Read => "read",
+                    AccessKind::Write => "wrote",
+                };
+                format!(
+                    "Watchpoint {}: {} {} {}\n{}",
+                    id, action, name, access.value, here
+                )
+            }
+            Stop::Halted => format!("Program halted after {} cycles", self.emulator.cycles),
+            Stop::Error(error) => error.to_string(),
+            Stop::Limit => format!("Paused after {} cycles\n{}", self.max_cycles, here),
+        }
+    }
+
+    fn describe_registers(&self) -> String {
+        self.emulator
+            .registers
+            .chunks(4)
+            .enumerate()
+            .map(|(row, registers)| {
+                registers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| {
+                        format!(
+                            "{:<4}{:>3} 0x{:02x}",
+                            format!("r{}", row * 4 + i),
+                            value,
+                            value
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("    ")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn describe_flags(&self) -> String {
+        let flags = &self.emulator.flags;
+        let mut text = format!("zero {}  carry {}", flags.zero as u8, flags.carry as u8);
+        if self.program.target == Backend::TauAnalyzersNone {
+            let _ = write!(text, "  negative {}", flags.negative as u8);
+        }
+        text
+    }
+
+    fn describe_memory(&self, address: usize, count: usize) -> String {
+        let base = self.emulator.bank * 256;
+        let end = (address + count.max(1)).min(256);
+        (address..end)
+            .collect::<Vec<_>>()
+            .chunks(8)
+            .map(|row| {
+                let values: Vec<_> = row
+                    .iter()
+                    .map(|address| format!("{:02x}", self.emulator.memory[base + address]))
+                    .collect();
+                format!("{:02x}: {}", row[0], values.join(" "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn describe_ports(&self) -> String {
+        let names = self.port_names();
+        let mut lines = Vec::new();
+        for port in 0..=u8::MAX {
+            let name: Vec<_> = names
+                .iter()
+                .filter(|(_, named)| *named == port)
+                .map(|(name, _)| *name)
+                .collect();
+            match self.ports[port as usize] {
+                Some(value) => lines.push(format!("{:3} {:<20}{}", port, name.join(", "), value)),
+                None if !name.is_empty() => {
+                    lines.push(format!("{:3} {:<20}-", port, name.join(", ")))
+                }
+                None => {}
+            }
+        }
+
+        if lines.is_empty() {
+            "No port accessed yet".to_string()
+        } else {
+            lines.join("\n")
+        }
+    }
+
+    /// Address of the current instruction, then of the `CAL` of every subroutine being run,
+    /// innermost first
+    pub fn frames(&self) -> Vec<usize> {
+        let target = self.emulator.target();
+        // Return addresses follow the `CAL`
+        let call_size = target.instruction_byte_size(&OperationWithArgs::Cal(Address::Value(0)));
+
+        std::iter::once(self.emulator.pc)
+            .chain(
+                self.emulator
+                    .call_stack
+                    .iter()
+                    .rev()
+                    .map(|address| address.saturating_sub(call_size)),
+            )
+            .collect()
+    }
+
+    fn describe_stack(&self) -> String {
+        self.frames()
+            .into_iter()
+            .enumerate()
+            .map(|(i, address)| {
+                let label = self
+                    .label_before(address)
+                    .map(|label| format!(".{}  ", label))
+                    .unwrap_or_default();
+                format!("#{} {}{}", i, label, self.describe_address(address))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn describe_points(&self) -> String {
+        let mut lines = Vec::new();
+        for breakpoint in &self.breakpoints {
+            lines.push(format!(
+                "{:<3} breakpoint  {}  {}",
+                breakpoint.id,
+                breakpoint.location,
+                self.describe_address(breakpoint.address)
+            ));
+        }
+        for watchpoint in &self.watchpoints {
+            let kind = match watchpoint.kind {
+                WatchKind::Read => "rwatch",
+                WatchKind::Write => "watch",
+                WatchKind::Access => "awatch",
+            };
+            lines.push(format!(
+                "{:<3} {:<11} {} ({})",
+                watchpoint.id, kind, watchpoint.name, watchpoint.address
+            ));
+        }
+
+        if lines.is_empty() {
+            "No breakpoints or watchpoints".to_string()
+        } else {
+            lines.join("\n")
+        }
+    }
+
+    fn describe_source(&self) -> String {
+        let Some((source, current)) = self.source_line(self.emulator.pc) else {
+            return self.describe_address(self.emulator.pc);
+        };
+        let Some(file) = self.program.sources.get(source) else {
+            return String::new();
+        };
+
+        let breakpoint_lines: Vec<_> = self
+            .breakpoints
+            .iter()
+            .filter_map(|breakpoint| self.source_line(breakpoint.address))
+            .filter(|(breakpoint_source, _)| *breakpoint_source == source)
+            .map(|(_, line)| line)
+            .collect();
+
+        file.text
+            .lines()
+            .enumerate()
+            .map(|(i, text)| (i + 1, text))
+            .skip(current.saturating_sub(6))
+            .take(11)
+            .map(|(line, text)| {
+                let marker = if line == current { '>' } else { ' ' };
+                let breakpoint = if breakpoint_lines.contains(&line) {
+                    '*'
+                } else {
+                    ' '
+                };
+                format!("{}{}{:>5}  {}", marker, breakpoint, line, text)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
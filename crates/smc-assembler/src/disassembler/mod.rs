@@ -0,0 +1,109 @@
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Write},
+};
+
+use crate::{
+    assembler::backends::Backend,
+    formatter::FormatOptions,
+    parser::operations::{Address, OperationWithArgs},
+};
+
+/// An instruction decoded from machine code
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisassembledInstruction {
+    /// Address in the units used by jumps of the target
+    pub address: usize,
+    pub bytes: Vec<u8>,
+    /// `None` if the bytes encode no instruction of the target
+    pub op: Option<OperationWithArgs>,
+}
+
+/// Decode every instruction of `bytes`
+pub fn disassemble(target: &Backend, bytes: &[u8]) -> Vec<DisassembledInstruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let (op, size) = target.disassemble_operation(&bytes[offset..]);
+        let size = size.max(1);
+        let end = (offset + size).min(bytes.len());
+
+        instructions.push(DisassembledInstruction {
+            address: offset / target.word_size(),
+            bytes: bytes[offset..end].to_vec(),
+            op,
+        });
+        offset = end;
+    }
+
+    instructions
+}
+
+/// Write instructions back as source, with a label at every address jumped to.
+///
+/// Bytes encoding no instruction are written as comments, so the output only reassembles to
+/// the same bytes if there are none.
+pub fn to_source(instructions: &[DisassembledInstruction]) -> Result<String, fmt::Error> {
+    let indent = " ".repeat(FormatOptions::default().indent);
+    let targets: BTreeSet<usize> = instructions
+        .iter()
+        .filter_map(|instruction| jump_target(instruction.op.as_ref()?))
+        .filter(|target| {
+            instructions
+                .iter()
+                .any(|instruction| instruction.address == *target)
+        })
+        .collect();
+
+    let mut source = String::new();
+    for instruction in instructions {
+        if targets.contains(&instruction.address) {
+            writeln!(source, "{}", label(instruction.address))?;
+        }
+
+        match &instruction.op {
+            Some(op) => writeln!(source, "{}{}", indent, with_label(op, &targets))?,
+            None => writeln!(
+                source,
+                "{}// unknown instruction: {}",
+                indent,
+                instruction
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )?,
+        }
+    }
+
+    Ok(source)
+}
+
+fn label(address: usize) -> String {
+    format!(".label_{}", address)
+}
+
+fn jump_target(op: &OperationWithArgs) -> Option<usize> {
+    match op {
+        OperationWithArgs::Jmp(Address::Value(address))
+        | OperationWithArgs::Cal(Address::Value(address))
+        | OperationWithArgs::Brh(_, Address::Value(address)) => usize::try_from(*address).ok(),
+        _ => None,
+    }
+}
+
+fn with_label(op: &OperationWithArgs, targets: &BTreeSet<usize>) -> OperationWithArgs {
+    let labelled = match jump_target(op) {
+        Some(target) if targets.contains(&target) => Address::Label(label(target)[1..].to_string()),
+        _ => return op.clone(),
+    };
+
+    match op.clone() {
+        OperationWithArgs::Jmp(_) => OperationWithArgs::Jmp(labelled),
+        OperationWithArgs::Cal(_) => OperationWithArgs::Cal(labelled),
+        OperationWithArgs::Brh(condition, _) => OperationWithArgs::Brh(condition, labelled),
+        op => op,
+    }
+}
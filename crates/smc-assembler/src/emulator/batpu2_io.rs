@@ -0,0 +1,241 @@
+use std::fmt::Write as _;
+
+use crate::emulator::{
+    Io,
+    input::{InputPlayer, InputScript},
+};
+
+/// Width and height of the screen in pixels
+pub const SCREEN_SIZE: usize = 32;
+
+/// Number of characters on the character display
+pub const CHAR_COUNT: usize = 10;
+
+const PIXEL_X: u8 = 240;
+const PIXEL_Y: u8 = 241;
+const DRAW_PIXEL: u8 = 242;
+const CLEAR_PIXEL: u8 = 243;
+const LOAD_PIXEL: u8 = 244;
+/// Port showing the screen buffer, ending a frame
+pub const BUFFER_SCREEN: u8 = 245;
+const CLEAR_SCREEN_BUFFER: u8 = 246;
+const WRITE_CHAR: u8 = 247;
+const BUFFER_CHARS: u8 = 248;
+const CLEAR_CHARS_BUFFER: u8 = 249;
+const SHOW_NUMBER: u8 = 250;
+const CLEAR_NUMBER: u8 = 251;
+const SIGNED_MODE: u8 = 252;
+const UNSIGNED_MODE: u8 = 253;
+const RNG: u8 = 254;
+const CONTROLLER_INPUT: u8 = 255;
+
+/// Pixels of the screen, `y = 0` being the bottom row like on the BatPU-2
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// One bit per pixel, `x = 0` in the lowest bit
+    rows: [u32; SCREEN_SIZE],
+}
+
+impl Frame {
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.rows[y] >> x & 1 != 0
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, lit: bool) {
+        if lit {
+            self.rows[y] |= 1 << x;
+        } else {
+            self.rows[y] &= !(1 << x);
+        }
+    }
+
+    /// The frame drawn with half block characters, two rows per line
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for top in (1..SCREEN_SIZE).rev().step_by(2) {
+            for x in 0..SCREEN_SIZE {
+                text.push(match (self.get(x, top), self.get(x, top - 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// The frame as a grayscale PNG, every pixel `scale` image pixels wide
+    pub fn to_png(&self, scale: usize) -> Result<Vec<u8>, png::EncodingError> {
+        let scale = scale.max(1);
+        let size = SCREEN_SIZE * scale;
+        let mut data = Vec::with_capacity(size * size);
+        for row in (0..size).rev() {
+            for column in 0..size {
+                data.push(match self.get(column / scale, row / scale) {
+                    true => 0xff,
+                    false => 0x20,
+                });
+            }
+        }
+
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, size as u32, size as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+
+        Ok(png)
+    }
+}
+
+/// The devices behind the ports of the BatPU-2: the screen, character display, number display,
+/// random number generator and controller.
+///
+/// Drawing goes to a buffer shown by `buffer_screen`, and `load_pixel` reads the buffer.
+/// Characters are collected the same way until `buffer_chars`.
+#[derive(Debug, Default, Clone)]
+pub struct Batpu2Io {
+    /// Seed of the numbers read from `rng`, the same seed always gives the same numbers
+    pub seed: u64,
+    rng: u64,
+    /// Buttons read from `controller_input`
+    pub input: InputPlayer,
+    pub pixel_x: u8,
+    pub pixel_y: u8,
+    /// Pixels drawn since the last `buffer_screen`
+    pub buffer: Frame,
+    /// Pixels on the screen
+    pub screen: Frame,
+    /// Number of `buffer_screen` writes so far
+    pub frames: usize,
+    /// Characters written since the last `buffer_chars`, extra ones are dropped
+    pub chars_buffer: Vec<u8>,
+    /// Characters on the display, as codes like those of `"A"` literals
+    pub chars: Vec<u8>,
+    /// Value on the number display, if any
+    pub number: Option<u8>,
+    /// Whether the number display shows values as two's complement
+    pub signed: bool,
+}
+
+impl Batpu2Io {
+    pub fn new(seed: u64, input: InputScript) -> Self {
+        Batpu2Io {
+            seed,
+            rng: seed,
+            input: InputPlayer::new(input),
+            ..Default::default()
+        }
+    }
+
+    /// Next number of a SplitMix64 sequence
+    fn random(&mut self) -> u8 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (z ^ (z >> 31)) as u8
+    }
+
+    /// Text on the character display
+    pub fn chars_text(&self) -> String {
+        self.chars
+            .iter()
+            .map(|code| char_from_code(*code))
+            .collect()
+    }
+
+    /// Text on the number display, empty if it is cleared
+    pub fn number_text(&self) -> String {
+        match self.number {
+            Some(value) if self.signed => (value as i8).to_string(),
+            Some(value) => value.to_string(),
+            None => String::new(),
+        }
+    }
+
+    /// The screen with the character and number displays below it
+    pub fn to_text(&self) -> String {
+        let mut text = self.screen.to_text();
+        let _ = writeln!(
+            text,
+            "{:chars$}  {:>4}",
+            self.chars_text(),
+            self.number_text(),
+            chars = CHAR_COUNT
+        );
+        text
+    }
+}
+
+impl Io for Batpu2Io {
+    fn read(&mut self, port: u8) -> u8 {
+        match port {
+            LOAD_PIXEL => self
+                .buffer
+                .get(self.pixel_x as usize, self.pixel_y as usize) as u8,
+            RNG => self.random(),
+            CONTROLLER_INPUT => self.input.buttons,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, port: u8, value: u8) {
+        let (x, y) = (self.pixel_x as usize, self.pixel_y as usize);
+
+        match port {
+            PIXEL_X => self.pixel_x = value % SCREEN_SIZE as u8,
+            PIXEL_Y => self.pixel_y = value % SCREEN_SIZE as u8,
+            DRAW_PIXEL => self.buffer.set(x, y, true),
+            CLEAR_PIXEL => self.buffer.set(x, y, false),
+            BUFFER_SCREEN => {
+                self.screen = self.buffer.clone();
+                self.frames += 1;
+            }
+            CLEAR_SCREEN_BUFFER => self.buffer = Frame::default(),
+            WRITE_CHAR if self.chars_buffer.len() < CHAR_COUNT => self.chars_buffer.push(value),
+            BUFFER_CHARS => self.chars = self.chars_buffer.clone(),
+            CLEAR_CHARS_BUFFER => self.chars_buffer.clear(),
+            SHOW_NUMBER => self.number = Some(value),
+            CLEAR_NUMBER => self.number = None,
+            SIGNED_MODE => self.signed = true,
+            UNSIGNED_MODE => self.signed = false,
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.input.advance(cycles, self.frames);
+    }
+
+    fn reset(&mut self) {
+        let mut input = std::mem::take(&mut self.input);
+        input.restart();
+        *self = Batpu2Io {
+            seed: self.seed,
+            rng: self.seed,
+            input,
+            ..Default::default()
+        };
+    }
+
+    fn display(&self) -> Option<String> {
+        Some(self.to_text())
+    }
+}
+
+/// The character shown for a code, the inverse of `"A"` literals
+fn char_from_code(code: u8) -> char {
+    match code {
+        0 => ' ',
+        1..=26 => (b'A' + code - 1) as char,
+        27 => '.',
+        28 => '!',
+        29 => '?',
+        _ => '_',
+    }
+}
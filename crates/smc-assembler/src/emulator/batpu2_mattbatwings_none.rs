@@ -0,0 +1,123 @@
+use crate::{
+    emulator::{Emulator, EmulatorError, Io, Step},
+    lexer::token::{Condition, Register},
+    parser::operations::{Address, Immediate, Offset, OperationWithArgs},
+};
+
+/// First address of data memory mapped to a port
+pub const FIRST_PORT: u8 = 240;
+
+pub fn execute<I: Io>(
+    emulator: &mut Emulator<I>,
+    op: OperationWithArgs,
+    step: &mut Step,
+) -> Result<(), EmulatorError> {
+    use OperationWithArgs::*;
+
+    let address = step.address;
+    let value = |immediate: &Immediate| match immediate {
+        Immediate::Value(value) => *value as u8,
+        Immediate::Define(_) => 0,
+    };
+    let target = |to: &Address| match to {
+        Address::Value(value) => *value as usize,
+        _ => 0,
+    };
+
+    match &op {
+        Nop => {}
+        Hlt => emulator.halted = true,
+        Add3(a, b, c) => {
+            let result = emulator
+                .flags
+                .add(emulator.register(*a), emulator.register(*b), false);
+            write(emulator, *c, result);
+        }
+        Sub3(a, b, c) => {
+            let result = emulator
+                .flags
+                .sub(emulator.register(*a), emulator.register(*b));
+            write(emulator, *c, result);
+        }
+        Nor3(a, b, c) => {
+            let result = emulator
+                .flags
+                .logic(!(emulator.register(*a) | emulator.register(*b)));
+            write(emulator, *c, result);
+        }
+        And3(a, b, c) => {
+            let result = emulator
+                .flags
+                .logic(emulator.register(*a) & emulator.register(*b));
+            write(emulator, *c, result);
+        }
+        Xor3(a, b, c) => {
+            let result = emulator
+                .flags
+                .logic(emulator.register(*a) ^ emulator.register(*b));
+            write(emulator, *c, result);
+        }
+        Rsh2(a, c) => write(emulator, *c, emulator.register(*a) >> 1),
+        Ldi2(a, immediate) => write(emulator, *a, value(immediate)),
+        Adi2(a, immediate) => {
+            let result = emulator
+                .flags
+                .add(emulator.register(*a), value(immediate), false);
+            write(emulator, *a, result);
+        }
+        Jmp(to) => emulator.pc = target(to),
+        Brh(condition, to) => {
+            let taken = match condition {
+                Condition::Equal => emulator.flags.zero,
+                Condition::NotEqual => !emulator.flags.zero,
+                Condition::GreaterEqual => emulator.flags.carry,
+                Condition::Less => !emulator.flags.carry,
+                _ => return Err(EmulatorError::UnsupportedOperation(address, op.clone())),
+            };
+            if taken {
+                emulator.pc = target(to);
+            }
+        }
+        Cal(to) => {
+            emulator.push_call(address, emulator.pc)?;
+            emulator.pc = target(to);
+        }
+        Ret => emulator.pc = emulator.pop_call(address)?,
+        // `LOD A B offset` loads the address in A plus the offset into B
+        Lod(a, b, offset) => {
+            let from = emulator.register(*a).wrapping_add(offset_value(offset));
+            let value = if from >= FIRST_PORT {
+                emulator.read_port(from, step)
+            } else {
+                emulator.load(from, step)
+            };
+            write(emulator, *b, value);
+        }
+        Str(a, b, offset) => {
+            let to = emulator.register(*a).wrapping_add(offset_value(offset));
+            let value = emulator.register(*b);
+            if to >= FIRST_PORT {
+                emulator.write_port(to, value, step);
+            } else {
+                emulator.store(to, value, step);
+            }
+        }
+        _ => return Err(EmulatorError::UnsupportedOperation(address, op.clone())),
+    }
+
+    Ok(())
+}
+
+/// Write a register, writes to `r0` are dropped
+fn write<I: Io>(emulator: &mut Emulator<I>, register: Register, value: u8) {
+    if register != Register::R0 {
+        emulator.set_register(register, value);
+    }
+}
+
+fn offset_value(offset: &Option<Offset>) -> u8 {
+    match offset {
+        Some(Offset::Value(value)) => *value as u8,
+        _ => 0,
+    }
+}
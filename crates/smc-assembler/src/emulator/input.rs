@@ -0,0 +1,154 @@
+use std::{fs, path::Path, str::FromStr};
+
+use thiserror::Error;
+
+use crate::debugger::parse_number;
+
+/// Buttons of the BatPU-2 controller, by their bit in `controller_input`
+pub const BUTTONS: [(&str, u8); 8] = [
+    ("left", 1 << 0),
+    ("down", 1 << 1),
+    ("right", 1 << 2),
+    ("up", 1 << 3),
+    ("b", 1 << 4),
+    ("a", 1 << 5),
+    ("select", 1 << 6),
+    ("start", 1 << 7),
+];
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum InputScriptError {
+    #[error("Line {0}: expected `cycle N` or `frame N` followed by buttons")]
+    ExpectedTime(usize),
+
+    #[error("Line {0}: unknown button `{1}`")]
+    UnknownButton(usize, String),
+
+    #[error("Failed to read input script: {0}")]
+    ReadFailed(String),
+}
+
+/// When the buttons of an [`InputEvent`] start being held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputTime {
+    /// Once this many instructions ran
+    Cycle(u64),
+    /// Once this many frames were shown with `buffer_screen`
+    Frame(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub at: InputTime,
+    /// Bits of every held button, nothing else is held until the next event
+    pub buttons: u8,
+}
+
+/// Controller input to replay, read from lines such as
+///
+/// ```text
+/// # Hold right from the third frame, then let go
+/// frame 3 right
+/// frame 4
+/// cycle 50000 up a
+/// ```
+///
+/// Buttons are `left`, `down`, `right`, `up`, `b`, `a`, `select` and `start`, or a number of
+/// bits. Events take effect in the order they are listed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputScript {
+    pub events: Vec<InputEvent>,
+}
+
+impl InputScript {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, InputScriptError> {
+        fs::read_to_string(path)
+            .map_err(|err| InputScriptError::ReadFailed(err.to_string()))?
+            .parse()
+    }
+}
+
+impl FromStr for InputScript {
+    type Err = InputScriptError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut events = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+
+            let Some(unit) = words.next() else {
+                continue;
+            };
+            let time = words
+                .next()
+                .and_then(|time| time.replace('_', "").parse::<u64>().ok())
+                .ok_or(InputScriptError::ExpectedTime(line_number))?;
+            let at = match unit.to_ascii_lowercase().as_str() {
+                "cycle" => InputTime::Cycle(time),
+                "frame" => InputTime::Frame(time as usize),
+                _ => return Err(InputScriptError::ExpectedTime(line_number)),
+            };
+
+            let mut buttons = 0;
+            for word in words {
+                let name = word.to_ascii_lowercase();
+                buttons |= BUTTONS
+                    .iter()
+                    .find(|(button, _)| *button == name)
+                    .map(|(_, bit)| *bit)
+                    .or_else(|| parse_number(&name).ok()?.try_into().ok())
+                    .ok_or_else(|| {
+                        InputScriptError::UnknownButton(line_number, word.to_string())
+                    })?;
+            }
+
+            events.push(InputEvent { at, buttons });
+        }
+
+        Ok(InputScript { events })
+    }
+}
+
+/// Plays an [`InputScript`] back as time passes
+#[derive(Debug, Clone, Default)]
+pub struct InputPlayer {
+    script: InputScript,
+    next: usize,
+    /// Bits of the buttons held now
+    pub buttons: u8,
+}
+
+impl InputPlayer {
+    pub fn new(script: InputScript) -> Self {
+        InputPlayer {
+            script,
+            next: 0,
+            buttons: 0,
+        }
+    }
+
+    /// Apply every event due after `cycles` instructions and `frames` frames
+    pub fn advance(&mut self, cycles: u64, frames: usize) {
+        while let Some(event) = self.script.events.get(self.next) {
+            let due = match event.at {
+                InputTime::Cycle(cycle) => cycles >= cycle,
+                InputTime::Frame(frame) => frames >= frame,
+            };
+            if !due {
+                break;
+            }
+
+            self.buttons = event.buttons;
+            self.next += 1;
+        }
+    }
+
+    /// Start the script over
+    pub fn restart(&mut self) {
+        self.next = 0;
+        self.buttons = 0;
+    }
+}
@@ -0,0 +1,307 @@
+use thiserror::Error;
+
+use crate::{
+    assembler::backends::Backend, lexer::token::Register, parser::operations::OperationWithArgs,
+};
+
+pub mod batpu2_io;
+pub mod batpu2_mattbatwings_none;
+pub mod input;
+pub mod profiler;
+pub mod tau_analyzers_none;
+pub mod trace;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum EmulatorError {
+    #[error("EmulatorError: The program has halted")]
+    Halted,
+
+    #[error("EmulatorError: Invalid instruction at address {0}")]
+    InvalidInstruction(usize),
+
+    #[error("EmulatorError: Call stack overflow at address {0}")]
+    StackOverflow(usize),
+
+    #[error("EmulatorError: Return with an empty call stack at address {0}")]
+    StackUnderflow(usize),
+
+    #[error("EmulatorError: Unsupported operation {1} at address {0}")]
+    UnsupportedOperation(usize, OperationWithArgs),
+}
+
+/// Flags set by the ALU, not every target has all of them
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Flags {
+    pub zero: bool,
+    pub carry: bool,
+    pub negative: bool,
+}
+
+/// Devices behind the ports of the target
+pub trait Io {
+    /// Value read from `port`
+    fn read(&mut self, port: u8) -> u8;
+
+    fn write(&mut self, port: u8, value: u8);
+
+    /// Called before every instruction with the number of instructions run so far
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// Put the devices back in the state they start in
+    fn reset(&mut self) {}
+
+    /// The output devices drawn as text, if there are any
+    fn display(&self) -> Option<String> {
+        None
+    }
+}
+
+/// No devices, reads give zero and writes are dropped
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullIo;
+
+impl Io for NullIo {
+    fn read(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    fn write(&mut self, _port: u8, _value: u8) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A read or write of data memory or a port by an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub address: u8,
+    pub value: u8,
+    /// Whether the access went to a port rather than memory
+    pub port: bool,
+}
+
+/// What executing a single instruction did
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// Address the instruction was executed from
+    pub address: usize,
+    pub op: OperationWithArgs,
+    pub accesses: Vec<Access>,
+}
+
+/// Runs machine code of a target, instruction by instruction
+#[derive(Debug, Clone)]
+pub struct Emulator<I = NullIo> {
+    target: Backend,
+    /// Decoded instruction and its size in bytes, for every byte of instruction memory
+    decoded: Vec<(Option<OperationWithArgs>, usize)>,
+    pub registers: Vec<u8>,
+    pub flags: Flags,
+    /// Data memory, every bank after the other
+    pub memory: Vec<u8>,
+    /// Selected bank of data memory
+    pub bank: usize,
+    /// Address of the next instruction, in the units of jumps and labels
+    pub pc: usize,
+    /// Return addresses, innermost last
+    pub call_stack: Vec<usize>,
+    /// Number of instructions executed
+    pub cycles: u64,
+    halted: bool,
+    pub io: I,
+}
+
+impl<I: Io + Default> Emulator<I> {
+    pub fn new(target: Backend, program: &[u8]) -> Self {
+        Self::with_io(target, program, I::default())
+    }
+}
+
+impl<I: Io> Emulator<I> {
+    /// Load `program` at the start of instruction memory, the rest of it being zero
+    pub fn with_io(target: Backend, program: &[u8], io: I) -> Self {
+        let mut bytes = program.to_vec();
+        bytes.resize(
+            (target.capacity() * target.word_size()).max(program.len()),
+            0,
+        );
+        let decoded = (0..bytes.len())
+            .map(|offset| target.disassemble_operation(&bytes[offset..]))
+            .collect();
+
+        Emulator {
+            registers: vec![0; target.register_count() as usize],
+            memory: vec![0; target.memory_size()],
+            target,
+            decoded,
+            flags: Flags::default(),
+            bank: 0,
+            pc: 0,
+            call_stack: Vec::new(),
+            cycles: 0,
+            halted: false,
+            io,
+        }
+    }
+
+    pub fn target(&self) -> &Backend {
+        &self.target
+    }
+
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Clear registers, flags, memory and the call stack, reset the devices and start over from
+    /// address 0.
+    pub fn reset(&mut self) {
+        self.registers.fill(0);
+        self.memory.fill(0);
+        self.flags = Flags::default();
+        self.bank = 0;
+        self.pc = 0;
+        self.call_stack.clear();
+        self.cycles = 0;
+        self.halted = false;
+        self.io.reset();
+    }
+
+    /// The instruction at `address` and its size in bytes
+    pub fn instruction_at(&self, address: usize) -> (Option<&OperationWithArgs>, usize) {
+        match self.decoded.get(address * self.target.word_size()) {
+            Some((op, size)) => (op.as_ref(), *size),
+            None => (None, 0),
+        }
+    }
+
+    /// The instruction executed by the next step
+    pub fn current(&self) -> Option<&OperationWithArgs> {
+        self.instruction_at(self.pc).0
+    }
+
+    /// Execute the instruction at the program counter
+    pub fn step(&mut self) -> Result<Step, EmulatorError> {
+        if self.halted {
+            return Err(EmulatorError::Halted);
+        }
+
+        let address = self.pc;
+        let (op, size) = self.instruction_at(address);
+        let op = op
+            .cloned()
+            .ok_or(EmulatorError::InvalidInstruction(address))?;
+        let next = (address + size / self.target.word_size()) % self.target.capacity();
+
+        let mut step = Step {
+            address,
+            op: op.clone(),
+            accesses: Vec::new(),
+        };
+        self.pc = next;
+        self.io.tick(self.cycles);
+        match self.target {
+            Backend::BatPU2 => batpu2_mattbatwings_none::execute(self, op, &mut step)?,
+            Backend::TauAnalyzersNone => tau_analyzers_none::execute(self, op, &mut step)?,
+        }
+        self.cycles += 1;
+
+        Ok(step)
+    }
+
+    fn register(&self, register: Register) -> u8 {
+        self.registers[register.0 as usize]
+    }
+
+    fn set_register(&mut self, register: Register, value: u8) {
+        self.registers[register.0 as usize] = value;
+    }
+
+    fn push_call(&mut self, address: usize, return_address: usize) -> Result<(), EmulatorError> {
+        if self.call_stack.len() >= self.target.call_stack_depth() {
+            return Err(EmulatorError::StackOverflow(address));
+        }
+        self.call_stack.push(return_address);
+        Ok(())
+    }
+
+    fn pop_call(&mut self, address: usize) -> Result<usize, EmulatorError> {
+        self.call_stack
+            .pop()
+            .ok_or(EmulatorError::StackUnderflow(address))
+    }
+
+    fn load(&mut self, address: u8, step: &mut Step) -> u8 {
+        let value = self.memory[self.bank * 256 + address as usize];
+        step.accesses.push(Access {
+            kind: AccessKind::Read,
+            address,
+            value,
+            port: false,
+        });
+        value
+    }
+
+    fn store(&mut self, address: u8, value: u8, step: &mut Step) {
+        self.memory[self.bank * 256 + address as usize] = value;
+        step.accesses.push(Access {
+            kind: AccessKind::Write,
+            address,
+            value,
+            port: false,
+        });
+    }
+
+    fn read_port(&mut self, port: u8, step: &mut Step) -> u8 {
+        let value = self.io.read(port);
+        step.accesses.push(Access {
+            kind: AccessKind::Read,
+            address: port,
+            value,
+            port: true,
+        });
+        value
+    }
+
+    fn write_port(&mut self, port: u8, value: u8, step: &mut Step) {
+        self.io.write(port, value);
+        step.accesses.push(Access {
+            kind: AccessKind::Write,
+            address: port,
+            value,
+            port: true,
+        });
+    }
+}
+
+impl Flags {
+    /// Add with carry in, setting the zero, carry and negative flags
+    fn add(&mut self, a: u8, b: u8, carry: bool) -> u8 {
+        let sum = a as u16 + b as u16 + carry as u16;
+        let result = sum as u8;
+        *self = Flags {
+            zero: result == 0,
+            carry: sum > 0xFF,
+            negative: result & 0x80 != 0,
+        };
+        result
+    }
+
+    /// Subtract as adding the complement, so carry is set when there is no borrow
+    fn sub(&mut self, a: u8, b: u8) -> u8 {
+        self.add(a, !b, true)
+    }
+
+    /// Set the flags of the result of a logic operation, which clears carry
+    fn logic(&mut self, result: u8) -> u8 {
+        *self = Flags {
+            zero: result == 0,
+            carry: false,
+            negative: result & 0x80 != 0,
+        };
+        result
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write as _,
+};
+
+use crate::{
+    CompiledProgram,
+    assembler::backends::Backend,
+    emulator::{AccessKind, Emulator, NullIo, Step, batpu2_io::BUFFER_SCREEN},
+    parser::operations::{Address, OperationWithArgs, SkipFlag},
+};
+
+/// Time spent in the code from a label up to the next one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelProfile {
+    pub name: String,
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+/// Time spent in a subroutine, including the subroutines it called
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubroutineProfile {
+    pub name: String,
+    pub calls: u64,
+    pub cycles: u64,
+}
+
+/// How often an instruction ran, by mnemonic of the instruction encoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MixEntry {
+    pub mnemonic: String,
+    pub count: u64,
+    pub cycles: u64,
+}
+
+/// Most cycles a subroutine can take, `None` if that depends on loops, recursion or code
+/// outside the program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Estimate {
+    pub name: String,
+    pub address: usize,
+    pub cycles: Option<u64>,
+}
+
+/// Where the cycles of a run went, hottest first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub instructions: u64,
+    pub cycles: u64,
+    pub labels: Vec<LabelProfile>,
+    pub subroutines: Vec<SubroutineProfile>,
+    pub mix: Vec<MixEntry>,
+    /// Cycles of every frame, counted from the previous `buffer_screen` write or the start
+    pub frames: Vec<u64>,
+}
+
+/// Records the cycles of every step run on an [`Emulator`], costing instructions with
+/// [`Backend::cycle_cost`]
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    target: Backend,
+    instructions: u64,
+    cycles: u64,
+    /// Count and cycles by address
+    addresses: HashMap<usize, (u64, u64)>,
+    /// Count and cycles by mnemonic
+    mix: HashMap<String, (u64, u64)>,
+    /// Calls and cycles by subroutine address
+    subroutines: HashMap<usize, (u64, u64)>,
+    /// Addresses of the subroutines running, innermost last
+    stack: Vec<usize>,
+    frame_start: u64,
+    frames: Vec<u64>,
+}
+
+impl Profiler {
+    pub fn new(target: Backend) -> Self {
+        Profiler {
+            target,
+            instructions: 0,
+            cycles: 0,
+            addresses: HashMap::new(),
+            mix: HashMap::new(),
+            subroutines: HashMap::new(),
+            stack: Vec::new(),
+            frame_start: 0,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Account for the step `emulator` just executed
+    pub fn record<I>(&mut self, step: &Step, emulator: &Emulator<I>) {
+        let cost = self.target.cycle_cost(&step.op);
+        self.instructions += 1;
+        self.cycles += cost;
+
+        let add = |(count, cycles): &mut (u64, u64)| {
+            *count += 1;
+            *cycles += cost;
+        };
+        add(self.addresses.entry(step.address).or_default());
+        let mnemonic = step.op.to_string();
+        let mnemonic = mnemonic.split_whitespace().next().unwrap_or_default();
+        add(self.mix.entry(mnemonic.to_string()).or_default());
+
+        // A recursive subroutine only counts once
+        for (i, address) in self.stack.iter().enumerate() {
+            if !self.stack[..i].contains(address) {
+                self.subroutines.entry(*address).or_default().1 += cost;
+            }
+        }
+
+        match emulator.call_stack.len().cmp(&self.stack.len()) {
+            Ordering::Greater => {
+                self.stack.push(emulator.pc);
+                self.subroutines.entry(emulator.pc).or_default().0 += 1;
+            }
+            Ordering::Less => {
+                self.stack.pop();
+            }
+            Ordering::Equal => {}
+        }
+
+        let shown = step.accesses.iter().any(|access| {
+            access.port && access.kind == AccessKind::Write && access.address == BUFFER_SCREEN
+        });
+        if self.target == Backend::BatPU2 && shown {
+            self.frames.push(self.cycles - self.frame_start);
+            self.frame_start = self.cycles;
+        }
+    }
+
+    /// The recorded cycles, attributed to the labels of `program`
+    pub fn profile(&self, program: &CompiledProgram) -> Profile {
+        let names = label_names(program);
+        let label_of = |address: usize| match names.range(..=address).next_back() {
+            Some((_, name)) => format!(".{}", name),
+            None => "(start)".to_string(),
+        };
+
+        let mut labels: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+        for (address, (count, cycles)) in &self.addresses {
+            let label = labels.entry(label_of(*address)).or_default();
+            label.0 += count;
+            label.1 += cycles;
+        }
+        let mut labels: Vec<LabelProfile> = labels
+            .into_iter()
+            .map(|(name, (instructions, cycles))| LabelProfile {
+                name,
+                instructions,
+                cycles,
+            })
+            .collect();
+        labels.sort_by(|a, b| b.cycles.cmp(&a.cycles).then_with(|| a.name.cmp(&b.name)));
+
+        let mut subroutines: Vec<SubroutineProfile> = self
+            .subroutines
+            .iter()
+            .map(|(address, (calls, cycles))| SubroutineProfile {
+                name: name_at(&names, *address),
+                calls: *calls,
+                cycles: *cycles,
+            })
+            .collect();
+        subroutines.sort_by(|a, b| b.cycles.cmp(&a.cycles).then_with(|| a.name.cmp(&b.name)));
+
+        let mut mix: Vec<MixEntry> = self
+            .mix
+            .iter()
+            .map(|(mnemonic, (count, cycles))| MixEntry {
+                mnemonic: mnemonic.clone(),
+                count: *count,
+                cycles: *cycles,
+            })
+            .collect();
+        mix.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.mnemonic.cmp(&b.mnemonic))
+        });
+
+        Profile {
+            instructions: self.instructions,
+            cycles: self.cycles,
+            labels,
+            subroutines,
+            mix,
+            frames: self.frames.clone(),
+        }
+    }
+}
+
+impl Profile {
+    /// A report of the `top` hottest labels and subroutines, the instruction mix and the frames
+    pub fn to_text(&self, top: usize) -> String {
+        let percent = |cycles: u64| cycles as f64 * 100.0 / self.cycles.max(1) as f64;
+        let mut text = format!(
+            "{} instructions, {} cycles\n",
+            self.instructions, self.cycles
+        );
+
+        let _ = writeln!(
+            text,
+            "\nHottest labels\n  {:>10} {:>6}  {:>12}  label",
+            "cycles", "%", "instructions"
+        );
+        for label in self.labels.iter().take(top) {
+            let _ = writeln!(
+                text,
+                "  {:>10} {:>5.1}%  {:>12}  {}",
+                label.cycles,
+                percent(label.cycles),
+                label.instructions,
+                label.name
+            );
+        }
+
+        if !self.subroutines.is_empty() {
+            let _ = writeln!(
+                text,
+                "\nHottest subroutines, with what they call\n  {:>10} {:>6}  {:>8}  subroutine",
+                "cycles", "%", "calls"
+            );
+            for subroutine in self.subroutines.iter().take(top) {
+                let _ = writeln!(
+                    text,
+                    "  {:>10} {:>5.1}%  {:>8}  {}",
+                    subroutine.cycles,
+                    percent(subroutine.cycles),
+                    subroutine.calls,
+                    subroutine.name
+                );
+            }
+        }
+
+        let _ = writeln!(
+            text,
+            "\nInstruction mix\n  {:>10} {:>6}  {:>10}  instruction",
+            "count", "%", "cycles"
+        );
+        for entry in &self.mix {
+            let _ = writeln!(
+                text,
+                "  {:>10} {:>5.1}%  {:>10}  {}",
+                entry.count,
+                entry.count as f64 * 100.0 / self.instructions.max(1) as f64,
+                entry.cycles,
+                entry.mnemonic
+            );
+        }
+
+        if let (Some(min), Some(max)) = (self.frames.iter().min(), self.frames.iter().max()) {
+            let mean = self.frames.iter().sum::<u64>() / self.frames.len() as u64;
+            let _ = writeln!(
+                text,
+                "\n{} frames, cycles per frame: {} min, {} mean, {} max",
+                self.frames.len(),
+                min,
+                mean,
+                max
+            );
+        }
+
+        text
+    }
+}
+
+/// The most cycles each called subroutine can take, in address order.
+///
+/// Only subroutines without loops have a bound, the cycles of the subroutines they call
+/// included.
+pub fn worst_case_cycles(program: &CompiledProgram) -> Vec<Estimate> {
+    let mut analysis = Analysis {
+        emulator: Emulator::with_io(program.target.clone(), &program.bytes, NullIo),
+        instructions: program
+            .instructions
+            .iter()
+            .map(|instruction| instruction.address)
+            .collect(),
+        longest: HashMap::new(),
+        visiting: HashSet::new(),
+        subroutines: HashMap::new(),
+    };
+
+    let mut called: Vec<usize> = program
+        .instructions
+        .iter()
+        .filter_map(
+            |instruction| match analysis.emulator.instruction_at(instruction.address).0 {
+                Some(OperationWithArgs::Cal(Address::Value(to))) => Some(*to as usize),
+                _ => None,
+            },
+        )
+        .collect();
+    called.sort();
+    called.dedup();
+
+    let names = label_names(program);
+    called
+        .into_iter()
+        .map(|address| Estimate {
+            name: name_at(&names, address),
+            address,
+            cycles: analysis.subroutine(address),
+        })
+        .collect()
+}
+
+/// Longest paths through the instructions of a program
+struct Analysis {
+    emulator: Emulator<NullIo>,
+    instructions: HashSet<usize>,
+    /// Most cycles from an address to the return or halt ending the path
+    longest: HashMap<usize, Option<u64>>,
+    /// Addresses on the path being followed, reaching one again means a loop
+    visiting: HashSet<usize>,
+    /// Bounds of subroutines, `None` while one is being worked out
+    subroutines: HashMap<usize, Option<u64>>,
+}
+
+impl Analysis {
+    fn subroutine(&mut self, address: usize) -> Option<u64> {
+        if let Some(cycles) = self.subroutines.get(&address) {
+            // A subroutine still being worked out calls itself
+            return *cycles;
+        }
+
+        self.subroutines.insert(address, None);
+        let cycles = self.longest(address);
+        self.subroutines.insert(address, cycles);
+        cycles
+    }
+
+    fn longest(&mut self, address: usize) -> Option<u64> {
+        if let Some(cycles) = self.longest.get(&address) {
+            return *cycles;
+        }
+        let (op, size) = self.emulator.instruction_at(address);
+        let op = op.cloned()?;
+        if !self.instructions.contains(&address) || !self.visiting.insert(address) {
+            return None;
+        }
+
+        let target = self.emulator.target().clone();
+        let next = (address + size / target.word_size()) % target.capacity();
+        let after_next = || {
+            let (_, size) = self.emulator.instruction_at(next);
+            (next + size / target.word_size()) % target.capacity()
+        };
+        let to = |address: &Address| match address {
+            Address::Value(value) => Some(*value as usize),
+            _ => None,
+        };
+
+        let rest = match &op {
+            OperationWithArgs::Hlt | OperationWithArgs::Ret => Some(0),
+            OperationWithArgs::Jmp(address) => to(address).and_then(|to| self.longest(to)),
+            OperationWithArgs::Brh(_, address) => match to(address) {
+                Some(to) => self
+                    .longest(to)
+                    .zip(self.longest(next))
+                    .map(|(a, b)| a.max(b)),
+                None => None,
+            },
+            OperationWithArgs::Cal(address) => match to(address) {
+                Some(to) => self
+                    .subroutine(to)
+                    .zip(self.longest(next))
+                    .map(|(a, b)| a + b),
+                None => None,
+            },
+            OperationWithArgs::Skp(SkipFlag::Never) => self.longest(next),
+            OperationWithArgs::Skp(SkipFlag::Always) => {
+                let after_next = after_next();
+                self.longest(after_next)
+            }
+            OperationWithArgs::Skp(_) => {
+                let after_next = after_next();
+                self.longest(next)
+                    .zip(self.longest(after_next))
+                    .map(|(a, b)| a.max(b))
+            }
+            _ => self.longest(next),
+        };
+
+        let cycles = rest.map(|rest| rest + target.cycle_cost(&op));
+        self.visiting.remove(&address);
+        self.longest.insert(address, cycles);
+        cycles
+    }
+}
+
+/// The first label of every labelled address
+fn label_names(program: &CompiledProgram) -> BTreeMap<usize, String> {
+    let mut labels: Vec<(&String, &usize)> = program.labels.iter().collect();
+    labels.sort();
+
+    let mut names = BTreeMap::new();
+    for (name, address) in labels {
+        names.entry(*address).or_insert_with(|| name.clone());
+    }
+    names
+}
+
+fn name_at(names: &BTreeMap<usize, String>, address: usize) -> String {
+    match names.get(&address) {
+        Some(name) => format!(".{}", name),
+        None => format!("{:04}", address),
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{
+    emulator::{Emulator, EmulatorError, Flags, Io, Step},
+    parser::operations::{Address, Immediate, OperationWithArgs, SkipFlag},
+};
+
+pub fn execute<I: Io>(
+    emulator: &mut Emulator<I>,
+    op: OperationWithArgs,
+    step: &mut Step,
+) -> Result<(), EmulatorError> {
+    use OperationWithArgs::*;
+
+    let address = step.address;
+    let value = |immediate: &Immediate| match immediate {
+        Immediate::Value(value) => *value as u8,
+        Immediate::Define(_) => 0,
+    };
+    let target = |to: &Address| match to {
+        Address::Value(value) => *value as usize,
+        _ => 0,
+    };
+
+    // Every operation works on its first register, the second being the source
+    match &op {
+        Add2(d, s) | Sub2(d, s) | Xor2(d, s) | And2(d, s) | Or2(d, s) | Adc2(d, s) => {
+            let (a, b) = (emulator.register(*d), emulator.register(*s));
+            let flags = &mut emulator.flags;
+            let result = match op {
+                Add2(..) => flags.add(a, b, false),
+                Sub2(..) => flags.sub(a, b),
+                Xor2(..) => flags.logic(a ^ b),
+                And2(..) => flags.logic(a & b),
+                Or2(..) => flags.logic(a | b),
+                _ => {
+                    let carry = flags.carry;
+                    flags.add(a, b, carry)
+                }
+            };
+            emulator.set_register(*d, result);
+        }
+        Cmp2(d, s) => {
+            emulator
+                .flags
+                .sub(emulator.register(*d), emulator.register(*s));
+        }
+        // Copies leave the flags alone
+        Cpy2(d, s) => emulator.set_register(*d, emulator.register(*s)),
+        Mld2(d, s) => {
+            let value = emulator.load(emulator.register(*s), step);
+            emulator.set_register(*d, value);
+        }
+        Mst2(d, s) => emulator.store(emulator.register(*s), emulator.register(*d), step),
+        Pld2(d, s) => {
+            let value = emulator.read_port(emulator.register(*s), step);
+            emulator.set_register(*d, value);
+        }
+        Pst2(d, s) => emulator.write_port(emulator.register(*s), emulator.register(*d), step),
+        Rsh1(d) => {
+            let a = emulator.register(*d);
+            let result = emulator.flags.logic(a >> 1);
+            emulator.flags.carry = a & 1 != 0;
+            emulator.set_register(*d, result);
+        }
+        Inv1(d) => {
+            let result = emulator.flags.logic(!emulator.register(*d));
+            emulator.set_register(*d, result);
+        }
+        Inc1(d) => {
+            let result = emulator.flags.add(emulator.register(*d), 1, false);
+            emulator.set_register(*d, result);
+        }
+        Dec1(d) => {
+            let result = emulator.flags.sub(emulator.register(*d), 1);
+            emulator.set_register(*d, result);
+        }
+        Ldi2(d, immediate) => emulator.set_register(*d, value(immediate)),
+        Adi2(d, immediate) => {
+            let result = emulator
+                .flags
+                .add(emulator.register(*d), value(immediate), false);
+            emulator.set_register(*d, result);
+        }
+        Cpi2(d, immediate) => {
+            emulator.flags.sub(emulator.register(*d), value(immediate));
+        }
+        Ani2(d, immediate) => {
+            let result = emulator
+                .flags
+                .logic(emulator.register(*d) & value(immediate));
+            emulator.set_register(*d, result);
+        }
+        Jmp(to) => emulator.pc = target(to),
+        Cal(to) => {
+            emulator.push_call(address, emulator.pc)?;
+            emulator.pc = target(to);
+        }
+        Ret => emulator.pc = emulator.pop_call(address)?,
+        Hlt => emulator.halted = true,
+        Bkl => emulator.bank = 0,
+        Bkr => emulator.bank = 1,
+        Skp(flag) => {
+            if skips(flag, &emulator.flags) {
+                let (_, size) = emulator.instruction_at(emulator.pc);
+                emulator.pc = (emulator.pc + size.max(1)) % emulator.target.capacity();
+            }
+        }
+        _ => return Err(EmulatorError::UnsupportedOperation(address, op.clone())),
+    }
+
+    Ok(())
+}
+
+/// Whether `SKP` skips the next instruction
+fn skips(flag: &SkipFlag, flags: &Flags) -> bool {
+    match flag {
+        SkipFlag::Never => false,
+        SkipFlag::IfZero => flags.zero,
+        SkipFlag::IfNotZero => !flags.zero,
+        SkipFlag::IfNegative => flags.negative,
+        SkipFlag::IfNotNegative => !flags.negative,
+        SkipFlag::Always => true,
+    }
+}
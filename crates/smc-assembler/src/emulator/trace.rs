@@ -0,0 +1,278 @@
+use std::{
+    fmt::{self, Write as _},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::emulator::{AccessKind, Emulator, Step};
+
+#[derive(Error, Debug)]
+pub enum TraceError {
+    #[error("Line {0}: {1}")]
+    InvalidLine(usize, String),
+
+    #[error("Failed to read {0}: {1}")]
+    ReadFailed(PathBuf, std::io::Error),
+}
+
+/// How a trace file is written, picked by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One line per step such as `0003 STR r2 r1 ; [12]=5 >245=0`
+    Text,
+    /// One JSON object per step, for `.json` and `.jsonl` files
+    Json,
+}
+
+impl TraceFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json" | "jsonl") => TraceFormat::Json,
+            _ => TraceFormat::Text,
+        }
+    }
+}
+
+/// What a single instruction did, as `(address, value)` pairs.
+///
+/// Written as text, the changes follow a `;`: `rN=V` for registers, `[A]=V` for data memory,
+/// `<P=V` for port reads and `>P=V` for port writes. Numbers are decimal, and lines starting with
+/// `#` are comments.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceStep {
+    pub pc: usize,
+    /// The instruction as disassembled, may be left empty by other tools
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub instruction: String,
+    /// Registers whose value changed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub registers: Vec<(u8, u8)>,
+    /// Writes to data memory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub memory: Vec<(u8, u8)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub port_reads: Vec<(u8, u8)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub port_writes: Vec<(u8, u8)>,
+}
+
+impl TraceStep {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("trace steps always serialize")
+    }
+
+    /// The ways `other` does something else, empty if they match.
+    ///
+    /// Instructions are compared ignoring case and spacing, and only when both traces have them.
+    pub fn differences(&self, other: &TraceStep) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.pc != other.pc {
+            differences.push(format!("pc {} != {}", self.pc, other.pc));
+        }
+
+        let normalize = |instruction: &str| {
+            instruction
+                .split_whitespace()
+                .map(str::to_ascii_lowercase)
+                .collect::<Vec<_>>()
+        };
+        let (a, b) = (normalize(&self.instruction), normalize(&other.instruction));
+        if !a.is_empty() && !b.is_empty() && a != b {
+            differences.push(format!(
+                "instruction `{}` != `{}`",
+                self.instruction, other.instruction
+            ));
+        }
+
+        let changes = [
+            ("registers", &self.registers, &other.registers),
+            ("memory writes", &self.memory, &other.memory),
+            ("port reads", &self.port_reads, &other.port_reads),
+            ("port writes", &self.port_writes, &other.port_writes),
+        ];
+        for (name, a, b) in changes {
+            let (mut a, mut b) = (a.clone(), b.clone());
+            a.sort();
+            b.sort();
+            if a != b {
+                differences.push(format!("{} differ", name));
+            }
+        }
+
+        differences
+    }
+}
+
+impl fmt::Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}", self.pc)?;
+        if !self.instruction.is_empty() {
+            write!(f, " {}", self.instruction)?;
+        }
+
+        let mut changes = String::new();
+        for (register, value) in &self.registers {
+            let _ = write!(changes, " r{}={}", register, value);
+        }
+        for (address, value) in &self.memory {
+            let _ = write!(changes, " [{}]={}", address, value);
+        }
+        for (port, value) in &self.port_reads {
+            let _ = write!(changes, " <{}={}", port, value);
+        }
+        for (port, value) in &self.port_writes {
+            let _ = write!(changes, " >{}={}", port, value);
+        }
+        if !changes.is_empty() {
+            write!(f, " ;{}", changes)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for TraceStep {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (step, changes) = line.split_once(';').unwrap_or((line, ""));
+        let step = step.trim();
+        let (pc, instruction) = step.split_once(char::is_whitespace).unwrap_or((step, ""));
+
+        let mut trace = TraceStep {
+            pc: pc
+                .parse()
+                .map_err(|_| format!("Expected an address, found `{}`", pc))?,
+            instruction: instruction.trim().to_string(),
+            ..Default::default()
+        };
+
+        for change in changes.split_whitespace() {
+            let invalid = || format!("Invalid change `{}`", change);
+            let (location, value) = change.split_once('=').ok_or_else(invalid)?;
+            let value = value.parse().map_err(|_| invalid())?;
+            let number = |text: &str| text.parse::<u8>().map_err(|_| invalid());
+
+            if let Some(register) = location.strip_prefix(['r', 'R']) {
+                trace.registers.push((number(register)?, value));
+            } else if let Some(address) = location
+                .strip_prefix('[')
+                .and_then(|location| location.strip_suffix(']'))
+            {
+                trace.memory.push((number(address)?, value));
+            } else if let Some(port) = location.strip_prefix('<') {
+                trace.port_reads.push((number(port)?, value));
+            } else if let Some(port) = location.strip_prefix('>') {
+                trace.port_writes.push((number(port)?, value));
+            } else {
+                return Err(invalid());
+            }
+        }
+
+        Ok(trace)
+    }
+}
+
+/// Turns the steps of an [`Emulator`] into [`TraceStep`]s, registers starting at zero
+#[derive(Debug, Default, Clone)]
+pub struct Tracer {
+    /// Registers after the previous step
+    registers: Vec<u8>,
+}
+
+impl Tracer {
+    /// The trace of the step `emulator` just executed
+    pub fn record<I>(&mut self, step: &Step, emulator: &Emulator<I>) -> TraceStep {
+        self.registers.resize(emulator.registers.len(), 0);
+
+        let mut trace = TraceStep {
+            pc: step.address,
+            instruction: step.op.to_string(),
+            ..Default::default()
+        };
+
+        for (register, (before, after)) in
+            self.registers.iter().zip(&emulator.registers).enumerate()
+        {
+            if before != after {
+                trace.registers.push((register as u8, *after));
+            }
+        }
+        self.registers.clone_from(&emulator.registers);
+
+        for access in &step.accesses {
+            let change = (access.address, access.value);
+            match (access.kind, access.port) {
+                (AccessKind::Write, false) => trace.memory.push(change),
+                (AccessKind::Read, true) => trace.port_reads.push(change),
+                (AccessKind::Write, true) => trace.port_writes.push(change),
+                (AccessKind::Read, false) => {}
+            }
+        }
+
+        trace
+    }
+}
+
+/// Where two traces first do something different
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Index of the step, from 0
+    pub index: usize,
+    /// The steps there, `None` for a trace that ended before
+    pub expected: Option<TraceStep>,
+    pub actual: Option<TraceStep>,
+    pub differences: Vec<String>,
+}
+
+/// The first step where `actual` does something `expected` does not
+pub fn first_divergence(expected: &[TraceStep], actual: &[TraceStep]) -> Option<Divergence> {
+    for index in 0..expected.len().max(actual.len()) {
+        let (a, b) = (expected.get(index), actual.get(index));
+        let differences = match (a, b) {
+            (Some(a), Some(b)) => a.differences(b),
+            (Some(_), None) => vec!["the second trace ended".to_string()],
+            (None, _) => vec!["the first trace ended".to_string()],
+        };
+
+        if !differences.is_empty() {
+            return Some(Divergence {
+                index,
+                expected: a.cloned(),
+                actual: b.cloned(),
+                differences,
+            });
+        }
+    }
+
+    None
+}
+
+pub fn parse_trace(text: &str, format: TraceFormat) -> Result<Vec<TraceStep>, TraceError> {
+    let mut steps = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let step = match format {
+            TraceFormat::Text => line.parse(),
+            TraceFormat::Json => serde_json::from_str(line).map_err(|err| err.to_string()),
+        };
+        steps.push(step.map_err(|err| TraceError::InvalidLine(i + 1, err))?);
+    }
+    Ok(steps)
+}
+
+/// Read a trace in the format of its extension
+pub fn load_trace<P: AsRef<Path>>(path: P) -> Result<Vec<TraceStep>, TraceError> {
+    let path = path.as_ref();
+    let text =
+        fs::read_to_string(path).map_err(|err| TraceError::ReadFailed(path.to_path_buf(), err))?;
+    parse_trace(&text, TraceFormat::from_path(path))
+}
@@ -0,0 +1,254 @@
+use std::{fs, path::Path};
+
+use crate::{
+    CompileError,
+    lexer::{
+        Lexer,
+        token::{Keyword, Token},
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// Number of spaces instructions are indented by, labels and defines are never indented
+    pub indent: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self { indent: 2 }
+    }
+}
+
+/// A single source line after classification
+#[derive(Debug)]
+enum Line<'a> {
+    Blank,
+    /// Left untouched, e.g. inside a block comment or when the line fails to lex
+    Verbatim(&'a str),
+    /// A line holding only a comment, `indented` if it was not at column 0
+    Comment {
+        indented: bool,
+        comment: &'a str,
+    },
+    Code {
+        indented: bool,
+        code: String,
+        comment: Option<&'a str>,
+    },
+}
+
+/// Format a whole source file.
+///
+/// Every input line produces exactly one output line, so line numbers are stable
+/// and a formatted range can be cut out of the result.
+pub fn format_source(source: &str, options: &FormatOptions) -> String {
+    let lines = classify_lines(source);
+    let mut output = String::with_capacity(source.len());
+
+    let mut block_start = 0;
+    while block_start < lines.len() {
+        // Trailing comments are aligned within runs of code lines at the same indentation
+        let block_end = match lines[block_start] {
+            Line::Code { indented, .. } => lines[block_start..]
+                .iter()
+                .position(|line| !matches!(line, Line::Code { indented: i, .. } if *i == indented))
+                .map_or(lines.len(), |len| block_start + len),
+            _ => block_start + 1,
+        };
+
+        let comment_column = lines[block_start..block_end]
+            .iter()
+            .filter_map(|line| match line {
+                Line::Code {
+                    indented,
+                    code,
+                    comment: Some(_),
+                } => Some(indent_width(*indented, options) + code.len() + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+
+        for line in &lines[block_start..block_end] {
+            match line {
+                Line::Blank => {}
+                Line::Verbatim(text) => output.push_str(text),
+                Line::Comment { indented, comment } => {
+                    output.push_str(&" ".repeat(indent_width(*indented, options)));
+                    output.push_str(comment);
+                }
+                Line::Code {
+                    indented,
+                    code,
+                    comment,
+                } => {
+                    let width = indent_width(*indented, options) + code.len();
+                    output.push_str(&" ".repeat(indent_width(*indented, options)));
+                    output.push_str(code);
+                    if let Some(comment) = comment {
+                        output.push_str(&" ".repeat(comment_column - width));
+                        output.push_str(comment);
+                    }
+                }
+            }
+            output.push('\n');
+        }
+
+        block_start = block_end;
+    }
+
+    if !source.ends_with('\n') {
+        output.pop();
+    }
+
+    output
+}
+
+/// Format a file in place, or only check it when `check` is set.
+///
+/// Returns whether the file is (or was) not formatted.
+pub fn format_file<P: AsRef<Path>>(
+    path: P,
+    options: &FormatOptions,
+    check: bool,
+) -> Result<bool, CompileError> {
+    let path = path.as_ref();
+
+    if !path.exists() {
+        return Err(CompileError::PathDoesNotExist);
+    }
+
+    let source = fs::read_to_string(path).map_err(CompileError::ReadFileError)?;
+    let formatted = format_source(&source, options);
+
+    if formatted == source {
+        return Ok(false);
+    }
+
+    if !check {
+        fs::write(path, formatted).map_err(CompileError::WriteFileError)?;
+    }
+
+    Ok(true)
+}
+
+fn indent_width(indented: bool, options: &FormatOptions) -> usize {
+    if indented { options.indent } else { 0 }
+}
+
+fn classify_lines(source: &str) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut in_block_comment = false;
+
+    for raw in source.lines() {
+        let line = raw.trim_end();
+
+        if in_block_comment {
+            in_block_comment = !line.contains("*/");
+            lines.push(Line::Verbatim(line));
+            continue;
+        }
+
+        if line.trim().is_empty() {
+            lines.push(Line::Blank);
+            continue;
+        }
+
+        let (code, comment) = split_comment(line);
+
+        // Block comments are kept exactly as written
+        if comment.is_some_and(|comment| comment.starts_with("/*")) {
+            in_block_comment = !comment.is_some_and(|comment| comment.contains("*/"));
+            lines.push(Line::Verbatim(line));
+            continue;
+        }
+
+        if code.trim().is_empty() {
+            lines.push(Line::Comment {
+                indented: line.starts_with(char::is_whitespace),
+                comment: comment.unwrap_or_default(),
+            });
+            continue;
+        }
+
+        match format_code(code) {
+            Some((indented, code)) => lines.push(Line::Code {
+                indented,
+                code,
+                comment,
+            }),
+            None => lines.push(Line::Verbatim(line)),
+        }
+    }
+
+    lines
+}
+
+/// Split a line into its code and trailing comment, ignoring comment markers in char and string literals
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'\'' | b'"') => {
+                i += bytes[i + 1..]
+                    .iter()
+                    .position(|&b| b == quote)
+                    .map_or(bytes.len() - i, |len| len + 1);
+            }
+            b'#' => return (&line[..i], Some(&line[i..])),
+            b'/' if matches!(bytes.get(i + 1), Some(b'/' | b'*')) => {
+                return (&line[..i], Some(&line[i..]));
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    (line, None)
+}
+
+/// Normalize the tokens of a line of code, returning whether it should be indented.
+///
+/// Returns `None` if the code does not lex, so that it is left for the assembler to report.
+fn format_code(code: &str) -> Option<(bool, String)> {
+    let mut parts: Vec<String> = Vec::new();
+    let mut indented = true;
+
+    for token in Lexer::new(code) {
+        let token = token.ok()?;
+        let snippet = token.span.snippet(code);
+
+        let part = match &token.token {
+            Token::Eof => break,
+            Token::Comma => continue,
+            Token::Keyword(Keyword::Operation(_)) => snippet.to_ascii_uppercase(),
+            Token::Keyword(Keyword::Define) => {
+                if parts.is_empty() {
+                    indented = false;
+                }
+                "define".to_string()
+            }
+            Token::Keyword(Keyword::Include) => {
+                if parts.is_empty() {
+                    indented = false;
+                }
+                "include".to_string()
+            }
+            Token::Keyword(Keyword::Condition(_)) => snippet.to_ascii_lowercase(),
+            Token::Register(register) => format!("r{}", register.0),
+            Token::Label(_) => {
+                if parts.is_empty() {
+                    indented = false;
+                }
+                snippet.to_string()
+            }
+            Token::Identifier(_) | Token::Number(_) | Token::String(_) => snippet.to_string(),
+        };
+
+        parts.push(part);
+    }
+
+    Some((indented, parts.join(" ")))
+}
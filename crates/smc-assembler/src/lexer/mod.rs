@@ -1,6 +1,8 @@
 use std::str::FromStr;
 
-use crate::lexer::token::{Condition, Keyword, Operation, Register, Span, Token, TokenSpan};
+use crate::lexer::token::{
+    Condition, Keyword, Operation, Register, SourceId, Span, Token, TokenSpan,
+};
 use anyhow::Result;
 use smc_macros::match_keywords;
 use thiserror::Error;
@@ -11,6 +13,7 @@ pub struct Lexer<'a> {
     input: &'a [u8],
     pos: usize,
     finished: bool,
+    source: SourceId,
 }
 
 #[derive(Error, Debug, Clone)]
@@ -35,22 +38,59 @@ pub enum LexerError {
 
     #[error("Invalid register number `{1}`")]
     InvalidRegisterNumber(Span, String),
+
+    #[error("Cannot include `{1}`: {2}")]
+    InvalidInclude(Span, String, String),
+}
+
+impl LexerError {
+    pub fn span(&self) -> &Span {
+        match self {
+            LexerError::InvalidNumber(span, _)
+            | LexerError::UnexpectedCharacter(span, _)
+            | LexerError::ExpectedCharacter(span, _)
+            | LexerError::UnknownCondition(span, _)
+            | LexerError::InvalidOffset(span, _)
+            | LexerError::InvalidIsaCode(span, _)
+            | LexerError::InvalidRegisterNumber(span, _)
+            | LexerError::InvalidInclude(span, _, _) => span,
+        }
+    }
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
+        Self::with_source(input, 0)
+    }
+
+    /// Lex a source file whose spans should point at `source`
+    pub fn with_source(input: &'a str, source: SourceId) -> Self {
         Self {
             input: input.as_bytes(),
             pos: 0,
             finished: false,
+            source,
         }
     }
 
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(start, end).in_source(self.source)
+    }
+
     /// Peek at the next byte without consuming
     fn peek(&self, amount: usize) -> Option<u8> {
         self.input.get(self.pos + amount).copied()
     }
 
+    /// The whole character starting at byte `pos`, which may be several bytes long
+    fn char_at(&self, pos: usize) -> char {
+        let end = (pos + 4).min(self.input.len());
+        String::from_utf8_lossy(&self.input[pos..end])
+            .chars()
+            .next()
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+
     /// Consume and return the next byte
     fn advance(&mut self) -> Option<u8> {
         let res = self.peek(0);
@@ -71,7 +111,7 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        return whitespace_exists;
+        whitespace_exists
     }
 
     pub fn read_identifier(&mut self) -> String {
@@ -187,6 +227,7 @@ impl<'a> Lexer<'a> {
             "notcarry" => Keyword::Condition(Condition::Less),
 
             "define" => Keyword::Define,
+            "include" => Keyword::Include,
         );
 
         let token = match keyword {
@@ -195,54 +236,73 @@ impl<'a> Lexer<'a> {
                     self.advance();
                 }
 
-                TokenSpan::new(Token::Keyword(keyword), Span::new(start, start + size))
+                TokenSpan::new(Token::Keyword(keyword), self.span(start, start + size))
             }
             None => match self.advance() {
-                Some(b'\'' | b'"') => match self.peek(1) {
-                    Some(b'\'' | b'"') => {
-                        let token = TokenSpan::new(
-                        Token::Number(char_to_isa_code(self.advance().expect("Should be impossible for a character to not exist after checking for the character after")).ok_or(LexerError::InvalidIsaCode(Span::new(start, self.pos), String::from("Invalid ISA code")))? as f64),
-                            Span::new(start, self.pos),
-                        );
+                Some(quote @ (b'\'' | b'"')) => match (self.peek(0), self.peek(1)) {
+                    // Only single quotes make a char literal, `"a"` is a one character string
+                    (Some(c), Some(b'\'')) if quote == b'\'' => {
+                        let value = char_to_isa_code(c).ok_or(LexerError::InvalidIsaCode(
+                            self.span(start, self.pos + 1),
+                            String::from("Invalid ISA code"),
+                        ))?;
 
+                        self.advance();
                         self.advance();
 
-                        token
+                        TokenSpan::new(Token::Number(value as f64), self.span(start, self.pos))
                     }
-                    Some(_) | None => {
-                        let original_char = self.peek(0).unwrap();
-                        self.advance();
-                        self.advance();
-                        return Err(LexerError::ExpectedCharacter(
-                            Span::new(start, self.pos),
-                            original_char as char,
-                        ));
+                    _ => {
+                        let content_start = self.pos;
+                        let value = loop {
+                            match self.peek(0) {
+                                Some(c) if c == quote => {
+                                    let value = String::from_utf8_lossy(
+                                        &self.input[content_start..self.pos],
+                                    )
+                                    .into_owned();
+                                    self.advance();
+                                    break value;
+                                }
+                                Some(c) if c != b'\n' => {
+                                    self.advance();
+                                }
+                                _ => {
+                                    return Err(LexerError::ExpectedCharacter(
+                                        self.span(start, self.pos),
+                                        quote as char,
+                                    ));
+                                }
+                            }
+                        };
+
+                        TokenSpan::new(Token::String(value), self.span(start, self.pos))
                     }
                 },
                 Some(b'.') => TokenSpan::new(
                     Token::Label(self.read_identifier()),
-                    Span::new(start, self.pos),
+                    self.span(start, self.pos),
                 ),
                 Some(b'-') => {
-                    if self.skip_whitespace() {
+                    if self.skip_whitespace() || self.peek(0).is_none() {
                         TokenSpan::new(
-                            Token::Keyword(Keyword::Condition(Condition::Not)),
-                            Span::new(start, start),
+                            Token::Keyword(Keyword::Condition(Condition::Negative)),
+                            self.span(start, start + 1),
                         )
                     } else {
                         self.pos -= 1;
                         let value: f64 = self.read_number()?;
-                        TokenSpan::new(Token::Number(value), Span::new(start, self.pos))
+                        TokenSpan::new(Token::Number(value), self.span(start, self.pos))
                     }
                 }
                 Some(b'0'..=b'9') => {
                     self.pos -= 1;
                     let value: f64 = self.read_number()?;
-                    TokenSpan::new(Token::Number(value), Span::new(start, self.pos))
+                    TokenSpan::new(Token::Number(value), self.span(start, self.pos))
                 }
                 None => TokenSpan {
                     token: Token::Eof,
-                    span: Span::new(self.pos, self.pos),
+                    span: self.span(self.pos, self.pos),
                 },
                 Some(b'r' | b'R') if self.peek(0).is_some_and(|b| b.is_ascii_digit()) => {
                     // Parse register number directly without string allocation
@@ -265,7 +325,7 @@ impl<'a> Lexer<'a> {
                         }
                     }
 
-                    let span = Span::new(start, self.pos);
+                    let span = self.span(start, self.pos);
 
                     if self
                         .peek(0)
@@ -274,7 +334,7 @@ impl<'a> Lexer<'a> {
                         self.pos = start;
                         TokenSpan::new(
                             Token::Identifier(self.read_identifier()),
-                            Span::new(start, self.pos),
+                            self.span(start, self.pos),
                         )
                     } else if has_overflow {
                         let raw: String = self.input[start..self.pos]
@@ -290,14 +350,16 @@ impl<'a> Lexer<'a> {
                     self.pos -= 1;
                     TokenSpan::new(
                         Token::Identifier(self.read_identifier()),
-                        Span::new(start, self.pos),
+                        self.span(start, self.pos),
                     )
                 }
-                Some(b',') => TokenSpan::new(Token::Comma, Span::new(start, self.pos)),
-                Some(c) => {
+                Some(b',') => TokenSpan::new(Token::Comma, self.span(start, self.pos)),
+                Some(_) => {
+                    let c = self.char_at(start);
+                    self.pos = start + c.len_utf8();
                     return Err(LexerError::UnexpectedCharacter(
-                        Span::new(start, self.pos),
-                        c as char,
+                        self.span(start, self.pos),
+                        c,
                     ));
                 }
             },
@@ -331,7 +393,7 @@ impl<'a> Lexer<'a> {
 
             if slice.is_empty() {
                 return Err(LexerError::InvalidNumber(
-                    Span::new(start, self.pos),
+                    self.span(start, self.pos),
                     "0b".to_string(),
                 ));
             }
@@ -340,12 +402,12 @@ impl<'a> Lexer<'a> {
                 Ok(value) => match value.to_string().parse::<N>() {
                     Ok(v) => Ok(v),
                     Err(_) => Err(LexerError::InvalidNumber(
-                        Span::new(start, self.pos),
+                        self.span(start, self.pos),
                         format!("0b{}", slice),
                     )),
                 },
                 Err(_) => Err(LexerError::InvalidNumber(
-                    Span::new(start, self.pos),
+                    self.span(start, self.pos),
                     format!("0b{}", slice),
                 )),
             }
@@ -371,7 +433,7 @@ impl<'a> Lexer<'a> {
 
             if slice.is_empty() {
                 return Err(LexerError::InvalidNumber(
-                    Span::new(start, self.pos),
+                    self.span(start, self.pos),
                     "0x".to_string(),
                 ));
             }
@@ -380,12 +442,12 @@ impl<'a> Lexer<'a> {
                 Ok(value) => match value.to_string().parse::<N>() {
                     Ok(v) => Ok(v),
                     Err(_) => Err(LexerError::InvalidNumber(
-                        Span::new(start, self.pos),
+                        self.span(start, self.pos),
                         format!("0x{}", slice),
                     )),
                 },
                 Err(_) => Err(LexerError::InvalidNumber(
-                    Span::new(start, self.pos),
+                    self.span(start, self.pos),
                     format!("0x{}", slice),
                 )),
             }
@@ -424,13 +486,13 @@ impl<'a> Lexer<'a> {
 
             match slice.parse::<N>() {
                 Ok(value) => Ok(value),
-                Err(_) => Err(LexerError::InvalidNumber(Span::new(start, self.pos), slice)),
+                Err(_) => Err(LexerError::InvalidNumber(self.span(start, self.pos), slice)),
             }
         }
     }
 }
 
-fn char_to_isa_code(c: u8) -> Option<u8> {
+pub(crate) fn char_to_isa_code(c: u8) -> Option<u8> {
     match c {
         b' ' => Some(0),
         b'a'..=b'z' => Some(c - b'a' + 1),
@@ -446,24 +508,22 @@ impl Iterator for Lexer<'_> {
     type Item = Result<TokenSpan, LexerError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            if self.finished {
-                return None;
-            }
+        if self.finished {
+            return None;
+        }
 
-            match self.next_token() {
-                Ok(
-                    token @ TokenSpan {
-                        token: Token::Eof,
-                        span: _,
-                    },
-                ) => {
-                    self.finished = true;
-                    return Some(Ok(token));
-                }
-                Ok(token) => return Some(Ok(token)),
-                Err(e) => return Some(Err(e)),
+        match self.next_token() {
+            Ok(
+                token @ TokenSpan {
+                    token: Token::Eof,
+                    span: _,
+                },
+            ) => {
+                self.finished = true;
+                Some(Ok(token))
             }
+            Ok(token) => Some(Ok(token)),
+            Err(e) => Some(Err(e)),
         }
     }
 }
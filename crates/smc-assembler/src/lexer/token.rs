@@ -1,5 +1,7 @@
 use std::path::Path;
 
+use strum_macros::VariantArray;
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct TokenSpan {
     pub token: Token,
@@ -12,10 +14,14 @@ impl TokenSpan {
     }
 }
 
+/// Index of the source file a span points into, `0` being the file being compiled
+pub type SourceId = usize;
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Span {
     start: usize,
     end: usize,
+    source: SourceId,
 }
 
 impl Span {
@@ -26,7 +32,17 @@ impl Span {
             start,
             end
         );
-        Span { start, end }
+        Span {
+            start,
+            end,
+            source: 0,
+        }
+    }
+
+    /// The same span, pointing into another source file
+    pub fn in_source(mut self, source: SourceId) -> Self {
+        self.source = source;
+        self
     }
 
     /// Calculate line and column from a byte offset in source text
@@ -86,6 +102,10 @@ impl Span {
         self.end
     }
 
+    pub fn source(&self) -> SourceId {
+        self.source
+    }
+
     /// Format an error with context from the source code
     pub fn format_error<P: AsRef<Path>>(&self, file: P, source: &str, error_msg: &str) -> String {
         let (start_line, start_col) = self.start_location(source);
@@ -130,7 +150,7 @@ pub enum Condition {
     NotNegative,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, VariantArray)]
 pub enum Operation {
     Nop,
     Hlt,
@@ -171,11 +191,57 @@ pub enum Operation {
     Clr,
 }
 
+impl Operation {
+    /// The lowercase mnemonic used to write this operation in source
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Operation::Nop => "nop",
+            Operation::Hlt => "hlt",
+            Operation::Add => "add",
+            Operation::Sub => "sub",
+            Operation::Nor => "nor",
+            Operation::And => "and",
+            Operation::Xor => "xor",
+            Operation::Rsh => "rsh",
+            Operation::Ldi => "ldi",
+            Operation::Adi => "adi",
+            Operation::Jmp => "jmp",
+            Operation::Brh => "brh",
+            Operation::Cal => "cal",
+            Operation::Ret => "ret",
+            Operation::Lod => "lod",
+            Operation::Str => "str",
+            Operation::Cmp => "cmp",
+            Operation::Mov => "mov",
+            Operation::Lsh => "lsh",
+            Operation::Inc => "inc",
+            Operation::Dec => "dec",
+            Operation::Not => "not",
+            Operation::Neg => "neg",
+            Operation::Or => "or",
+            Operation::Cpy => "cpy",
+            Operation::Adc => "adc",
+            Operation::Mld => "mld",
+            Operation::Mst => "mst",
+            Operation::Pld => "pld",
+            Operation::Pst => "pst",
+            Operation::Inv => "inv",
+            Operation::Cpi => "cpi",
+            Operation::Ani => "ani",
+            Operation::Bkl => "bkl",
+            Operation::Bkr => "bkr",
+            Operation::Skp => "skp",
+            Operation::Clr => "clr",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Keyword {
     Operation(Operation),
     Condition(Condition),
     Define,
+    Include,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -191,7 +257,21 @@ pub enum Token {
     Label(String),
     Identifier(String),
     Number(f64),
+    String(String),
     Comma,
     Eof,
     Register(Register),
 }
+
+impl Token {
+    /// The value of a number, or of a one character string such as `"A"` in ISA code
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Token::Number(n) => Some(*n),
+            Token::String(s) if s.len() == 1 => {
+                super::char_to_isa_code(s.as_bytes()[0]).map(f64::from)
+            }
+            _ => None,
+        }
+    }
+}
@@ -1,16 +1,31 @@
-use std::{fs, path::Path};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use crate::{
-    assembler::{Assembler, AssemblerError, backends::Backend},
-    lexer::{Lexer, LexerError},
-    parser::{Parser, ParserError},
-    save::{memory::Format, save_file},
+    assembler::{
+        AssembledInstruction, Assembler, AssemblerError, AssemblerResult, LabelMap,
+        backends::Backend,
+    },
+    lexer::{Lexer, LexerError, token::TokenSpan},
+    parser::{DefineMap, Parser},
+    save::{SaveOptions, save_program},
+    sources::{Sources, map::SourceMap},
 };
 
 pub mod assembler;
+pub mod debugger;
+pub mod disassembler;
+pub mod emulator;
+pub mod formatter;
 pub mod lexer;
 pub mod parser;
+pub mod project;
 pub mod save;
+pub mod sources;
+pub mod testing;
+pub mod watch;
 
 #[derive(thiserror::Error, Debug)]
 pub enum CompileError {
@@ -28,79 +43,156 @@ pub enum CompileError {
     CompilationFailed,
     #[error("Schematic save failed")]
     SchematicSaveFailed(#[from] mc_schem::Error),
+    #[error("Failed to write datapack: {0}")]
+    DatapackWriteFailed(zip::result::ZipError),
+    #[error("Failed to load schematic: {0}")]
+    SchematicLoadFailed(mc_schem::Error),
+    #[error("No {0} found in schematic")]
+    MemoryNotFound(String),
+    #[error("Invalid machine code on line {0}")]
+    InvalidMachineCode(usize),
     #[error("Unsupported file type")]
     UnsupportedFileType,
     #[error(
         "Missing format when generating schematic, specify format with `--format <FORMAT NAME>`"
     )]
     MissingFormat,
+    #[error("Failed to parse manifest: {0}")]
+    ManifestParseError(toml::de::Error),
+    #[error("Invalid glob `{0}`: {1}")]
+    InvalidGlob(String, globset::Error),
+    #[error("Unknown backend `{0}`")]
+    UnknownBackend(String),
+    #[error("Unknown format `{0}`")]
+    UnknownFormat(String),
+    #[error("Format `{0}` is not supported for {1}")]
+    UnsupportedFormat(String, &'static str),
+    #[error("Failed to watch files: {0}")]
+    WatchError(notify::Error),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CompileOptions {
+    /// Defines added to those of the source, replacing any with the same name
+    pub defines: DefineMap,
+    /// Directories searched for included files not found next to the including file
+    pub include_dirs: Vec<PathBuf>,
+    /// Write the tokens, parsed items and defines to the working directory
+    pub debug_artifacts: bool,
 }
 
+/// A successfully assembled program, which every output format is rendered from
+#[derive(Debug, Clone)]
+pub struct CompiledProgram {
+    pub target: Backend,
+    pub bytes: Vec<u8>,
+    pub instructions: Vec<AssembledInstruction>,
+    pub labels: LabelMap,
+    /// Defines of the source, the target's and those given as options
+    pub defines: DefineMap,
+    pub sources: Sources,
+}
+
+impl CompiledProgram {
+    pub fn source_map(&self) -> SourceMap {
+        SourceMap::new(&self.target, &self.instructions, &self.sources)
+    }
+
+    /// Used and free addresses of every memory segment of the target, one line each
+    pub fn size_report(&self) -> String {
+        let word_size = self.target.word_size();
+
+        self.target
+            .segments()
+            .iter()
+            .map(|segment| {
+                let used: usize = self
+                    .instructions
+                    .iter()
+                    .filter(|instruction| {
+                        (segment.start..segment.start + segment.size).contains(&instruction.address)
+                    })
+                    .map(|instruction| instruction.bytes.len().div_ceil(word_size))
+                    .sum();
+
+                format!(
+                    "{}: {} of {} {}s used, {} free",
+                    segment.name,
+                    used,
+                    segment.size,
+                    segment.unit,
+                    segment.size.saturating_sub(used)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Compile `input` once and write every one of `outputs`, each in the format of its extension
 pub fn compile_to_file<P1: AsRef<Path>, P2: AsRef<Path>>(
     input: P1,
-    output: P2,
+    outputs: &[P2],
     target: Backend,
-    debug_artifacts: bool,
-    format: Option<Format>,
+    options: &CompileOptions,
+    save_options: &SaveOptions,
 ) -> Result<(), CompileError> {
-    let input = input.as_ref();
-    let output = output.as_ref();
+    let program = compile_file(input, target, options)?;
+    println!("{}", program.size_report());
 
-    if !input.exists() {
-        return Err(CompileError::PathDoesNotExist.into());
+    for output in outputs {
+        let output = output.as_ref();
+        save_program(output, &program, save_options)?;
+        println!("Output written to: {}", output.display());
     }
 
-    let source = fs::read_to_string(&input).map_err(|err| CompileError::ReadFileError(err))?;
+    Ok(())
+}
 
-    let result = compile(&source, target, debug_artifacts);
+/// Compile a source file and the files it includes, printing any errors
+pub fn compile_file<P: AsRef<Path>>(
+    input: P,
+    target: Backend,
+    options: &CompileOptions,
+) -> Result<CompiledProgram, CompileError> {
+    compile_file_reporting(input, target, options, |report| eprint!("{}", report))
+}
 
-    match result {
-        Ok(result) => {
-            save_file(output, result, format)?;
+/// Like [`compile_file`], passing the errors shown against their source to `report` instead
+pub fn compile_file_reporting<P: AsRef<Path>>(
+    input: P,
+    target: Backend,
+    options: &CompileOptions,
+    report: impl FnOnce(&str),
+) -> Result<CompiledProgram, CompileError> {
+    let (sources, tokens) = Sources::load(input, &options.include_dirs)?;
 
-            println!("Output written to: {}", output.display());
-            return Ok(());
-        }
-        Err(errors) => {
-            let errors = match errors {
-                CompileError::AssembleError(assembler_errors) => assembler_errors,
-                error @ _ => return Err(error),
-            };
-
-            eprintln!("Compilation failed with {} error(s):\n", errors.len());
-            for err in &errors {
-                let span = match err {
-                    AssemblerError::DefineNotFound(span, _) => span,
-                    AssemblerError::LabelNotFound(span, _) => span,
-                    AssemblerError::ParserError(parser_error) => match parser_error {
-                        ParserError::SyntaxError(lexer_error) => match lexer_error {
-                            LexerError::InvalidNumber(span, _) => span,
-                            LexerError::UnexpectedCharacter(span, _) => span,
-                            LexerError::ExpectedCharacter(span, _) => span,
-                            LexerError::UnknownCondition(span, _) => span,
-                            LexerError::InvalidOffset(span, _) => span,
-                            LexerError::InvalidIsaCode(span, _) => span,
-                            LexerError::InvalidRegisterNumber(span, _) => span,
-                        },
-                        ParserError::DuplicateDefine(span, _) => span,
-                        ParserError::DuplicateLabel(span, _) => span,
-                        ParserError::ExpectedButReceived(span, _, _) => span,
-                        ParserError::UnexpectedEof(span) => span,
-                        ParserError::InvalidSkip(span, _) => span,
-                    },
-                    AssemblerError::UnsupportedOperation(span, _) => span,
-                    AssemblerError::InvalidRegister(span, _) => span,
-                    AssemblerError::AddressOutOfRange(span, _) => span,
-                    AssemblerError::OffsetOutOfRange(span, _) => span,
-                    AssemblerError::InvalidCondition(span, _) => span,
-                    AssemblerError::ImmediateOutOfRange(span, _) => span,
-                };
-
-                eprintln!("{}", span.format_error(&input, &source, &err.to_string()));
-            }
-            return Err(CompileError::CompilationFailed);
+    let result = assemble_tokens(tokens, target.clone(), options)?;
+    let errors = match result.result {
+        Ok(bytes) => {
+            return Ok(CompiledProgram {
+                target,
+                bytes,
+                instructions: result.instructions,
+                labels: result.labels,
+                defines: result.defines,
+                sources,
+            });
         }
+        Err(errors) => errors,
+    };
+
+    let mut text = format!("Compilation failed with {} error(s):\n\n", errors.len());
+    for err in &errors {
+        let span = err.span();
+
+        let file = sources.get(span.source()).unwrap_or(&sources.files[0]);
+        text.push_str(&span.format_error(&file.path, &file.text, &err.to_string()));
+        text.push('\n');
     }
+    report(&text);
+
+    Err(CompileError::CompilationFailed)
 }
 
 pub fn compile(
@@ -108,9 +200,23 @@ pub fn compile(
     target: Backend,
     generate_debug_artifacts: bool,
 ) -> Result<Vec<u8>, CompileError> {
-    let tokens: Vec<_> = Lexer::new(source).into_iter().collect();
+    let tokens: Vec<_> = Lexer::new(source).collect();
+    let options = CompileOptions {
+        debug_artifacts: generate_debug_artifacts,
+        ..Default::default()
+    };
+
+    assemble_tokens(tokens, target, &options)?
+        .result
+        .map_err(CompileError::AssembleError)
+}
 
-    if generate_debug_artifacts {
+fn assemble_tokens(
+    tokens: Vec<Result<TokenSpan, LexerError>>,
+    target: Backend,
+    options: &CompileOptions,
+) -> Result<AssemblerResult, CompileError> {
+    if options.debug_artifacts {
         fs::write(
             "tokens.txt",
             tokens
@@ -119,12 +225,13 @@ pub fn compile(
                 .collect::<Vec<String>>()
                 .join("\n"),
         )
-        .map_err(|err| CompileError::WriteFileError(err))?;
+        .map_err(CompileError::WriteFileError)?;
     }
 
-    let parsed = Parser::new(tokens).parse();
+    let mut parsed = Parser::new(tokens).parse();
+    parsed.defines.extend(options.defines.clone());
 
-    if generate_debug_artifacts {
+    if options.debug_artifacts {
         fs::write(
             "items.txt",
             parsed
@@ -134,7 +241,7 @@ pub fn compile(
                 .collect::<Vec<String>>()
                 .join("\n"),
         )
-        .map_err(|err| CompileError::WriteFileError(err))?;
+        .map_err(CompileError::WriteFileError)?;
 
         fs::write(
             "defines.txt",
@@ -145,12 +252,9 @@ pub fn compile(
                 .collect::<Vec<String>>()
                 .join("\n"),
         )
-        .map_err(|err| CompileError::WriteFileError(err))?;
+        .map_err(CompileError::WriteFileError)?;
     }
 
     let assembler = Assembler::new(target, parsed);
-    assembler
-        .assemble()
-        .result
-        .map_err(|err| CompileError::AssembleError(err))
+    Ok(assembler.assemble())
 }
@@ -1,6 +1,43 @@
-use anyhow::Result;
+use std::{
+    env, fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
 use clap::{Parser as ClapParser, Subcommand};
-use smc_assembler::{assembler::backends::Backend, compile_to_file, save::memory::Format};
+use smc_assembler::{
+    CompileError, CompileOptions, CompiledProgram,
+    assembler::backends::Backend,
+    compile_file, compile_to_file,
+    debugger::{Command, Debugger},
+    disassembler::{disassemble, to_source},
+    emulator::{
+        Emulator, Io, NullIo, Step,
+        batpu2_io::Batpu2Io,
+        input::InputScript,
+        profiler::{Profiler, worst_case_cycles},
+        trace::{TraceFormat, Tracer, first_divergence, load_trace},
+    },
+    formatter::{FormatOptions, format_file},
+    lexer::{Lexer, token::Token},
+    parser::DefineMap,
+    project::{Manifest, select_backend},
+    save::{
+        Endianness, SaveOptions,
+        convert::convert_to_mc,
+        load_program,
+        memory::Format,
+        save_file, save_program_if_changed,
+        schematic::{Mirror, Rotation, SchematicOptions},
+    },
+    sources::Sources,
+    testing::{
+        TestSuite, discover,
+        golden::{GoldenOptions, Outcome, check_golden},
+    },
+    watch::watch,
+};
 use tracing::instrument;
 
 #[derive(ClapParser)]
@@ -17,8 +54,13 @@ enum Commands {
         /// Path to the input file
         input: String,
 
-        /// Path of the output file
-        output: String,
+        /// Path of the output file, its extension picks the format
+        #[arg(required_unless_present = "outputs")]
+        output: Option<PathBuf>,
+
+        /// Additional output file, all outputs are written from a single compilation
+        #[arg(short, long = "output", value_name = "OUTPUT")]
+        outputs: Vec<PathBuf>,
 
         /// Target backend
         #[arg(short, long)]
@@ -28,10 +70,286 @@ enum Commands {
         #[arg(short, long)]
         format: Option<Format>,
 
+        /// Byte order of instruction words in `.bin` outputs
+        #[arg(long, default_value = "big")]
+        endianness: Endianness,
+
+        #[command(flatten)]
+        schematic: Box<SchematicArgs>,
+
+        /// Program already built in the world, as machine code or a schematic, so schematic and
+        /// `.mcfunction` outputs only hold the blocks that changed
+        #[arg(long, value_name = "PREVIOUS")]
+        diff_from: Option<PathBuf>,
+
+        /// Define overriding any in the source, e.g. `-D DEBUG` or `-D LEVEL=2`
+        #[arg(short = 'D', long = "define", value_name = "NAME[=VALUE]", value_parser = parse_define)]
+        defines: Vec<(String, f64)>,
+
+        /// Directory searched for included files
+        #[arg(short = 'I', long = "include", value_name = "DIR")]
+        include_dirs: Vec<PathBuf>,
+
         /// Generate debug artifacts
         #[arg(long)]
         debug_artifacts: bool,
+
+        /// Recompile whenever the input or one of its includes changes
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Builds the targets of the project manifest
+    Build {
+        /// Names of the targets to build, all of them if none are given
+        targets: Vec<String>,
+
+        /// Path to the manifest, defaults to the closest `smc.toml`
+        #[arg(long)]
+        manifest: Option<PathBuf>,
+
+        /// Define overriding any in the manifest or source, e.g. `-D DEBUG` or `-D LEVEL=2`
+        #[arg(short = 'D', long = "define", value_name = "NAME[=VALUE]", value_parser = parse_define)]
+        defines: Vec<(String, f64)>,
+
+        /// Rebuild whenever the manifest or a source file of the targets changes
+        #[arg(long)]
+        watch: bool,
+    },
+    /// Reads a program back from the memory built in a schematic
+    Extract {
+        /// Path to the `.schem`, `.litematic` or `.nbt` file
+        input: PathBuf,
+
+        /// Path of the output file, its extension picks the format, prints machine code if omitted
+        output: Option<PathBuf>,
+
+        /// Memory format to read
+        #[arg(short, long, default_value = "batpu2-instruction-memory")]
+        format: Format,
+
+        /// Rotation the memory was built with
+        #[arg(long, default_value = "0")]
+        rotation: Rotation,
+
+        /// Mirroring the memory was built with
+        #[arg(long, default_value = "none")]
+        mirror: Mirror,
+
+        /// Write source disassembled for the given target instead of machine code
+        #[arg(short, long, value_name = "TARGET")]
+        disassemble: Option<Backend>,
+    },
+    /// Runs a program on the emulator in an interactive step debugger
+    Debug {
+        /// Path to the input file
+        input: PathBuf,
+
+        /// Target backend
+        #[arg(short, long)]
+        target: Backend,
+
+        /// Define overriding any in the source, e.g. `-D DEBUG` or `-D LEVEL=2`
+        #[arg(short = 'D', long = "define", value_name = "NAME[=VALUE]", value_parser = parse_define)]
+        defines: Vec<(String, f64)>,
+
+        /// Directory searched for included files
+        #[arg(short = 'I', long = "include", value_name = "DIR")]
+        include_dirs: Vec<PathBuf>,
+
+        /// Most instructions `continue` and `next` run before pausing
+        #[arg(long, default_value_t = 10_000_000)]
+        max_cycles: u64,
+
+        #[command(flatten)]
+        io: IoArgs,
     },
+    /// Runs a program on the emulator, showing the BatPU-2 screen and displays once it stops
+    Run {
+        /// Path to the input file
+        input: PathBuf,
+
+        /// Target backend
+        #[arg(short, long)]
+        target: Backend,
+
+        /// Define overriding any in the source, e.g. `-D DEBUG` or `-D LEVEL=2`
+        #[arg(short = 'D', long = "define", value_name = "NAME[=VALUE]", value_parser = parse_define)]
+        defines: Vec<(String, f64)>,
+
+        /// Directory searched for included files
+        #[arg(short = 'I', long = "include", value_name = "DIR")]
+        include_dirs: Vec<PathBuf>,
+
+        /// Most instructions to run before stopping
+        #[arg(long, default_value_t = 10_000_000)]
+        max_cycles: u64,
+
+        /// Stop once this many frames were shown with `buffer_screen`
+        #[arg(long)]
+        frames: Option<usize>,
+
+        /// Write the screen to a PNG once the program stops
+        #[arg(long, value_name = "PNG")]
+        screenshot: Option<PathBuf>,
+
+        /// Write every frame to a PNG named `frame-NNNN.png` in this directory
+        #[arg(long, value_name = "DIR")]
+        frame_dir: Option<PathBuf>,
+
+        /// Image pixels per screen pixel in PNG outputs
+        #[arg(long, default_value_t = 8)]
+        scale: usize,
+
+        /// Report where the cycles went once the program stops
+        #[arg(long)]
+        profile: bool,
+
+        /// Number of labels and subroutines the profile lists
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+
+        /// Write every step to a trace, as JSON lines if it ends in `.json` or `.jsonl`
+        #[arg(long, value_name = "FILE")]
+        trace: Option<PathBuf>,
+
+        #[command(flatten)]
+        io: IoArgs,
+    },
+    /// Runs the tests written for programs on the emulator
+    Test {
+        /// Programs, test files or directories to search for programs with tests
+        #[arg(default_value = ".")]
+        paths: Vec<PathBuf>,
+
+        /// Target backend, picked for each program like the LSP does if not given
+        #[arg(short, long)]
+        target: Option<Backend>,
+
+        /// Define overriding any in the source, e.g. `-D DEBUG` or `-D LEVEL=2`
+        #[arg(short = 'D', long = "define", value_name = "NAME[=VALUE]", value_parser = parse_define)]
+        defines: Vec<(String, f64)>,
+
+        /// Directory searched for included files
+        #[arg(short = 'I', long = "include", value_name = "DIR")]
+        include_dirs: Vec<PathBuf>,
+    },
+    /// Finds the first step where two traces of `run --trace` or other tools differ
+    TraceDiff {
+        /// Trace to compare against
+        expected: PathBuf,
+
+        /// Trace to compare
+        actual: PathBuf,
+
+        /// Number of matching steps shown before the first difference
+        #[arg(long, default_value_t = 3)]
+        context: usize,
+    },
+    /// Compares the outputs of the programs in `DIR/programs` with those in `DIR/expected`
+    CheckGolden {
+        /// Directories holding `programs` and `expected`
+        #[arg(required = true)]
+        dirs: Vec<PathBuf>,
+
+        /// Write the expected outputs from the programs instead of comparing them
+        #[arg(long)]
+        bless: bool,
+
+        /// Instruction memory format for schematic outputs, the target's by default
+        #[arg(short, long)]
+        format: Option<Format>,
+
+        /// Byte order of instruction words in `.bin` outputs
+        #[arg(long, default_value = "big")]
+        endianness: Endianness,
+    },
+    /// Formats the given source files in place
+    Fmt {
+        /// Paths to the source files
+        #[arg(required = true)]
+        inputs: Vec<String>,
+
+        /// Only check formatting, failing if any file would be changed
+        #[arg(long)]
+        check: bool,
+
+        /// Number of spaces to indent instructions by
+        #[arg(long, default_value_t = FormatOptions::default().indent)]
+        indent: usize,
+    },
+}
+
+#[derive(clap::Args)]
+struct IoArgs {
+    /// Seed of the numbers read from `rng` on the BatPU-2
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Controller input script to replay on the BatPU-2
+    #[arg(long, value_name = "SCRIPT")]
+    controller: Option<PathBuf>,
+}
+
+impl IoArgs {
+    fn batpu2_io(&self) -> Result<Batpu2Io> {
+        let script = match &self.controller {
+            Some(path) => InputScript::load(path)
+                .with_context(|| format!("Failed to load {}", path.display()))?,
+            None => InputScript::default(),
+        };
+        Ok(Batpu2Io::new(self.seed, script))
+    }
+}
+
+#[derive(clap::Args)]
+struct SchematicArgs {
+    /// Move the blocks of schematic outputs relative to the paste point
+    #[arg(long, value_name = "X,Y,Z", value_parser = parse_position, allow_hyphen_values = true)]
+    offset: Option<[i32; 3]>,
+
+    /// World position of the paste point, for absolute coordinates in `.mcfunction` and datapack
+    /// outputs
+    #[arg(long, value_name = "X,Y,Z", value_parser = parse_position, allow_hyphen_values = true)]
+    origin: Option<[i32; 3]>,
+
+    /// Rotate schematic outputs clockwise about the paste point, in degrees
+    #[arg(long, default_value = "0")]
+    rotation: Rotation,
+
+    /// Flip schematic outputs along an axis, before rotating them
+    #[arg(long, default_value = "none")]
+    mirror: Mirror,
+
+    /// Name of the region in schematic outputs
+    #[arg(long)]
+    region_name: Option<String>,
+
+    /// Name stored in the metadata of schematic outputs
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Author stored in the metadata of schematic outputs
+    #[arg(long)]
+    author: Option<String>,
+
+    /// Description stored in the metadata of schematic outputs
+    #[arg(long)]
+    description: Option<String>,
+}
+
+impl SchematicArgs {
+    fn options(&self) -> SchematicOptions {
+        SchematicOptions {
+            offset: self.offset.unwrap_or_default(),
+            rotation: self.rotation,
+            mirror: self.mirror,
+            origin: self.origin,
+            region_name: self.region_name.clone(),
+            name: self.name.clone(),
+            author: self.author.clone(),
+            description: self.description.clone(),
+        }
+    }
 }
 
 #[instrument]
@@ -42,11 +360,597 @@ fn main() -> Result<()> {
         Commands::Compile {
             input,
             output,
+            outputs,
             target,
+            defines,
+            include_dirs,
             debug_artifacts,
             format,
-        } => compile_to_file(input, output, target.clone(), *debug_artifacts, *format)?,
+            endianness,
+            schematic,
+            diff_from,
+            watch: watching,
+        } => {
+            let mut save_options = SaveOptions {
+                format: *format,
+                endianness: *endianness,
+                schematic: schematic.options(),
+                previous: None,
+            };
+            if let Some(previous) = diff_from {
+                save_options.previous = Some(
+                    load_program(previous, &save_options)
+                        .with_context(|| format!("Failed to read {}", previous.display()))?,
+                );
+            }
+            let outputs: Vec<PathBuf> = output.iter().chain(outputs).cloned().collect();
+
+            let options = CompileOptions {
+                defines: defines.iter().cloned().collect(),
+                include_dirs: include_dirs.clone(),
+                debug_artifacts: *debug_artifacts,
+            };
+
+            if !*watching {
+                compile_to_file(input, &outputs, target.clone(), &options, &save_options)?;
+                return Ok(());
+            }
+
+            watch(|| {
+                match compile_file(input, target.clone(), &options) {
+                    Ok(program) => {
+                        println!("{}", program.size_report());
+                        for output in &outputs {
+                            match save_program_if_changed(output, &program, &save_options) {
+                                Ok(true) => println!("Output written to: {}", output.display()),
+                                Ok(false) => println!("Unchanged: {}", output.display()),
+                                Err(err) => eprintln!("Error: {}", err),
+                            }
+                        }
+                    }
+                    // The errors have already been printed
+                    Err(CompileError::CompilationFailed) => {}
+                    Err(err) => eprintln!("Error: {}", err),
+                }
+
+                Sources::dependencies(input, &options.include_dirs)
+            })?
+        }
+        Commands::Build {
+            targets,
+            manifest,
+            defines,
+            watch: watching,
+        } => {
+            let path = match manifest {
+                Some(path) => path.clone(),
+                None => Manifest::find(env::current_dir()?)
+                    .context("No smc.toml found in the current directory or its parents")?,
+            };
+            let defines: DefineMap = defines.iter().cloned().collect();
+
+            if *watching {
+                watch(|| {
+                    let (files, result) = build(&path, targets, &defines);
+                    if let Err(err) = result {
+                        eprintln!("Error: {:#}", err);
+                    }
+                    files
+                })?
+            } else {
+                build(&path, targets, &defines).1?
+            }
+        }
+        Commands::Extract {
+            input,
+            output,
+            format,
+            rotation,
+            mirror,
+            disassemble: target,
+        } => {
+            let options = SchematicOptions {
+                rotation: *rotation,
+                mirror: *mirror,
+                ..Default::default()
+            };
+            let bytes = format.read_schematic_file(input, &options)?;
+
+            let text = match (target, output) {
+                (Some(target), _) => to_source(&disassemble(target, &bytes))?,
+                (None, Some(output)) => {
+                    let save_options = SaveOptions {
+                        format: Some(*format),
+                        ..Default::default()
+                    };
+                    save_file(output, bytes, &save_options)?;
+                    println!("Output written to: {}", output.display());
+                    return Ok(());
+                }
+                (None, None) => convert_to_mc(bytes)?,
+            };
+
+            match output {
+                Some(output) => {
+                    fs::write(output, text)?;
+                    println!("Output written to: {}", output.display());
+                }
+                None => print!("{}", text),
+            }
+        }
+        Commands::Debug {
+            input,
+            target,
+            defines,
+            include_dirs,
+            max_cycles,
+            io,
+        } => {
+            let options = CompileOptions {
+                defines: defines.iter().cloned().collect(),
+                include_dirs: include_dirs.clone(),
+                debug_artifacts: false,
+            };
+            let program = compile_file(input, target.clone(), &options)?;
+            match target {
+                Backend::BatPU2 => {
+                    let mut debugger = Debugger::new(program, io.batpu2_io()?);
+                    debugger.max_cycles = *max_cycles;
+                    debug(&mut debugger)?;
+                }
+                _ => {
+                    let mut debugger = Debugger::new(program, NullIo);
+                    debugger.max_cycles = *max_cycles;
+                    debug(&mut debugger)?;
+                }
+            }
+        }
+        Commands::Run {
+            input,
+            target,
+            defines,
+            include_dirs,
+            max_cycles,
+            frames,
+            screenshot,
+            frame_dir,
+            scale,
+            profile,
+            top,
+            trace,
+            io,
+        } => {
+            let options = CompileOptions {
+                defines: defines.iter().cloned().collect(),
+                include_dirs: include_dirs.clone(),
+                debug_artifacts: false,
+            };
+            let program = compile_file(input, target.clone(), &options)?;
+            let mut profiler = Profiler::new(target.clone());
+            let mut trace = trace.as_deref().map(TraceFile::create).transpose()?;
+
+            if *target != Backend::BatPU2 {
+                if screenshot.is_some() || frame_dir.is_some() || frames.is_some() {
+                    bail!("{} has no screen", target.to_str());
+                }
+                if io.controller.is_some() {
+                    bail!("{} has no controller", target.to_str());
+                }
+
+                let mut emulator = Emulator::with_io(target.clone(), &program.bytes, NullIo);
+                run(&mut emulator, *max_cycles, |emulator, step| {
+                    profiler.record(step, emulator);
+                    if let Some(trace) = &mut trace {
+                        trace.record(step, emulator)?;
+                    }
+                    Ok(false)
+                })?;
+                for (i, value) in emulator.registers.iter().enumerate() {
+                    println!("r{:<2} {:3} 0x{:02x}", i, value, value);
+                }
+                if *profile {
+                    print_profile(&profiler, &program, *top);
+                }
+                return Ok(());
+            }
+
+            if let Some(dir) = frame_dir {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+            }
+
+            let mut emulator = Emulator::with_io(target.clone(), &program.bytes, io.batpu2_io()?);
+            let mut shown = 0;
+            run(&mut emulator, *max_cycles, |emulator, step| {
+                profiler.record(step, emulator);
+                if let Some(trace) = &mut trace {
+                    trace.record(step, emulator)?;
+                }
+                let io = &emulator.io;
+                if io.frames != shown {
+                    shown = io.frames;
+                    if let Some(dir) = frame_dir {
+                        let path = dir.join(format!("frame-{:04}.png", shown));
+                        fs::write(&path, io.screen.to_png(*scale)?)
+                            .with_context(|| format!("Failed to write {}", path.display()))?;
+                    }
+                }
+                Ok(frames.is_some_and(|frames| io.frames >= frames))
+            })?;
+
+            print!("{}", emulator.io.to_text());
+            println!("{} frames shown", emulator.io.frames);
+            if *profile {
+                print_profile(&profiler, &program, *top);
+            }
+
+            if let Some(path) = screenshot {
+                fs::write(path, emulator.io.screen.to_png(*scale)?)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            }
+        }
+        Commands::Test {
+            paths,
+            target,
+            defines,
+            include_dirs,
+        } => {
+            let options = CompileOptions {
+                defines: defines.iter().cloned().collect(),
+                include_dirs: include_dirs.clone(),
+                debug_artifacts: false,
+            };
+
+            let mut programs = Vec::new();
+            for path in paths {
+                programs.extend(discover(path)?);
+            }
+            programs.sort();
+            programs.dedup();
+
+            let (mut passed, mut failed) = (0, 0);
+            for program in &programs {
+                match test_program(program, target.as_ref(), &options) {
+                    Ok(results) => {
+                        passed += results.iter().filter(|passed| **passed).count();
+                        failed += results.iter().filter(|passed| !**passed).count();
+                    }
+                    Err(err) => {
+                        eprintln!("{}: {}", program.display(), err);
+                        failed += 1;
+                    }
+                }
+            }
+
+            println!("\n{} passed, {} failed", passed, failed);
+            if failed > 0 {
+                bail!("{} test(s) failed", failed);
+            }
+        }
+        Commands::TraceDiff {
+            expected,
+            actual,
+            context,
+        } => {
+            let load = |path: &PathBuf| {
+                load_trace(path).with_context(|| format!("Failed to load {}", path.display()))
+            };
+            let (expected, actual) = (load(expected)?, load(actual)?);
+
+            let Some(divergence) = first_divergence(&expected, &actual) else {
+                println!("Traces match over {} steps", expected.len());
+                return Ok(());
+            };
+
+            println!(
+                "Traces diverge at step {}: {}",
+                divergence.index,
+                divergence.differences.join(", ")
+            );
+            for step in &expected[divergence.index.saturating_sub(*context)..divergence.index] {
+                println!("  {}", step);
+            }
+            if let Some(step) = &divergence.expected {
+                println!("- {}", step);
+            }
+            if let Some(step) = &divergence.actual {
+                println!("+ {}", step);
+            }
+            bail!("Traces diverge");
+        }
+        Commands::CheckGolden {
+            dirs,
+            bless,
+            format,
+            endianness,
+        } => {
+            let options = GoldenOptions {
+                bless: *bless,
+                save: SaveOptions {
+                    format: *format,
+                    endianness: *endianness,
+                    ..Default::default()
+                },
+            };
+
+            let mut failed = 0;
+            for dir in dirs {
+                for result in check_golden(dir, &options)? {
+                    let expected = result.expected.display();
+                    match result.outcome {
+                        Outcome::Matched => println!("ok {}", expected),
+                        Outcome::Blessed => println!("blessed {}", expected),
+                        Outcome::Failed(message) => {
+                            failed += 1;
+                            println!("FAILED {}: {}", expected, message.trim_end());
+                        }
+                    }
+                }
+            }
+
+            if failed > 0 {
+                bail!(
+                    "{} output(s) do not match, rerun with --bless to update them",
+                    failed
+                );
+            }
+        }
+        Commands::Fmt {
+            inputs,
+            check,
+            indent,
+        } => {
+            let options = FormatOptions { indent: *indent };
+            let mut unformatted = 0;
+
+            for input in inputs {
+                if format_file(input, &options, *check)? {
+                    unformatted += 1;
+                    if *check {
+                        println!("Would reformat: {}", input);
+                    } else {
+                        println!("Formatted: {}", input);
+                    }
+                }
+            }
+
+            if *check && unformatted > 0 {
+                bail!("{} file(s) would be reformatted", unformatted);
+            }
+        }
     }
 
     Ok(())
 }
+
+/// Read debugger commands from standard input until `quit` or the end of input.
+///
+/// An empty line repeats the previous command.
+fn debug<I: Io>(debugger: &mut Debugger<I>) -> Result<()> {
+    let interactive = io::stdin().is_terminal();
+    println!("{}", debugger.describe_address(debugger.emulator.pc));
+
+    let mut previous = String::new();
+    let mut lines = io::stdin().lines();
+    loop {
+        if interactive {
+            print!("(smc) ");
+            io::stdout().flush()?;
+        }
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let line = line?;
+        let line = match line.trim() {
+            "" => previous.clone(),
+            line => line.to_string(),
+        };
+        if line.is_empty() {
+            continue;
+        }
+
+        match line.parse::<Command>() {
+            Ok(Command::Quit) => break,
+            Ok(command) => match debugger.execute(command) {
+                Ok(output) => println!("{}", output),
+                Err(error) => println!("{}", error),
+            },
+            Err(error) => println!("{}", error),
+        }
+        previous = line;
+    }
+
+    Ok(())
+}
+
+/// Run the tests of `program`, printing each result, returning whether each passed
+fn test_program(
+    program: &Path,
+    target: Option<&Backend>,
+    options: &CompileOptions,
+) -> Result<Vec<bool>> {
+    let suites = TestSuite::load(program)?;
+    let target = match target {
+        Some(target) => target.clone(),
+        None => {
+            let source = fs::read_to_string(program)
+                .with_context(|| format!("Failed to read {}", program.display()))?;
+            select_backend(program, &source)?
+                .context("No backend for this file, pass one with --target")?
+        }
+    };
+    let compiled = compile_file(program, target, options)?;
+
+    let mut results = Vec::new();
+    for suite in &suites {
+        for result in suite.run(&compiled) {
+            match result.passed() {
+                true => println!(
+                    "test {} {} ... ok ({} cycles)",
+                    program.display(),
+                    result.name,
+                    result.cycles
+                ),
+                false => {
+                    println!("test {} {} ... FAILED", program.display(), result.name);
+                    for failure in &result.failures {
+                        eprint!(
+                            "{}",
+                            failure
+                                .span
+                                .format_error(&suite.path, &suite.text, &failure.message)
+                        );
+                    }
+                }
+            }
+            results.push(result.passed());
+        }
+    }
+
+    Ok(results)
+}
+
+/// Run until the program halts, `max_cycles` is reached or `stop` returns true after a step
+fn run<I: Io>(
+    emulator: &mut Emulator<I>,
+    max_cycles: u64,
+    mut stop: impl FnMut(&Emulator<I>, &Step) -> Result<bool>,
+) -> Result<()> {
+    while !emulator.halted() && emulator.cycles < max_cycles {
+        let step = emulator.step()?;
+        if stop(emulator, &step)? {
+            break;
+        }
+    }
+
+    match emulator.halted() {
+        true => eprintln!("Halted after {} cycles", emulator.cycles),
+        false => eprintln!("Stopped after {} cycles", emulator.cycles),
+    }
+    Ok(())
+}
+
+/// A trace being written by the run command
+struct TraceFile {
+    file: io::BufWriter<fs::File>,
+    format: TraceFormat,
+    tracer: Tracer,
+}
+
+impl TraceFile {
+    fn create(path: &Path) -> Result<Self> {
+        let file = fs::File::create(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(TraceFile {
+            file: io::BufWriter::new(file),
+            format: TraceFormat::from_path(path),
+            tracer: Tracer::default(),
+        })
+    }
+
+    fn record<I>(&mut self, step: &Step, emulator: &Emulator<I>) -> Result<()> {
+        let step = self.tracer.record(step, emulator);
+        match self.format {
+            TraceFormat::Text => writeln!(self.file, "{}", step)?,
+            TraceFormat::Json => writeln!(self.file, "{}", step.to_json())?,
+        }
+        Ok(())
+    }
+}
+
+/// Print the profile of a run with the worst case of every subroutine without loops
+fn print_profile(profiler: &Profiler, program: &CompiledProgram, top: usize) {
+    println!("\n{}", profiler.profile(program).to_text(top));
+
+    let estimates = worst_case_cycles(program);
+    if !estimates.is_empty() {
+        println!("Worst case cycles of subroutines");
+        for estimate in estimates {
+            match estimate.cycles {
+                Some(cycles) => println!("  {:>10}  {}", cycles, estimate.name),
+                None => println!("  {:>10}  {}", "loops", estimate.name),
+            }
+        }
+    }
+}
+
+/// Build the targets of the manifest at `path`, returning the files read even if it fails
+fn build(path: &Path, names: &[String], defines: &DefineMap) -> (Vec<PathBuf>, Result<()>) {
+    let mut files = vec![path.to_path_buf()];
+    let result = build_targets(path, names, defines, &mut files);
+    (files, result)
+}
+
+fn build_targets(
+    path: &Path,
+    names: &[String],
+    defines: &DefineMap,
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let manifest =
+        Manifest::load(path).with_context(|| format!("Failed to load {}", path.display()))?;
+    let root = path.parent().unwrap_or(Path::new(""));
+
+    if manifest.targets.is_empty() {
+        bail!("{} has no [[targets]]", path.display());
+    }
+
+    for name in names {
+        if !manifest.targets.iter().any(|target| target.name() == *name) {
+            bail!("No target named `{}` in {}", name, path.display());
+        }
+    }
+
+    // Keep going after a failure so every broken target is reported at once
+    let mut failed = Vec::new();
+    for target in &manifest.targets {
+        let name = target.name();
+        if !names.is_empty() && !names.contains(&name) {
+            continue;
+        }
+
+        println!("Building {}", name);
+        if let Err(err) = target.build(root, defines) {
+            eprintln!("Failed to build {}: {}\n", name, err);
+            failed.push(name);
+        }
+        files.extend(target.dependencies(root));
+    }
+
+    if !failed.is_empty() {
+        bail!("{} target(s) failed: {}", failed.len(), failed.join(", "));
+    }
+
+    Ok(())
+}
+
+/// Parse `NAME=VALUE` into a define, `NAME` on its own defines it as 1
+fn parse_define(arg: &str) -> Result<(String, f64), String> {
+    let (name, value) = arg.split_once('=').unwrap_or((arg, "1"));
+
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("invalid define name `{}`", name));
+    }
+
+    let tokens: Vec<_> = Lexer::new(value.trim()).collect();
+    match tokens.as_slice() {
+        [Ok(number), Ok(eof)] if eof.token == Token::Eof => match number.token.as_number() {
+            Some(value) => Ok((name.to_string(), value)),
+            None => Err(format!("invalid define value `{}`", value)),
+        },
+        _ => Err(format!("invalid define value `{}`", value)),
+    }
+}
+
+/// Parse `X,Y,Z` into a block position
+fn parse_position(arg: &str) -> Result<[i32; 3], String> {
+    let coordinates: Vec<i32> = arg
+        .split(',')
+        .map(|coordinate| coordinate.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid position `{}`", arg))?;
+
+    coordinates
+        .try_into()
+        .map_err(|_| format!("expected 3 coordinates in `{}`", arg))
+}
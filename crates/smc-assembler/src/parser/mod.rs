@@ -38,6 +38,23 @@ pub enum ParserError {
 
     #[error("Semantic Error: Invalid skip `{1}`")]
     InvalidSkip(Span, String),
+
+    #[error("Semantic Error: Cannot include `{1}` outside of a source file")]
+    UnresolvedInclude(Span, String),
+}
+
+impl ParserError {
+    pub fn span(&self) -> &Span {
+        match self {
+            ParserError::SyntaxError(lexer_error) => lexer_error.span(),
+            ParserError::DuplicateDefine(span, _)
+            | ParserError::DuplicateLabel(span, _)
+            | ParserError::ExpectedButReceived(span, _, _)
+            | ParserError::UnexpectedEof(span)
+            | ParserError::InvalidSkip(span, _)
+            | ParserError::UnresolvedInclude(span, _) => span,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -90,6 +107,79 @@ enum InstructionFormat {
     Skip,
 }
 
+impl InstructionFormat {
+    /// The widest form of the instruction format accepted by the parser for an operation
+    fn widest(op: &Operation) -> Self {
+        use InstructionFormat::*;
+
+        match op {
+            Operation::Nop | Operation::Hlt | Operation::Ret | Operation::Bkl | Operation::Bkr => {
+                NoOperand
+            }
+            Operation::Inv | Operation::Inc | Operation::Dec | Operation::Clr => Reg1,
+            Operation::Rsh
+            | Operation::Cmp
+            | Operation::Mov
+            | Operation::Lsh
+            | Operation::Not
+            | Operation::Neg
+            | Operation::Or
+            | Operation::Cpy
+            | Operation::Adc
+            | Operation::Mld
+            | Operation::Mst
+            | Operation::Pld
+            | Operation::Pst => Reg2,
+            Operation::Add | Operation::Sub | Operation::Nor | Operation::And | Operation::Xor => {
+                Reg3
+            }
+            Operation::Ldi | Operation::Adi | Operation::Cpi | Operation::Ani => RegImm,
+            Operation::Jmp | Operation::Cal => Addr,
+            Operation::Brh => CondAddr,
+            Operation::Skp => Skip,
+            Operation::Lod | Operation::Str => Reg2Offset,
+        }
+    }
+
+    fn operand_kinds(self) -> &'static [OperandKind] {
+        use OperandKind::*;
+
+        match self {
+            InstructionFormat::NoOperand => &[],
+            InstructionFormat::Reg1 => &[Register],
+            InstructionFormat::Reg2 => &[Register, Register],
+            InstructionFormat::Reg3 => &[Register, Register, Register],
+            InstructionFormat::RegImm => &[Register, Immediate],
+            InstructionFormat::Addr => &[Address],
+            InstructionFormat::CondAddr => &[Condition, Address],
+            InstructionFormat::Reg2Offset => &[Register, Register, Offset],
+            InstructionFormat::Skip => &[Skip],
+        }
+    }
+}
+
+/// The kind of value accepted in an operand slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandKind {
+    /// A register such as `r1`
+    Register,
+    /// A number or define
+    Immediate,
+    /// A number, label, or define
+    Address,
+    /// A branch condition such as `eq`
+    Condition,
+    /// An optional memory offset (number or define)
+    Offset,
+    /// An optional skip flag such as `!0`
+    Skip,
+}
+
+/// Operand slots of the widest form of an operation, e.g. `ADD r1 r2 r3`
+pub fn operand_kinds(op: &Operation) -> &'static [OperandKind] {
+    InstructionFormat::widest(op).operand_kinds()
+}
+
 impl Parser {
     pub fn new(tokens: Vec<Result<TokenSpan, LexerError>>) -> Self {
         Parser {
@@ -119,7 +209,7 @@ impl Parser {
     }
 
     fn make_span(&self, start: &Span) -> Span {
-        Span::new(start.start(), self.last_span.end())
+        Span::new(start.start(), self.last_span.end()).in_source(start.source())
     }
 
     fn enter_recovery(&mut self, error: ParserError, errors: &mut Vec<ParserError>) {
@@ -133,6 +223,7 @@ impl Parser {
             Ok(TokenSpan {
                 token: Token::Keyword(Keyword::Operation(_))
                     | Token::Keyword(Keyword::Define)
+                    | Token::Keyword(Keyword::Include)
                     | Token::Label(_)
                     | Token::Eof,
                 ..
@@ -181,10 +272,7 @@ impl Parser {
     }
 
     fn expect_number(&mut self, offset: usize) -> Result<f64, ParserError> {
-        self.expect(offset, "number", |token| match token {
-            Token::Number(n) => Some(*n),
-            _ => None,
-        })
+        self.expect(offset, "number", Token::as_number)
     }
 
     fn expect_identifier(&mut self, offset: usize) -> Result<String, ParserError> {
@@ -194,6 +282,13 @@ impl Parser {
         })
     }
 
+    fn expect_string(&mut self, offset: usize) -> Result<String, ParserError> {
+        self.expect(offset, "string", |token| match token {
+            Token::String(s) => Some(s.clone()),
+            _ => None,
+        })
+    }
+
     fn expect_condition(&mut self, offset: usize) -> Result<Condition, ParserError> {
         self.expect(offset, "condition (eq, ne, ge, lt)", |token| match token {
             Token::Keyword(Keyword::Condition(c)) => Some(c.clone()),
@@ -206,10 +301,9 @@ impl Parser {
             offset,
             "address (number, label, or define)",
             |token| match token {
-                Token::Number(n) => Some(Address::Value(*n as i128)),
                 Token::Label(l) => Some(Address::Label(l.clone())),
                 Token::Identifier(id) => Some(Address::Define(id.clone())),
-                _ => None,
+                token => token.as_number().map(|n| Address::Value(n as i128)),
             },
         )?;
 
@@ -217,13 +311,13 @@ impl Parser {
             Address::Label(l) => {
                 self.label_references
                     .entry(l.clone())
-                    .or_insert_with(Vec::new)
+                    .or_default()
                     .push(span);
             }
             Address::Define(id) => {
                 self.define_references
                     .entry(id.clone())
-                    .or_insert_with(Vec::new)
+                    .or_default()
                     .push(span);
             }
             Address::Value(_) => {}
@@ -237,9 +331,8 @@ impl Parser {
             offset,
             "immediate (number or define)",
             |token| match token {
-                Token::Number(n) => Some(Immediate::Value(*n as i128)),
                 Token::Identifier(id) => Some(Immediate::Define(id.clone())),
-                _ => None,
+                token => token.as_number().map(|n| Immediate::Value(n as i128)),
             },
         )?;
 
@@ -247,7 +340,7 @@ impl Parser {
         if let Immediate::Define(ref id) = immediate {
             self.define_references
                 .entry(id.clone())
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(span);
         }
 
@@ -277,17 +370,16 @@ impl Parser {
 
     fn try_offset(&mut self, offset: usize) -> Result<Option<Offset>, ParserError> {
         match self.peek(offset) {
-            Ok(TokenSpan {
-                token: Token::Number(n),
-                ..
-            }) => Ok(Some(Offset::Value(n as i128))),
+            Ok(TokenSpan { token, .. }) if token.as_number().is_some() => {
+                Ok(token.as_number().map(|n| Offset::Value(n as i128)))
+            }
             Ok(TokenSpan {
                 token: Token::Identifier(id),
                 span,
             }) => {
                 self.define_references
                     .entry(id.clone())
-                    .or_insert_with(Vec::new)
+                    .or_default()
                     .push(span);
                 Ok(Some(Offset::Define(id)))
             }
@@ -379,12 +471,14 @@ impl Parser {
                 Ok(ParsedOperands::Reg2Offset(r1, r2, offset))
             }
             InstructionFormat::Skip => {
-                let skip = self.try_skip(0)?;
-                let skip = match skip {
-                    Some(skip) => skip,
-                    None => SkipFlag::Never,
+                // A bare `SKP` always skips, and leaves the next token alone
+                let skip = match self.try_skip(0)? {
+                    Some(skip) => {
+                        self.advance()?;
+                        skip
+                    }
+                    None => SkipFlag::Always,
                 };
-                self.advance()?;
                 Ok(ParsedOperands::Skip(skip))
             }
         }
@@ -445,6 +539,15 @@ impl Parser {
                     Err(e) => self.enter_recovery(e, &mut errors),
                 },
 
+                // Includes are spliced in by `Sources::load`, any left over came from a bare string
+                Ok(TokenSpan {
+                    token: Token::Keyword(Keyword::Include),
+                    span,
+                }) => match self.parse_include(&span) {
+                    Ok((path, span)) => errors.push(ParserError::UnresolvedInclude(span, path)),
+                    Err(e) => self.enter_recovery(e, &mut errors),
+                },
+
                 Ok(TokenSpan {
                     token: Token::Keyword(Keyword::Operation(op)),
                     span,
@@ -495,6 +598,12 @@ impl Parser {
         Ok((name, value, name_span))
     }
 
+    fn parse_include(&mut self, start_span: &Span) -> Result<(String, Span), ParserError> {
+        let path = self.expect_string(0)?;
+        self.advance()?;
+        Ok((path, self.make_span(start_span)))
+    }
+
     fn parse_operation(
         &mut self,
         op: Operation,
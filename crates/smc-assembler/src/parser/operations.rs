@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::lexer::token::{Condition, Register, Span};
 
 #[derive(Debug, PartialEq, Clone)]
@@ -98,3 +100,120 @@ impl SpannedOperation {
         Self { op, span }
     }
 }
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "r{}", self.0)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Condition::Equal => "eq",
+            Condition::NotEqual => "ne",
+            Condition::GreaterEqual => "ge",
+            Condition::Less => "lt",
+            Condition::Not => "!",
+            Condition::NotZero => "!0",
+            Condition::Negative => "-",
+            Condition::NotNegative => "!-",
+        })
+    }
+}
+
+impl fmt::Display for Immediate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Immediate::Value(value) => write!(f, "{}", value),
+            Immediate::Define(name) => f.write_str(name),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Value(value) => write!(f, "{}", value),
+            Address::Define(name) => f.write_str(name),
+            Address::Label(name) => write!(f, ".{}", name),
+        }
+    }
+}
+
+impl fmt::Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Offset::Value(value) => write!(f, "{}", value),
+            Offset::Define(name) => f.write_str(name),
+        }
+    }
+}
+
+impl fmt::Display for SkipFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SkipFlag::Never => "!",
+            SkipFlag::IfZero => "0",
+            SkipFlag::IfNotZero => "!0",
+            SkipFlag::IfNegative => "-",
+            SkipFlag::IfNotNegative => "!-",
+            SkipFlag::Always => "",
+        })
+    }
+}
+
+/// Writes the operation back as source, e.g. `ADD r1 r2 r3`
+impl fmt::Display for OperationWithArgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use OperationWithArgs::*;
+
+        match self {
+            Add3(a, b, c) => write!(f, "ADD {} {} {}", a, b, c),
+            Add2(a, b) => write!(f, "ADD {} {}", a, b),
+            Sub3(a, b, c) => write!(f, "SUB {} {} {}", a, b, c),
+            Sub2(a, b) => write!(f, "SUB {} {}", a, b),
+            Nor3(a, b, c) => write!(f, "NOR {} {} {}", a, b, c),
+            And3(a, b, c) => write!(f, "AND {} {} {}", a, b, c),
+            And2(a, b) => write!(f, "AND {} {}", a, b),
+            Xor3(a, b, c) => write!(f, "XOR {} {} {}", a, b, c),
+            Xor2(a, b) => write!(f, "XOR {} {}", a, b),
+            Or2(a, b) => write!(f, "OR {} {}", a, b),
+            Cmp2(a, b) => write!(f, "CMP {} {}", a, b),
+            Cpy2(a, b) => write!(f, "CPY {} {}", a, b),
+            Adc2(a, b) => write!(f, "ADC {} {}", a, b),
+            Mld2(a, b) => write!(f, "MLD {} {}", a, b),
+            Mst2(a, b) => write!(f, "MST {} {}", a, b),
+            Pld2(a, b) => write!(f, "PLD {} {}", a, b),
+            Pst2(a, b) => write!(f, "PST {} {}", a, b),
+            Mov2(a, b) => write!(f, "MOV {} {}", a, b),
+            Lsh2(a, b) => write!(f, "LSH {} {}", a, b),
+            Not2(a, b) => write!(f, "NOT {} {}", a, b),
+            Neg2(a, b) => write!(f, "NEG {} {}", a, b),
+            Rsh2(a, b) => write!(f, "RSH {} {}", a, b),
+            Rsh1(a) => write!(f, "RSH {}", a),
+            Inv1(a) => write!(f, "INV {}", a),
+            Inc1(a) => write!(f, "INC {}", a),
+            Dec1(a) => write!(f, "DEC {}", a),
+            Clr1(a) => write!(f, "CLR {}", a),
+            Ldi2(a, imm) => write!(f, "LDI {} {}", a, imm),
+            Adi2(a, imm) => write!(f, "ADI {} {}", a, imm),
+            Cpi2(a, imm) => write!(f, "CPI {} {}", a, imm),
+            Ani2(a, imm) => write!(f, "ANI {} {}", a, imm),
+            Jmp(addr) => write!(f, "JMP {}", addr),
+            Cal(addr) => write!(f, "CAL {}", addr),
+            Nop => f.write_str("NOP"),
+            Hlt => f.write_str("HLT"),
+            Bkl => f.write_str("BKL"),
+            Bkr => f.write_str("BKR"),
+            Ret => f.write_str("RET"),
+            Skp(SkipFlag::Always) => f.write_str("SKP"),
+            Skp(flag) => write!(f, "SKP {}", flag),
+            Brh(cond, addr) => write!(f, "BRH {} {}", cond, addr),
+            Lod(a, b, Some(offset)) => write!(f, "LOD {} {} {}", a, b, offset),
+            Lod(a, b, None) => write!(f, "LOD {} {}", a, b),
+            Str(a, b, Some(offset)) => write!(f, "STR {} {} {}", a, b, offset),
+            Str(a, b, None) => write!(f, "STR {} {}", a, b),
+        }
+    }
+}
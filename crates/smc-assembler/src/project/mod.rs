@@ -0,0 +1,266 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use globset::Glob;
+use serde::Deserialize;
+
+use crate::{
+    CompileError, CompileOptions,
+    assembler::backends::Backend,
+    compile_file,
+    lexer::token::Span,
+    parser::DefineMap,
+    save::{
+        Endianness, SaveOptions, load_program, memory::Format, save_program_if_changed,
+        schematic::SchematicOptions,
+    },
+    sources::Sources,
+};
+
+/// File name of the project manifest, looked up from a source file's directory upwards
+pub const MANIFEST_FILE_NAME: &str = "smc.toml";
+
+/// Marker of the in-file target pragma, e.g. `// smc-target: tau-analyzers-none`
+pub const TARGET_PRAGMA: &str = "smc-target:";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Backend selection for files matching a glob, the first matching rule wins
+    #[serde(default)]
+    pub backends: Vec<BackendRule>,
+    /// Programs compiled by `smc-assembler build`
+    #[serde(default)]
+    pub targets: Vec<BuildTarget>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackendRule {
+    /// Glob matched against the path relative to the project root, e.g. `tau/**/*.tasm`
+    pub files: String,
+    /// Backend name as accepted by `--target`
+    pub target: String,
+}
+
+impl BackendRule {
+    /// Whether `path` matches this rule, with the glob taken relative to `root`
+    pub fn matches(&self, root: &Path, path: &Path) -> Result<bool, CompileError> {
+        let glob = Glob::new(&self.files)
+            .map_err(|err| CompileError::InvalidGlob(self.files.clone(), err))?
+            .compile_matcher();
+
+        Ok(glob.is_match(path.strip_prefix(root).unwrap_or(path)))
+    }
+}
+
+/// A program built from the manifest, with paths relative to the manifest directory
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildTarget {
+    /// Name to select the target by on the command line, defaults to the entry file stem
+    pub name: Option<String>,
+    pub entry: PathBuf,
+    /// Backend name as accepted by `--target`
+    pub backend: String,
+    /// Every output is written from the same compilation, its extension picks the file type
+    pub outputs: Vec<PathBuf>,
+    /// Memory format for schematic outputs, as accepted by `--format`
+    pub format: Option<String>,
+    /// Byte order of instruction words in `.bin` outputs
+    #[serde(default)]
+    pub endianness: Endianness,
+    /// Placement and metadata of schematic outputs
+    #[serde(default)]
+    pub schematic: SchematicOptions,
+    /// Program already built in the world, as for `--diff-from`
+    pub diff_from: Option<PathBuf>,
+    #[serde(default)]
+    pub defines: DefineMap,
+    /// Directories searched for included files
+    #[serde(default)]
+    pub include: Vec<PathBuf>,
+}
+
+impl BuildTarget {
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            self.entry
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+
+    pub fn backend(&self) -> Result<Backend, CompileError> {
+        Backend::from_name(&self.backend)
+            .ok_or_else(|| CompileError::UnknownBackend(self.backend.clone()))
+    }
+
+    pub fn format(&self) -> Result<Option<Format>, CompileError> {
+        self.format
+            .as_deref()
+            .map(|format| {
+                Format::from_str(format, true)
+                    .map_err(|_| CompileError::UnknownFormat(format.to_string()))
+            })
+            .transpose()
+    }
+
+    pub fn compile_options(&self, root: &Path, defines: &DefineMap) -> CompileOptions {
+        let mut options = CompileOptions {
+            defines: self.defines.clone(),
+            include_dirs: self.include.iter().map(|dir| root.join(dir)).collect(),
+            debug_artifacts: false,
+        };
+        options.defines.extend(defines.clone());
+        options
+    }
+
+    /// Compile the target and write those of its outputs that changed.
+    ///
+    /// `defines` are applied on top of the target's own, e.g. from the command line.
+    pub fn build(&self, root: &Path, defines: &DefineMap) -> Result<(), CompileError> {
+        let backend = self.backend()?;
+        let mut save_options = SaveOptions {
+            format: self.format()?,
+            endianness: self.endianness,
+            schematic: self.schematic.clone(),
+            previous: None,
+        };
+        if let Some(previous) = &self.diff_from {
+            save_options.previous = Some(load_program(root.join(previous), &save_options)?);
+        }
+        let options = self.compile_options(root, defines);
+
+        let program = compile_file(root.join(&self.entry), backend, &options)?;
+        println!("{}", program.size_report());
+
+        for output in &self.outputs {
+            let output = root.join(output);
+            if let Some(parent) = output.parent() {
+                fs::create_dir_all(parent).map_err(CompileError::WriteFileError)?;
+            }
+
+            if save_program_if_changed(&output, &program, &save_options)? {
+                println!("Output written to: {}", output.display());
+            } else {
+                println!("Unchanged: {}", output.display());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The entry file and every file it includes
+    pub fn dependencies(&self, root: &Path) -> Vec<PathBuf> {
+        let include_dirs: Vec<_> = self.include.iter().map(|dir| root.join(dir)).collect();
+        Sources::dependencies(root.join(&self.entry), &include_dirs)
+    }
+}
+
+impl Manifest {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CompileError> {
+        let source = fs::read_to_string(path).map_err(CompileError::ReadFileError)?;
+        toml::from_str(&source).map_err(CompileError::ManifestParseError)
+    }
+
+    /// Find the closest manifest in `start` or any of its parent directories
+    pub fn find<P: AsRef<Path>>(start: P) -> Option<PathBuf> {
+        start
+            .as_ref()
+            .ancestors()
+            .map(|dir| dir.join(MANIFEST_FILE_NAME))
+            .find(|path| path.is_file())
+    }
+
+    /// The include directories of every target, joined onto the manifest directory `root`
+    pub fn include_dirs(&self, root: &Path) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for dir in self.targets.iter().flat_map(|target| &target.include) {
+            let dir = root.join(dir);
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+        dirs
+    }
+
+    /// The first backend rule matching `path`, relative to the manifest directory `root`
+    pub fn backend_rule(
+        &self,
+        root: &Path,
+        path: &Path,
+    ) -> Result<Option<&BackendRule>, CompileError> {
+        first_matching_rule(&self.backends, root, path)
+    }
+}
+
+/// The first rule in `rules` matching `path`, with globs taken relative to `root`
+pub fn first_matching_rule<'a>(
+    rules: &'a [BackendRule],
+    root: &Path,
+    path: &Path,
+) -> Result<Option<&'a BackendRule>, CompileError> {
+    for rule in rules {
+        if rule.matches(root, path)? {
+            return Ok(Some(rule));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Backend of the source file at `path`, picked by an in-file pragma, the `backends` rules of
+/// the closest manifest or the file extension, in that order
+pub fn select_backend(path: &Path, source: &str) -> Result<Option<Backend>, CompileError> {
+    let by_name = |name: &str| {
+        Backend::from_name(name).ok_or_else(|| CompileError::UnknownBackend(name.to_string()))
+    };
+
+    if let Some((name, _)) = target_pragma(source) {
+        return by_name(name).map(Some);
+    }
+
+    if let Some(manifest_path) = path.parent().and_then(Manifest::find) {
+        let manifest = Manifest::load(&manifest_path)?;
+        let root = manifest_path.parent().unwrap_or(Path::new(""));
+        if let Some(rule) = manifest.backend_rule(root, path)? {
+            return by_name(&rule.target).map(Some);
+        }
+    }
+
+    Ok(match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("tasm") => Some(Backend::TauAnalyzersNone),
+        Some(ext) if ext.eq_ignore_ascii_case("smc") => Some(Backend::BatPU2),
+        _ => None,
+    })
+}
+
+/// Find a `smc-target: <backend>` pragma inside a comment, returning the backend name and its span
+pub fn target_pragma(source: &str) -> Option<(&str, Span)> {
+    let mut line_start = 0;
+    for line in source.split_inclusive('\n') {
+        if let Some(index) = line.find(TARGET_PRAGMA) {
+            let before = &line[..index];
+            if before.contains("//") || before.contains('#') || before.contains("/*") {
+                let rest = &line[index + TARGET_PRAGMA.len()..];
+                let name_start = rest.len() - rest.trim_start().len();
+                let name = rest[name_start..]
+                    .split(|c: char| c.is_whitespace() || c == '*')
+                    .next()
+                    .unwrap_or_default();
+
+                let start = line_start + index + TARGET_PRAGMA.len() + name_start;
+                return Some((name, Span::new(start, start + name.len())));
+            }
+        }
+        line_start += line.len();
+    }
+
+    None
+}
@@ -1,5 +1,13 @@
 use std::fmt::Write as _;
 
+use crate::{
+    CompiledProgram,
+    save::{
+        Endianness,
+        schematic::{Placement, SchematicOptions},
+    },
+};
+
 pub fn convert_to_mc(input: Vec<u8>) -> Result<String, std::fmt::Error> {
     let bytes = input.chunks(2);
 
@@ -19,3 +27,181 @@ pub fn convert_to_tau(input: Vec<u8>) -> Result<String, std::fmt::Error> {
     }
     Ok(output)
 }
+
+/// Group bytes into big-endian words of `word_size` bytes, padding the last word with zeros
+fn words(input: &[u8], word_size: usize) -> impl Iterator<Item = u64> + '_ {
+    input.chunks(word_size).map(move |chunk| {
+        (0..word_size).fold(0, |word, i| {
+            (word << 8) | u64::from(chunk.get(i).copied().unwrap_or(0))
+        })
+    })
+}
+
+/// Raw bytes, with the bytes of each word swapped for little-endian
+pub fn convert_to_bin(input: &[u8], word_size: usize, endianness: Endianness) -> Vec<u8> {
+    match endianness {
+        Endianness::Big => input.to_vec(),
+        Endianness::Little => input
+            .chunks(word_size)
+            .flat_map(|chunk| {
+                let mut word = chunk.to_vec();
+                word.resize(word_size, 0);
+                word.into_iter().rev()
+            })
+            .collect(),
+    }
+}
+
+/// Intel HEX with 16 byte data records, addressed in bytes
+pub fn convert_to_intel_hex(input: &[u8]) -> Result<String, std::fmt::Error> {
+    fn record(output: &mut String, kind: u8, address: u16, data: &[u8]) -> std::fmt::Result {
+        let [high, low] = address.to_be_bytes();
+        let sum = [data.len() as u8, high, low, kind]
+            .iter()
+            .chain(data)
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        write!(output, ":{:02X}{:04X}{:02X}", data.len(), address, kind)?;
+        for byte in data {
+            write!(output, "{:02X}", byte)?;
+        }
+        writeln!(output, "{:02X}", sum.wrapping_neg())
+    }
+
+    let mut output = String::new();
+    for (i, chunk) in input.chunks(16).enumerate() {
+        let address = i * 16;
+        // Extended linear address record when crossing into the next 64 KiB
+        if address > 0 && address % 0x10000 == 0 {
+            record(&mut output, 4, 0, &((address >> 16) as u16).to_be_bytes())?;
+        }
+        record(&mut output, 0, address as u16, chunk)?;
+    }
+    record(&mut output, 1, 0, &[])?;
+
+    Ok(output)
+}
+
+/// Logisim-evolution `v2.0 raw` memory image, one word per address
+pub fn convert_to_logisim(input: &[u8], word_size: usize) -> Result<String, std::fmt::Error> {
+    let words: Vec<_> = words(input, word_size).collect();
+
+    let mut output = String::from("v2.0 raw\n");
+    for line in words.chunks(8) {
+        let line: Vec<_> = line
+            .iter()
+            .map(|word| format!("{:0width$x}", word, width = word_size * 2))
+            .collect();
+        writeln!(output, "{}", line.join(" "))?;
+    }
+    Ok(output)
+}
+
+/// Verilog `$readmemb` memory file, one binary word per line
+pub fn convert_to_readmemb(input: &[u8], word_size: usize) -> Result<String, std::fmt::Error> {
+    let mut output = String::new();
+    for word in words(input, word_size) {
+        writeln!(output, "{:0width$b}", word, width = word_size * 8)?;
+    }
+    Ok(output)
+}
+
+/// Verilog `$readmemh` memory file, one hex word per line
+pub fn convert_to_readmemh(input: &[u8], word_size: usize) -> Result<String, std::fmt::Error> {
+    let mut output = String::new();
+    for word in words(input, word_size) {
+        writeln!(output, "{:0width$x}", word, width = word_size * 2)?;
+    }
+    Ok(output)
+}
+
+/// A listing with the address, encoding and source location of every instruction, followed by
+/// the instruction encoded for pseudo-instructions
+pub fn convert_to_listing(program: &CompiledProgram) -> Result<String, std::fmt::Error> {
+    let encoding = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let source_map = program.source_map();
+    let locations: Vec<_> = source_map
+        .entries
+        .iter()
+        .map(|entry| match program.sources.get(entry.file) {
+            Some(file) => {
+                let name = file.path.file_name().unwrap_or(file.path.as_os_str());
+                let code = file.text[entry.start..entry.end.min(file.text.len())]
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let code = match &entry.expanded {
+                    Some(expanded) => format!("{}  ; {}", code, expanded),
+                    None => code,
+                };
+                (format!("{}:{}", name.to_string_lossy(), entry.line), code)
+            }
+            None => (String::new(), String::new()),
+        })
+        .collect();
+
+    let encoding_width = program
+        .instructions
+        .iter()
+        .map(|instruction| encoding(&instruction.bytes).len())
+        .max()
+        .unwrap_or(0);
+    let location_width = locations
+        .iter()
+        .map(|(location, _)| location.len())
+        .max()
+        .unwrap_or(0);
+
+    let mut labels: Vec<_> = program.labels.iter().collect();
+    labels.sort_by_key(|(name, address)| (**address, name.as_str()));
+    let mut labels = labels.into_iter().peekable();
+
+    let mut output = String::new();
+    writeln!(output, "; {}", program.target.to_str())?;
+
+    for (instruction, (location, code)) in program.instructions.iter().zip(&locations) {
+        while let Some((name, _)) = labels.next_if(|(_, address)| **address <= instruction.address)
+        {
+            writeln!(output, "{:6}.{}", "", name)?;
+        }
+
+        writeln!(
+            output,
+            "{:04x}  {:encoding_width$}  {:location_width$}  {}",
+            instruction.address,
+            encoding(&instruction.bytes),
+            location,
+            code,
+        )?;
+    }
+
+    for (name, _) in labels {
+        writeln!(output, "{:6}.{}", "", name)?;
+    }
+
+    Ok(output)
+}
+
+/// Write a `setblock` command for every block, at the origin of `options` or relative to the
+/// position the function runs at
+pub fn convert_to_mcfunction(
+    blocks: Vec<Placement>,
+    options: &SchematicOptions,
+) -> Result<String, std::fmt::Error> {
+    let mut output = String::new();
+    for block in blocks {
+        let (pos, block) = options.transform(block);
+        let [x, y, z] = match options.origin {
+            Some(origin) => [0, 1, 2].map(|axis| (origin[axis] + pos[axis]).to_string()),
+            None => pos.map(|coordinate| format!("~{}", coordinate)),
+        };
+        writeln!(output, "setblock {} {} {} {}", x, y, z, block.full_id())?;
+    }
+    Ok(output)
+}
@@ -0,0 +1,78 @@
+use std::{fs::File, io::Write, path::Path};
+
+use serde_json::json;
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    CompileError,
+    save::{
+        convert::convert_to_mcfunction,
+        schematic::{Placement, SchematicOptions},
+    },
+};
+
+/// Pack format of Minecraft 1.21, the first to read functions from `function` rather than
+/// `functions`
+const PACK_FORMAT: u32 = 48;
+
+/// Write a datapack placing `blocks` with the function `<namespace>:place`, the namespace being
+/// the file name of `output`
+pub fn save_datapack(
+    output: &Path,
+    blocks: Vec<Placement>,
+    options: &SchematicOptions,
+) -> Result<(), CompileError> {
+    let function = convert_to_mcfunction(blocks, options).map_err(CompileError::FormatError)?;
+    let namespace = namespace(output);
+    let description = options
+        .description
+        .clone()
+        .unwrap_or_else(|| format!("Places {} with /function {}:place", namespace, namespace));
+    let mcmeta = json!({
+        "pack": {
+            "pack_format": PACK_FORMAT,
+            "description": description,
+        }
+    });
+    let mcmeta =
+        serde_json::to_string_pretty(&mcmeta).expect("pack metadata always serializes") + "\n";
+
+    let file = File::create(output).map_err(CompileError::WriteFileError)?;
+    let mut zip = ZipWriter::new(file);
+    for (path, content) in [
+        ("pack.mcmeta".to_string(), mcmeta),
+        (
+            format!("data/{}/function/place.mcfunction", namespace),
+            function,
+        ),
+    ] {
+        zip.start_file(path, SimpleFileOptions::default())
+            .map_err(CompileError::DatapackWriteFailed)?;
+        zip.write_all(content.as_bytes())
+            .map_err(CompileError::WriteFileError)?;
+    }
+    zip.finish().map_err(CompileError::DatapackWriteFailed)?;
+
+    Ok(())
+}
+
+/// The file name of `output`, limited to the characters allowed in a namespace
+fn namespace(output: &Path) -> String {
+    let name: String = output
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '_' | '-' | '.' => c,
+            _ => '_',
+        })
+        .collect();
+
+    if name.is_empty() {
+        env!("CARGO_PKG_NAME").replace('-', "_")
+    } else {
+        name
+    }
+}
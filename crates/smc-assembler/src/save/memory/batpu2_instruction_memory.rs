@@ -1,8 +1,11 @@
-use mc_schem::{Region, Schematic};
+use mc_schem::Schematic;
 
 use crate::{
     CompileError,
-    save::{convert::convert_to_mc, memory::make_block},
+    save::{
+        memory::{BitCell, make_bit_blocks, read_bit_blocks},
+        schematic::{Placement, SchematicOptions},
+    },
 };
 
 fn generate_instruction_positions() -> Vec<[i32; 3]> {
@@ -38,73 +41,51 @@ fn generate_instruction_positions() -> Vec<[i32; 3]> {
     pos_list
 }
 
-fn write_instructions(
-    region: &mut Region,
-    pos_list: &[[i32; 3]],
-    lines: &[&str],
-    offset: [i32; 3],
-) {
-    for (address, line) in lines.iter().enumerate() {
-        let face = if address < 512 { "east" } else { "west" };
-        let mut new_pos = pos_list[address];
-
-        let byte1 = &line[8..]; // Lower byte
-        let byte2 = &line[..8]; // Upper byte
-
-        // Write lower byte
-        for char in byte1.chars() {
-            let block = if char == '1' {
-                make_block(&format!("minecraft:repeater[facing={}]", face))
-            } else {
-                make_block("minecraft:purple_wool")
-            };
-            let pos = [
-                (new_pos[0] - offset[0]),
-                (new_pos[1] - offset[1]),
-                (new_pos[2] - offset[2]),
-            ];
-            let _ = region.set_block(pos, &block);
-            new_pos[1] -= 2;
-        }
-
-        new_pos[1] -= 2;
-
-        // Write upper byte
-        for char in byte2.chars() {
-            let block = if char == '1' {
-                make_block(&format!("minecraft:repeater[facing={}]", face))
-            } else {
-                make_block("minecraft:purple_wool")
-            };
-            let pos = [
-                (new_pos[0] - offset[0]),
-                (new_pos[1] - offset[1]),
-                (new_pos[2] - offset[2]),
-            ];
-            let _ = region.set_block(pos, &block);
-            new_pos[1] -= 2;
+/// Every bit of the ROM, in the order they are built
+fn bit_cells() -> Vec<BitCell> {
+    let mut cells = Vec::with_capacity(1024 * 16);
+
+    for (address, mut pos) in generate_instruction_positions().into_iter().enumerate() {
+        // Lower byte, then upper byte, each from the most significant bit down. Words are stored
+        // big-endian, so the lower byte comes second.
+        for (byte, offset) in [(address * 2 + 1, 0), (address * 2, 2)] {
+            pos[1] -= offset;
+            for bit in (0..8).rev() {
+                cells.push((pos, byte, 1 << bit));
+                pos[1] -= 2;
+            }
         }
     }
+
+    cells
 }
 
-pub fn make_schematic(data: Vec<u8>) -> Result<Schematic, CompileError> {
-    let binding = convert_to_mc(data).map_err(CompileError::FormatError)?;
-    let mut lines = binding.lines().collect::<Vec<&str>>();
+/// Blocks of the ROM, relative to the point the CPU's schematic is pasted at
+pub fn make_blocks(data: Vec<u8>) -> Result<Vec<Placement>, CompileError> {
+    // Repeaters of the second half of the ROM point the other way
+    Ok(make_bit_blocks(&bit_cells(), &data, |byte| {
+        if byte < 1024 { "east" } else { "west" }
+    }))
+}
 
-    // Pad to 1024 lines
-    while lines.len() < 1024 {
-        lines.push("0000000000000000");
+/// Read the ROM back from a schematic containing it, wherever it is.
+///
+/// Trailing `NOP`s are dropped, as they cannot be told apart from the padding of the ROM.
+pub fn read_schematic(
+    schematic: &Schematic,
+    options: &SchematicOptions,
+) -> Result<Vec<u8>, CompileError> {
+    let mut bytes = read_bit_blocks(
+        schematic,
+        &bit_cells(),
+        2048,
+        options,
+        "BatPU-2 instruction memory",
+    )?;
+
+    while bytes.len() >= 2 && bytes[bytes.len() - 2..] == [0, 0] {
+        bytes.truncate(bytes.len() - 2);
     }
 
-    let offset = [-130, -50, -80];
-    let shape = [150, 60, 160];
-
-    let mut schematic = Schematic::new();
-    let mut region = Region::with_shape(shape);
-
-    let pos_list = generate_instruction_positions();
-    write_instructions(&mut region, &pos_list, &lines, offset);
-
-    schematic.regions.push(region);
-    Ok(schematic)
+    Ok(bytes)
 }
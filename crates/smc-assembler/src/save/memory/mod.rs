@@ -1,25 +1,151 @@
+use std::path::Path;
+
 use clap::ValueEnum;
 use mc_schem::{Block, Schematic};
 
-use crate::CompileError;
+use crate::{
+    CompileError,
+    save::schematic::{PlacedSchematic, Placement, SchematicOptions, find_bits, load_schematic},
+};
+
 pub mod arc_memory_hexserial;
 pub mod batpu2_instruction_memory;
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum Format {
     ArcMemoryHexSerial,
     Batpu2InstructionMemory,
 }
 
 impl Format {
-    pub fn make_schematic(&self, data: Vec<u8>) -> Result<Schematic, CompileError> {
+    pub fn make_blocks(&self, data: Vec<u8>) -> Result<Vec<Placement>, CompileError> {
         match self {
-            Format::ArcMemoryHexSerial => todo!("Still need to implement"),
-            Format::Batpu2InstructionMemory => batpu2_instruction_memory::make_schematic(data),
+            Format::ArcMemoryHexSerial => Err(self.unsupported("schematics")),
+            Format::Batpu2InstructionMemory => batpu2_instruction_memory::make_blocks(data),
         }
     }
+
+    /// Blocks of `data`, or with `previous` only those that differ from the blocks of `previous`
+    pub fn make_patch(
+        &self,
+        data: Vec<u8>,
+        previous: Option<&[u8]>,
+    ) -> Result<Vec<Placement>, CompileError> {
+        let blocks = self.make_blocks(data)?;
+        let Some(previous) = previous else {
+            return Ok(blocks);
+        };
+
+        let previous = self.make_blocks(previous.to_vec())?;
+        Ok(blocks
+            .into_iter()
+            .zip(previous)
+            .filter(|(block, previous)| block != previous)
+            .map(|(block, _)| block)
+            .collect())
+    }
+
+    pub fn make_schematic(
+        &self,
+        data: Vec<u8>,
+        previous: Option<&[u8]>,
+        options: &SchematicOptions,
+    ) -> Result<PlacedSchematic, CompileError> {
+        Ok(PlacedSchematic::new(
+            self.make_patch(data, previous)?,
+            &self.name(),
+            options,
+        ))
+    }
+
+    /// The name of the format as given on the command line
+    pub fn name(&self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default()
+    }
+
+    fn unsupported(&self, operation: &'static str) -> CompileError {
+        CompileError::UnsupportedFormat(self.name(), operation)
+    }
+
+    /// Read the memory back from a schematic, the inverse of [`Format::make_schematic`]
+    pub fn read_schematic(
+        &self,
+        schematic: &Schematic,
+        options: &SchematicOptions,
+    ) -> Result<Vec<u8>, CompileError> {
+        match self {
+            Format::ArcMemoryHexSerial => Err(self.unsupported("extraction")),
+            Format::Batpu2InstructionMemory => {
+                batpu2_instruction_memory::read_schematic(schematic, options)
+            }
+        }
+    }
+
+    /// Load a `.schem`, `.litematic` or `.nbt` file and read the memory back from it
+    pub fn read_schematic_file<P: AsRef<Path>>(
+        &self,
+        input: P,
+        options: &SchematicOptions,
+    ) -> Result<Vec<u8>, CompileError> {
+        self.read_schematic(&load_schematic(input.as_ref())?, options)
+    }
 }
 
 pub fn make_block(id: &str) -> Block {
     Block::from_id(id).unwrap_or_else(|_| Block::air())
 }
+
+/// Where a bit of a memory is built: its position, the index of its byte and its mask
+pub type BitCell = ([i32; 3], usize, u8);
+
+/// Blocks storing `data` in `cells`, a repeater facing `facing(byte)` for every set bit and
+/// purple wool for every other. Bytes past the end of `data` are zero.
+pub fn make_bit_blocks(
+    cells: &[BitCell],
+    data: &[u8],
+    facing: impl Fn(usize) -> &'static str,
+) -> Vec<Placement> {
+    cells
+        .iter()
+        .map(|&(pos, byte, mask)| {
+            let block = if data.get(byte).is_some_and(|value| value & mask != 0) {
+                make_block(&format!("minecraft:repeater[facing={}]", facing(byte)))
+            } else {
+                make_block("minecraft:purple_wool")
+            };
+            (pos, block)
+        })
+        .collect()
+}
+
+/// Read `size` bytes stored as by [`make_bit_blocks`] back from a schematic containing them.
+///
+/// Only `rotation` and `mirror` of `options` are used, the memory is searched for.
+pub fn read_bit_blocks(
+    schematic: &Schematic,
+    cells: &[BitCell],
+    size: usize,
+    options: &SchematicOptions,
+    name: &str,
+) -> Result<Vec<u8>, CompileError> {
+    let positions: Vec<[i32; 3]> = cells.iter().map(|(pos, _, _)| *pos).collect();
+    let values = find_bits(schematic, &positions, options, |block| {
+        match (block.namespace.as_str(), block.id.as_str()) {
+            ("minecraft", "repeater") => Some(true),
+            ("minecraft", "purple_wool") => Some(false),
+            _ => None,
+        }
+    })
+    .ok_or_else(|| CompileError::MemoryNotFound(name.to_string()))?;
+
+    let mut bytes = vec![0; size];
+    for (&(_, byte, mask), value) in cells.iter().zip(values) {
+        if value {
+            bytes[byte] |= mask;
+        }
+    }
+
+    Ok(bytes)
+}
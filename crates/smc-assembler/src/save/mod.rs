@@ -1,42 +1,70 @@
-use std::{fs, path::Path};
+use std::{
+    fs,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use clap::ValueEnum;
+use serde::Deserialize;
 
 use crate::{
-    CompileError,
+    CompileError, CompiledProgram,
     save::{
-        convert::{convert_to_mc, convert_to_tau},
+        convert::{
+            convert_to_bin, convert_to_intel_hex, convert_to_listing, convert_to_logisim,
+            convert_to_mc, convert_to_mcfunction, convert_to_readmemb, convert_to_readmemh,
+            convert_to_tau,
+        },
+        datapack::save_datapack,
         memory::Format,
+        schematic::SchematicOptions,
     },
 };
 
 pub mod convert;
+pub mod datapack;
 pub mod memory;
+pub mod schematic;
 
 pub fn save_file<P: AsRef<Path>>(
     output: P,
     data: Vec<u8>,
-    format: Option<Format>,
+    options: &SaveOptions,
 ) -> Result<(), CompileError> {
     let output = output.as_ref();
-    let extension = output
-        .extension()
-        .unwrap_or_default()
-        .to_str()
-        .unwrap_or("");
+    let extension = extension(output);
 
     // Check if it's a schematic format
-    if extension == "litematic" || extension == "nbt" || extension == "schem" {
-        let format = match format {
+    if matches!(
+        extension,
+        "litematic" | "nbt" | "schem" | "mcfunction" | "zip"
+    ) {
+        let format = match options.format {
             Some(format) => format,
             None => return Err(CompileError::MissingFormat),
         };
+        let previous = options.previous.as_deref();
 
-        let schematic = format.make_schematic(data)?;
+        if extension == "mcfunction" {
+            let blocks = format.make_patch(data, previous)?;
+            fs::write(
+                output,
+                convert_to_mcfunction(blocks, &options.schematic)
+                    .map_err(CompileError::FormatError)?,
+            )
+            .map_err(CompileError::WriteFileError)?;
 
-        schematic
-            .save_to_file(&output.display().to_string())
-            .map_err(|e| CompileError::SchematicSaveFailed(e))?;
+            return Ok(());
+        }
 
-        return Ok(());
+        if extension == "zip" {
+            let blocks = format.make_patch(data, previous)?;
+            return save_datapack(output, blocks, &options.schematic);
+        }
+
+        return format
+            .make_schematic(data, previous, &options.schematic)?
+            .save(output);
     }
 
     if extension == "mc" {
@@ -59,3 +87,157 @@ pub fn save_file<P: AsRef<Path>>(
 
     Err(CompileError::UnsupportedFileType)
 }
+
+/// Byte order of the words of instruction memory in `.bin` outputs
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Endianness {
+    #[default]
+    Big,
+    Little,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SaveOptions {
+    /// Instruction memory format for schematic outputs
+    pub format: Option<Format>,
+    pub endianness: Endianness,
+    pub schematic: SchematicOptions,
+    /// Program already built in the world, schematic and `.mcfunction` outputs then only hold
+    /// the blocks that changed since
+    pub previous: Option<Vec<u8>>,
+}
+
+/// Read back a program written by [`save_file`] as machine code or a schematic
+pub fn load_program<P: AsRef<Path>>(
+    input: P,
+    options: &SaveOptions,
+) -> Result<Vec<u8>, CompileError> {
+    let input = input.as_ref();
+
+    match extension(input) {
+        "mc" | "tau" => {
+            let text = fs::read_to_string(input).map_err(CompileError::ReadFileError)?;
+            let mut bytes = Vec::new();
+            for (line, bits) in text.lines().map(str::trim).enumerate() {
+                if bits.is_empty() {
+                    continue;
+                }
+                let word = u64::from_str_radix(bits, 2)
+                    .ok()
+                    .filter(|_| bits.len() % 8 == 0 && bits.len() <= 64)
+                    .ok_or(CompileError::InvalidMachineCode(line + 1))?;
+                bytes.extend_from_slice(&word.to_be_bytes()[8 - bits.len() / 8..]);
+            }
+            Ok(bytes)
+        }
+        "litematic" | "nbt" | "schem" => options
+            .format
+            .ok_or(CompileError::MissingFormat)?
+            .read_schematic_file(input, &options.schematic),
+        _ => Err(CompileError::UnsupportedFileType),
+    }
+}
+
+/// Save a compiled program, like [`save_file`] but also supporting outputs that depend on the
+/// target or the source, picked by extension:
+///
+/// - `.lst`: listing of every instruction
+/// - `.map`: JSON source map from addresses to the source of their instruction
+/// - `.bin`: raw bytes
+/// - `.hex`: Intel HEX
+/// - `.img`: Logisim-evolution `v2.0 raw` image
+/// - `.memb` / `.memh`: Verilog `$readmemb` / `$readmemh` memory file
+pub fn save_program<P: AsRef<Path>>(
+    output: P,
+    program: &CompiledProgram,
+    options: &SaveOptions,
+) -> Result<(), CompileError> {
+    let output = output.as_ref();
+
+    match render_program(output, program, options)? {
+        Some(content) => fs::write(output, content).map_err(CompileError::WriteFileError),
+        None => save_file(output, program.bytes.clone(), options),
+    }
+}
+
+/// The file contents of an output only [`save_program`] supports, `None` for the others
+fn render_program(
+    output: &Path,
+    program: &CompiledProgram,
+    options: &SaveOptions,
+) -> Result<Option<Vec<u8>>, CompileError> {
+    let word_size = program.target.word_size();
+    let text = match extension(output) {
+        "lst" => convert_to_listing(program),
+        "map" => Ok(program.source_map().to_json()),
+        "bin" => {
+            return Ok(Some(convert_to_bin(
+                &program.bytes,
+                word_size,
+                options.endianness,
+            )));
+        }
+        "hex" => convert_to_intel_hex(&program.bytes),
+        "img" => convert_to_logisim(&program.bytes, word_size),
+        "memb" => convert_to_readmemb(&program.bytes, word_size),
+        "memh" => convert_to_readmemh(&program.bytes, word_size),
+        _ => return Ok(None),
+    };
+
+    text.map(|text| Some(text.into_bytes()))
+        .map_err(CompileError::FormatError)
+}
+
+fn extension(path: &Path) -> &str {
+    path.extension().unwrap_or_default().to_str().unwrap_or("")
+}
+
+/// Number of outputs rendered so far, keeping scratch directories apart
+static RENDERED: AtomicUsize = AtomicUsize::new(0);
+
+/// Save `program` like [`save_program`], unless `output` already holds what would be written, so
+/// tools watching it are not woken up for nothing.
+///
+/// Schematics are compared by the program they hold, as their bytes differ on every save.
+/// Returns whether the file was written.
+pub fn save_program_if_changed<P: AsRef<Path>>(
+    output: P,
+    program: &CompiledProgram,
+    options: &SaveOptions,
+) -> Result<bool, CompileError> {
+    let output = output.as_ref();
+
+    // Rendered in a scratch directory since schematics can only be saved to a file
+    let scratch = std::env::temp_dir().join(format!(
+        "{}-{}-{}",
+        env!("CARGO_PKG_NAME"),
+        std::process::id(),
+        RENDERED.fetch_add(1, Ordering::Relaxed)
+    ));
+    let rendered = scratch.join(output.file_name().unwrap_or_default());
+
+    fs::create_dir_all(&scratch).map_err(CompileError::WriteFileError)?;
+    let written = save_program(&rendered, program, options).and_then(|()| {
+        if holds_same(output, &rendered, options) {
+            return Ok(false);
+        }
+        fs::copy(&rendered, output).map_err(CompileError::WriteFileError)?;
+        Ok(true)
+    });
+    let _ = fs::remove_dir_all(&scratch);
+
+    written
+}
+
+/// Whether the output `existing` holds what `rendered` does
+fn holds_same(existing: &Path, rendered: &Path, options: &SaveOptions) -> bool {
+    match (extension(existing), options.format) {
+        ("litematic" | "nbt" | "schem", Some(format)) => {
+            let read = |path: &Path| format.read_schematic_file(path, &options.schematic).ok();
+            read(existing).is_some_and(|program| read(rendered) == Some(program))
+        }
+        _ => fs::read(existing)
+            .is_ok_and(|existing| fs::read(rendered).is_ok_and(|rendered| rendered == existing)),
+    }
+}
@@ -0,0 +1,427 @@
+use std::{collections::HashMap, fs::File, path::Path};
+
+use clap::ValueEnum;
+use fastnbt::Value;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use mc_schem::{
+    Block, Region, Schematic,
+    region::WorldSlice,
+    schem::{LitematicaSaveOption, VanillaStructureSaveOption, WorldEdit13SaveOption},
+};
+use serde::Deserialize;
+
+use crate::CompileError;
+
+/// A block and its position relative to the point the schematic is pasted at
+pub type Placement = ([i32; 3], Block);
+
+/// Clockwise rotation of the blocks about the paste point, seen from above
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum, Deserialize)]
+pub enum Rotation {
+    #[default]
+    #[value(name = "0")]
+    #[serde(rename = "0")]
+    None,
+    #[value(name = "90")]
+    #[serde(rename = "90")]
+    Clockwise90,
+    #[value(name = "180")]
+    #[serde(rename = "180")]
+    Clockwise180,
+    #[value(name = "270")]
+    #[serde(rename = "270")]
+    Clockwise270,
+}
+
+/// Axis along which the blocks are flipped, before they are rotated
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mirror {
+    #[default]
+    None,
+    X,
+    Z,
+}
+
+/// Placement and metadata of schematic outputs.
+///
+/// With the defaults, pasting while standing where the CPU's reference point is writes the
+/// program straight into its memory.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SchematicOptions {
+    /// Moves every block, after mirroring and rotating
+    pub offset: [i32; 3],
+    pub rotation: Rotation,
+    pub mirror: Mirror,
+    /// World position of the paste point. `.mcfunction` and datapack outputs use absolute
+    /// coordinates with it, and coordinates relative to where they run without it.
+    pub origin: Option<[i32; 3]>,
+    /// Name of the region, defaults to the memory format name
+    pub region_name: Option<String>,
+    /// Name of the schematic, defaults to the region name
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+}
+
+impl SchematicOptions {
+    /// Mirror, rotate and offset a placement, turning directional blocks along with it
+    pub fn transform(&self, (pos, mut block): Placement) -> Placement {
+        let [mut x, y, mut z] = pos;
+
+        match self.mirror {
+            Mirror::None => {}
+            Mirror::X => x = -x,
+            Mirror::Z => z = -z,
+        }
+
+        for _ in 0..self.rotation.quarter_turns() {
+            (x, z) = (-z, x);
+        }
+
+        if let Some(facing) = block.attributes.get_mut("facing") {
+            *facing = self.turn(facing).to_string();
+        }
+
+        (
+            [x + self.offset[0], y + self.offset[1], z + self.offset[2]],
+            block,
+        )
+    }
+
+    fn turn<'a>(&self, facing: &'a str) -> &'a str {
+        const CLOCKWISE: [&str; 4] = ["north", "east", "south", "west"];
+
+        let facing = match (self.mirror, facing) {
+            (Mirror::X, "east") => "west",
+            (Mirror::X, "west") => "east",
+            (Mirror::Z, "north") => "south",
+            (Mirror::Z, "south") => "north",
+            _ => facing,
+        };
+
+        match CLOCKWISE.iter().position(|direction| *direction == facing) {
+            Some(index) => CLOCKWISE[(index + self.rotation.quarter_turns()) % 4],
+            // Up and down are unaffected
+            None => facing,
+        }
+    }
+}
+
+impl Rotation {
+    fn quarter_turns(&self) -> usize {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 1,
+            Rotation::Clockwise180 => 2,
+            Rotation::Clockwise270 => 3,
+        }
+    }
+}
+
+/// A single region schematic together with where it goes relative to the paste point
+pub struct PlacedSchematic {
+    pub schematic: Schematic,
+    /// Position of the region's minimum corner relative to the paste point
+    pub origin: [i32; 3],
+}
+
+impl PlacedSchematic {
+    /// Build a schematic tightly enclosing `placements` after transforming them by `options`
+    pub fn new(placements: Vec<Placement>, region_name: &str, options: &SchematicOptions) -> Self {
+        let placements: Vec<Placement> = placements
+            .into_iter()
+            .map(|placement| options.transform(placement))
+            .collect();
+
+        let (min, max) = match placements.is_empty() {
+            true => ([0; 3], [0; 3]),
+            false => bounds(placements.iter().map(|(pos, _)| pos)),
+        };
+
+        let mut region = Region::with_shape([
+            max[0] - min[0] + 1,
+            max[1] - min[1] + 1,
+            max[2] - min[2] + 1,
+        ]);
+        region.name = options
+            .region_name
+            .clone()
+            .unwrap_or_else(|| region_name.to_string());
+        for (pos, block) in &placements {
+            let _ = region.set_block([pos[0] - min[0], pos[1] - min[1], pos[2] - min[2]], block);
+        }
+
+        let mut schematic = Schematic::new();
+        schematic.metadata.name = options.name.clone().unwrap_or(region.name.clone());
+        schematic.metadata.author = options
+            .author
+            .clone()
+            .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string());
+        schematic.metadata.description = options.description.clone().unwrap_or_default();
+        schematic.regions.push(region);
+
+        PlacedSchematic {
+            schematic,
+            origin: min,
+        }
+    }
+
+    /// Save as `.schem`, `.litematic` or `.nbt`, picked by the extension of `output`.
+    ///
+    /// WorldEdit and Litematica outputs record [`PlacedSchematic::origin`], so they paste in
+    /// place. Structure blocks have no such offset, `.nbt` outputs load at the minimum corner and
+    /// leave out air.
+    pub fn save(&self, output: &Path) -> Result<(), CompileError> {
+        let nbt = match output.extension().and_then(|extension| extension.to_str()) {
+            Some("schem") => self.world_edit_nbt()?,
+            Some("litematic") => self.litematica_nbt()?,
+            // Leave out air, so loading the structure does not clear what surrounds the memory
+            _ => {
+                let option = VanillaStructureSaveOption {
+                    keep_air: false,
+                    ..Default::default()
+                };
+                return self
+                    .schematic
+                    .save_vanilla_structure_file(&output.display().to_string(), &option)
+                    .map_err(CompileError::SchematicSaveFailed);
+            }
+        };
+
+        let file = File::create(output).map_err(CompileError::WriteFileError)?;
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        fastnbt::to_writer(&mut encoder, &nbt)
+            .map_err(|e| CompileError::SchematicSaveFailed(mc_schem::Error::NBTWriteError(e)))?;
+        encoder.finish().map_err(CompileError::WriteFileError)?;
+
+        Ok(())
+    }
+
+    fn world_edit_nbt(&self) -> Result<HashMap<String, Value>, CompileError> {
+        let mut nbt = self
+            .schematic
+            .to_nbt_world_edit_13(&WorldEdit13SaveOption::default())
+            .map_err(CompileError::SchematicSaveFailed)?;
+
+        // Version 3 nests everything in a `Schematic` compound and keeps the minimum corner in
+        // `Offset`, version 2 keeps it in the `WEOffset` metadata
+        if let Some(Value::Compound(mut schematic)) = nbt.remove("Schematic") {
+            schematic.insert("Offset".to_string(), int_array(self.origin));
+            self.insert_world_edit_metadata(&mut schematic, false);
+            nbt.insert("Schematic".to_string(), Value::Compound(schematic));
+        } else {
+            self.insert_world_edit_metadata(&mut nbt, true);
+        }
+
+        Ok(nbt)
+    }
+
+    fn insert_world_edit_metadata(&self, root: &mut HashMap<String, Value>, we_offset: bool) {
+        let mut metadata = match root.remove("Metadata") {
+            Some(Value::Compound(metadata)) => metadata,
+            _ => HashMap::new(),
+        };
+
+        if we_offset {
+            for (axis, origin) in ["X", "Y", "Z"].iter().zip(self.origin) {
+                metadata.insert(format!("WEOffset{}", axis), Value::Int(origin));
+            }
+        }
+        let info = &self.schematic.metadata;
+        metadata.insert("Name".to_string(), Value::String(info.name.clone()));
+        metadata.insert("Author".to_string(), Value::String(info.author.clone()));
+
+        root.insert("Metadata".to_string(), Value::Compound(metadata));
+    }
+
+    fn litematica_nbt(&self) -> Result<HashMap<String, Value>, CompileError> {
+        let mut nbt = self
+            .schematic
+            .to_nbt_litematica(&LitematicaSaveOption::default())
+            .map_err(CompileError::SchematicSaveFailed)?;
+
+        if let Some(Value::Compound(regions)) = nbt.get_mut("Regions") {
+            for region in regions.values_mut() {
+                if let Value::Compound(region) = region {
+                    let position = ["x", "y", "z"]
+                        .iter()
+                        .zip(self.origin)
+                        .map(|(axis, origin)| (axis.to_string(), Value::Int(origin)))
+                        .collect();
+                    region.insert("Position".to_string(), Value::Compound(position));
+                }
+            }
+        }
+
+        Ok(nbt)
+    }
+}
+
+/// Minimum and maximum corner of `positions`
+fn bounds<'a>(positions: impl Iterator<Item = &'a [i32; 3]>) -> ([i32; 3], [i32; 3]) {
+    positions.fold(([i32::MAX; 3], [i32::MIN; 3]), |(min, max), pos| {
+        (
+            [0, 1, 2].map(|axis| min[axis].min(pos[axis])),
+            [0, 1, 2].map(|axis| max[axis].max(pos[axis])),
+        )
+    })
+}
+
+fn int_array(values: [i32; 3]) -> Value {
+    Value::IntArray(fastnbt::IntArray::new(values.to_vec()))
+}
+
+/// Load a `.schem`, `.litematic` or `.nbt` file
+pub fn load_schematic(input: &Path) -> Result<Schematic, CompileError> {
+    if input.extension().and_then(|extension| extension.to_str()) == Some("nbt") {
+        return load_structure(input);
+    }
+
+    Schematic::from_file(&input.display().to_string())
+        .map(|(schematic, _)| schematic)
+        .map_err(CompileError::SchematicLoadFailed)
+}
+
+/// Load a vanilla structure, mc_schem shifts the palette of these by one when loading them
+fn load_structure(input: &Path) -> Result<Schematic, CompileError> {
+    let file = File::open(input).map_err(CompileError::ReadFileError)?;
+    let nbt: HashMap<String, Value> = fastnbt::from_reader(GzDecoder::new(file))
+        .map_err(|e| CompileError::SchematicLoadFailed(mc_schem::Error::NBTReadError(e)))?;
+    let invalid = |tag: &str| {
+        CompileError::SchematicLoadFailed(mc_schem::Error::InvalidValue {
+            tag_path: format!("/{}", tag),
+            error: "missing or malformed".to_string(),
+        })
+    };
+
+    fn list(value: Option<&Value>) -> Option<&Vec<Value>> {
+        match value {
+            Some(Value::List(list)) => Some(list),
+            _ => None,
+        }
+    }
+    let position = |value: Option<&Value>, tag: &str| -> Result<[i32; 3], CompileError> {
+        match list(value).map(Vec::as_slice) {
+            Some([Value::Int(x), Value::Int(y), Value::Int(z)]) => Ok([*x, *y, *z]),
+            _ => Err(invalid(tag)),
+        }
+    };
+
+    let palette: Vec<Block> = list(nbt.get("palette"))
+        .ok_or_else(|| invalid("palette"))?
+        .iter()
+        .map(|entry| {
+            let Value::Compound(entry) = entry else {
+                return Err(invalid("palette"));
+            };
+            let Some(Value::String(name)) = entry.get("Name") else {
+                return Err(invalid("palette/Name"));
+            };
+            let mut block = Block::from_id(name).map_err(|_| invalid("palette/Name"))?;
+            if let Some(Value::Compound(properties)) = entry.get("Properties") {
+                for (key, value) in properties {
+                    if let Value::String(value) = value {
+                        block.attributes.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            Ok(block)
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut region = Region::with_shape(position(nbt.get("size"), "size")?);
+    for entry in list(nbt.get("blocks")).ok_or_else(|| invalid("blocks"))? {
+        let Value::Compound(entry) = entry else {
+            return Err(invalid("blocks"));
+        };
+        let block = match entry.get("state") {
+            Some(Value::Int(state)) => palette.get(*state as usize),
+            _ => None,
+        }
+        .ok_or_else(|| invalid("blocks/state"))?;
+        let _ = region.set_block(position(entry.get("pos"), "blocks/pos")?, block);
+    }
+
+    let mut schematic = Schematic::new();
+    schematic.regions.push(region);
+    Ok(schematic)
+}
+
+/// Find where the blocks at `positions`, transformed by the rotation and mirroring of
+/// `options`, all lie in `schematic`, returning the bit `read` gives for each of them.
+///
+/// `read` returns `None` for blocks that cannot be part of the layout. The layout must be at one
+/// side of the blocks `read` accepts along each axis, as in a schematic holding only the memory
+/// and the circuitry around it.
+pub fn find_bits(
+    schematic: &Schematic,
+    positions: &[[i32; 3]],
+    options: &SchematicOptions,
+    read: impl Fn(&Block) -> Option<bool>,
+) -> Option<Vec<bool>> {
+    let options = SchematicOptions {
+        offset: [0; 3],
+        ..options.clone()
+    };
+    let positions: Vec<[i32; 3]> = positions
+        .iter()
+        .map(|pos| options.transform((*pos, Block::air())).0)
+        .collect();
+    if positions.is_empty() {
+        return None;
+    }
+
+    let mut bits = HashMap::new();
+    for region in &schematic.regions {
+        let shape = region.shape();
+        for x in 0..shape[0] {
+            for y in 0..shape[1] {
+                for z in 0..shape[2] {
+                    if let Some(bit) = region.block_at([x, y, z]).and_then(&read) {
+                        let pos = [
+                            x + region.offset[0],
+                            y + region.offset[1],
+                            z + region.offset[2],
+                        ];
+                        bits.entry(pos).or_insert(bit);
+                    }
+                }
+            }
+        }
+    }
+
+    // The layout fills its bounding box along every axis, so it touches the blocks found on
+    // at least one side of each, leaving at most two shifts per axis to try
+    let (layout_min, layout_max) = bounds(positions.iter());
+    let (found_min, found_max) = bounds(bits.keys());
+    let shifts = [0, 1, 2].map(|axis| {
+        [
+            found_min[axis] - layout_min[axis],
+            found_max[axis] - layout_max[axis],
+        ]
+    });
+
+    let mut candidates: Vec<[i32; 3]> = Vec::with_capacity(8);
+    for x in shifts[0] {
+        for y in shifts[1] {
+            for z in shifts[2] {
+                if !candidates.contains(&[x, y, z]) {
+                    candidates.push([x, y, z]);
+                }
+            }
+        }
+    }
+    candidates.sort_by_key(|shift| shift.map(i32::abs).iter().sum::<i32>());
+
+    candidates.into_iter().find_map(|shift| {
+        positions
+            .iter()
+            .map(|pos| {
+                bits.get(&[pos[0] + shift[0], pos[1] + shift[1], pos[2] + shift[2]])
+                    .copied()
+            })
+            .collect()
+    })
+}
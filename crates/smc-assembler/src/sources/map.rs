@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assembler::{AssembledInstruction, backends::Backend},
+    lexer::token::SourceId,
+    sources::Sources,
+};
+
+/// Where every instruction of a program came from, for debuggers, emulators and listings.
+///
+/// Serialized as JSON by [`SourceMap::to_json`], which is what `.map` outputs hold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceMap {
+    pub target: String,
+    /// Path of every source file, indexed by the `file` of entries
+    pub files: Vec<PathBuf>,
+    /// One entry per instruction, in address order
+    pub entries: Vec<SourceMapEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceMapEntry {
+    pub address: usize,
+    /// Number of addresses the instruction takes
+    pub size: usize,
+    pub file: SourceId,
+    /// Byte offsets of the operation in its file
+    pub start: usize,
+    pub end: usize,
+    /// 1-based line and column of the start of the operation
+    pub line: usize,
+    pub column: usize,
+    /// The operation as written
+    pub operation: String,
+    /// The instruction actually encoded, only present for pseudo-instructions such as `CMP`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expanded: Option<String>,
+}
+
+impl SourceMap {
+    pub fn new(target: &Backend, instructions: &[AssembledInstruction], sources: &Sources) -> Self {
+        let entries = instructions
+            .iter()
+            .map(|instruction| {
+                let span = &instruction.span;
+                let (line, column) = sources
+                    .get(span.source())
+                    .map_or((0, 0), |file| span.location(&file.text));
+
+                SourceMapEntry {
+                    address: instruction.address,
+                    size: target.instruction_byte_size(&instruction.op),
+                    file: span.source(),
+                    start: span.start(),
+                    end: span.end(),
+                    line,
+                    column,
+                    operation: instruction.op.to_string(),
+                    expanded: (instruction.encoded != instruction.op)
+                        .then(|| instruction.encoded.to_string()),
+                }
+            })
+            .collect();
+
+        SourceMap {
+            target: target.to_str().to_string(),
+            files: sources.files.iter().map(|file| file.path.clone()).collect(),
+            entries,
+        }
+    }
+
+    /// The instruction taking up `address`
+    pub fn entry(&self, address: usize) -> Option<&SourceMapEntry> {
+        let index = self
+            .entries
+            .partition_point(|entry| entry.address + entry.size <= address);
+        self.entries
+            .get(index)
+            .filter(|entry| entry.address <= address)
+    }
+
+    /// Address of the first instruction on `line`, or on the closest line after it with code
+    pub fn line_address(&self, file: SourceId, line: usize) -> Option<usize> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.file == file && entry.line >= line)
+            .min_by_key(|entry| (entry.line, entry.address))
+            .map(|entry| entry.address)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("source maps always serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
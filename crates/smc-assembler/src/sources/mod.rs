@@ -0,0 +1,194 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    CompileError,
+    lexer::{
+        Lexer, LexerError,
+        token::{Keyword, SourceId, Span, Token, TokenSpan},
+    },
+};
+
+pub mod map;
+
+/// A file taking part in a compilation
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    /// Path as given for the entry file, or joined onto the directory it was found in
+    pub path: PathBuf,
+    pub text: String,
+}
+
+/// The entry file and every file it includes, indexed by the source id of spans
+#[derive(Debug, Clone, Default)]
+pub struct Sources {
+    pub files: Vec<SourceFile>,
+    canonical: Vec<PathBuf>,
+    /// The `include` directive that brought in each file, `None` for the entry file
+    included_at: Vec<Option<Span>>,
+}
+
+impl Sources {
+    /// Read and lex `entry`, splicing in the tokens of every `include "path"`.
+    ///
+    /// Includes are resolved relative to the including file first, then against each of
+    /// `include_dirs` in order. A file is only included once, later includes are ignored.
+    pub fn load<P: AsRef<Path>>(
+        entry: P,
+        include_dirs: &[PathBuf],
+    ) -> Result<(Self, Vec<Result<TokenSpan, LexerError>>), CompileError> {
+        let entry = entry.as_ref();
+
+        if !entry.exists() {
+            return Err(CompileError::PathDoesNotExist);
+        }
+
+        let text = fs::read_to_string(entry).map_err(CompileError::ReadFileError)?;
+
+        Ok(Self::from_text(entry, text, include_dirs))
+    }
+
+    /// Like [`Sources::load`], with the entry file's text already in memory, e.g. from an editor
+    pub fn from_text<P: AsRef<Path>>(
+        entry: P,
+        text: String,
+        include_dirs: &[PathBuf],
+    ) -> (Self, Vec<Result<TokenSpan, LexerError>>) {
+        let end = text.len();
+
+        let mut sources = Sources::default();
+        sources.push(entry.as_ref().to_path_buf(), text, None);
+
+        let mut tokens = Vec::new();
+        sources.lex_into(0, include_dirs, &mut vec![0], &mut tokens);
+        tokens.push(Ok(TokenSpan::new(Token::Eof, Span::new(end, end))));
+
+        (sources, tokens)
+    }
+
+    /// Paths of `entry` and every file it includes, or only `entry` if it cannot be read
+    pub fn dependencies<P: AsRef<Path>>(entry: P, include_dirs: &[PathBuf]) -> Vec<PathBuf> {
+        match Self::load(&entry, include_dirs) {
+            Ok((sources, _)) => sources.files.into_iter().map(|file| file.path).collect(),
+            Err(_) => vec![entry.as_ref().to_path_buf()],
+        }
+    }
+
+    pub fn get(&self, source: SourceId) -> Option<&SourceFile> {
+        self.files.get(source)
+    }
+
+    /// The `include` directive in the entry file through which `span` was included, or `span`
+    /// itself if it is in the entry file
+    pub fn span_in_entry<'a>(&'a self, mut span: &'a Span) -> &'a Span {
+        while let Some(Some(include)) = self.included_at.get(span.source()) {
+            span = include;
+        }
+        span
+    }
+
+    fn push(&mut self, path: PathBuf, text: String, included_at: Option<Span>) -> SourceId {
+        self.canonical
+            .push(fs::canonicalize(&path).unwrap_or_else(|_| path.clone()));
+        self.files.push(SourceFile { path, text });
+        self.included_at.push(included_at);
+        self.files.len() - 1
+    }
+
+    fn lex_into(
+        &mut self,
+        source: SourceId,
+        include_dirs: &[PathBuf],
+        stack: &mut Vec<SourceId>,
+        tokens: &mut Vec<Result<TokenSpan, LexerError>>,
+    ) {
+        let text = self.files[source].text.clone();
+        let mut lexer = Lexer::with_source(&text, source).peekable();
+
+        while let Some(token) = lexer.next() {
+            let include_span = match token {
+                Ok(TokenSpan {
+                    token: Token::Eof, ..
+                }) => break,
+                Ok(TokenSpan {
+                    token: Token::Keyword(Keyword::Include),
+                    span,
+                }) => span,
+                token => {
+                    tokens.push(token);
+                    continue;
+                }
+            };
+
+            // Leave a malformed include for the parser to report
+            let (path, span) = match lexer.peek() {
+                Some(Ok(TokenSpan {
+                    token: Token::String(path),
+                    span,
+                })) => (
+                    path.clone(),
+                    Span::new(include_span.start(), span.end()).in_source(source),
+                ),
+                _ => {
+                    tokens.push(Ok(TokenSpan::new(
+                        Token::Keyword(Keyword::Include),
+                        include_span,
+                    )));
+                    continue;
+                }
+            };
+            lexer.next();
+
+            let resolved = match self.resolve(source, &path, include_dirs) {
+                Some(resolved) => resolved,
+                None => {
+                    tokens.push(Err(LexerError::InvalidInclude(
+                        span,
+                        path,
+                        "file not found".to_string(),
+                    )));
+                    continue;
+                }
+            };
+
+            let canonical = fs::canonicalize(&resolved).unwrap_or_else(|_| resolved.clone());
+            if let Some(existing) = self.canonical.iter().position(|path| *path == canonical) {
+                if stack.contains(&existing) {
+                    tokens.push(Err(LexerError::InvalidInclude(
+                        span,
+                        path,
+                        "file includes itself".to_string(),
+                    )));
+                }
+                continue;
+            }
+
+            match fs::read_to_string(&resolved) {
+                Ok(text) => {
+                    let included = self.push(resolved, text, Some(span));
+                    stack.push(included);
+                    self.lex_into(included, include_dirs, stack, tokens);
+                    stack.pop();
+                }
+                Err(err) => {
+                    tokens.push(Err(LexerError::InvalidInclude(span, path, err.to_string())))
+                }
+            }
+        }
+    }
+
+    fn resolve(&self, source: SourceId, path: &str, include_dirs: &[PathBuf]) -> Option<PathBuf> {
+        let parent = self.files[source]
+            .path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        std::iter::once(&parent)
+            .chain(include_dirs)
+            .map(|dir| dir.join(path))
+            .find(|candidate| candidate.is_file())
+    }
+}
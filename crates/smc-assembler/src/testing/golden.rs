@@ -0,0 +1,291 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use thiserror::Error;
+
+use crate::{
+    CompileOptions, CompiledProgram,
+    assembler::backends::Backend,
+    compile_file_reporting,
+    project::select_backend,
+    save::{SaveOptions, memory::Format, save_program},
+};
+
+/// Directory of the programs of a golden directory
+pub const PROGRAMS_DIR: &str = "programs";
+
+/// Directory of the outputs expected from the programs, named after them
+pub const EXPECTED_DIR: &str = "expected";
+
+#[derive(Error, Debug)]
+pub enum GoldenError {
+    #[error("Failed to read {0}: {1}")]
+    ReadFailed(PathBuf, std::io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Matched,
+    /// The expected output was written from the program
+    Blessed,
+    /// The output differs, or the program could not be built
+    Failed(String),
+}
+
+/// How one output of a program compared
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoldenResult {
+    pub program: PathBuf,
+    pub expected: PathBuf,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct GoldenOptions {
+    /// Write the expected outputs instead of comparing them
+    pub bless: bool,
+    /// Options of every output, schematic outputs default to the instruction memory of the
+    /// program's backend
+    pub save: SaveOptions,
+}
+
+/// Build every program in `dir/programs` and compare it with each of its outputs in
+/// `dir/expected`, `expected/tetris.mc` and `expected/tetris.schem` being outputs of
+/// `programs/tetris.smc`.
+///
+/// The backend of each program is picked like the LSP does. Schematics are compared by the
+/// program they hold, and datapacks by the files in them. Source paths in outputs are relative
+/// to `dir`. When blessing, a program without outputs gets its machine code written.
+pub fn check_golden(dir: &Path, options: &GoldenOptions) -> Result<Vec<GoldenResult>, GoldenError> {
+    let expected = list(&dir.join(EXPECTED_DIR))?;
+    let mut results = Vec::new();
+
+    for program in list(&dir.join(PROGRAMS_DIR))? {
+        let stem = program.file_stem().unwrap_or_default();
+        let mut outputs: Vec<PathBuf> = expected
+            .iter()
+            .filter(|path| path.file_stem() == Some(stem))
+            .cloned()
+            .collect();
+
+        let compiled = build(dir, &program);
+        if outputs.is_empty() {
+            let message = match &compiled {
+                Ok(compiled) if options.bless => {
+                    let path = dir.join(EXPECTED_DIR).join(stem);
+                    outputs.push(path.with_extension(machine_code_extension(&compiled.target)));
+                    None
+                }
+                Ok(_) => Some("No expected outputs".to_string()),
+                Err(message) => Some(message.clone()),
+            };
+            if let Some(message) = message {
+                results.push(GoldenResult {
+                    program: program.clone(),
+                    expected: dir.join(EXPECTED_DIR),
+                    outcome: Outcome::Failed(message),
+                });
+            }
+        }
+
+        for expected in outputs {
+            let outcome = match &compiled {
+                Ok(compiled) => compare(compiled, &expected, options),
+                Err(message) => Outcome::Failed(message.clone()),
+            };
+            results.push(GoldenResult {
+                program: program.clone(),
+                expected,
+                outcome,
+            });
+        }
+    }
+
+    Ok(results)
+}
+
+/// Files directly in `dir`, sorted
+fn list(dir: &Path) -> Result<Vec<PathBuf>, GoldenError> {
+    let entries =
+        fs::read_dir(dir).map_err(|err| GoldenError::ReadFailed(dir.to_path_buf(), err))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn build(dir: &Path, program: &Path) -> Result<CompiledProgram, String> {
+    let source = fs::read_to_string(program).map_err(|err| err.to_string())?;
+    let target = select_backend(program, &source)
+        .map_err(|err| err.to_string())?
+        .ok_or("No backend for this file")?;
+
+    let mut report = String::new();
+    let mut compiled =
+        compile_file_reporting(program, target, &CompileOptions::default(), |errors| {
+            report = errors.to_string()
+        })
+        .map_err(|err| format!("{}\n{}", err, report))?;
+
+    for file in &mut compiled.sources.files {
+        if let Ok(path) = file.path.strip_prefix(dir) {
+            file.path = path.to_path_buf();
+        }
+    }
+    Ok(compiled)
+}
+
+fn machine_code_extension(target: &Backend) -> &'static str {
+    match target {
+        Backend::BatPU2 => "mc",
+        Backend::TauAnalyzersNone => "tau",
+    }
+}
+
+fn default_format(target: &Backend) -> Option<Format> {
+    match target {
+        Backend::BatPU2 => Some(Format::Batpu2InstructionMemory),
+        Backend::TauAnalyzersNone => None,
+    }
+}
+
+fn compare(program: &CompiledProgram, expected: &Path, options: &GoldenOptions) -> Outcome {
+    let mut save = options.save.clone();
+    save.format = save.format.or(default_format(&program.target));
+
+    if options.bless {
+        return match save_program(expected, program, &save) {
+            Ok(()) => Outcome::Blessed,
+            Err(err) => Outcome::Failed(err.to_string()),
+        };
+    }
+
+    // Outputs such as datapacks depend on their file name, so keep it
+    static RENDERS: AtomicUsize = AtomicUsize::new(0);
+    let dir = env::temp_dir().join(format!(
+        "smc-golden-{}-{}",
+        process::id(),
+        RENDERS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let actual = dir.join(expected.file_name().unwrap_or_default());
+
+    let difference = fs::create_dir_all(&dir)
+        .map_err(|err| err.to_string())
+        .and_then(|()| save_program(&actual, program, &save).map_err(|err| err.to_string()))
+        .and_then(|()| difference(expected, &actual, &save));
+    let _ = fs::remove_dir_all(&dir);
+
+    match difference {
+        Ok(None) => Outcome::Matched,
+        Ok(Some(difference)) | Err(difference) => Outcome::Failed(difference),
+    }
+}
+
+/// How `actual` differs from `expected`, if it does
+fn difference(
+    expected: &Path,
+    actual: &Path,
+    save: &SaveOptions,
+) -> Result<Option<String>, String> {
+    let extension = expected.extension().unwrap_or_default().to_string_lossy();
+
+    let (expected, actual) = match extension.as_ref() {
+        "schem" | "litematic" | "nbt" => {
+            let format = save.format.ok_or("Schematic outputs need a format")?;
+            let read = |path: &Path| {
+                format
+                    .read_schematic_file(path, &save.schematic)
+                    .map_err(|err| format!("{}: {}", path.display(), err))
+            };
+            let (expected, actual) = (read(expected)?, read(actual)?);
+            return Ok(first_difference(&expected, &actual)
+                .map(|at| format!("Holds a different program, from byte {}", at)));
+        }
+        "zip" => (zip_files(expected)?, zip_files(actual)?),
+        _ => {
+            let read =
+                |path: &Path| fs::read(path).map_err(|err| format!("{}: {}", path.display(), err));
+            let file = BTreeMap::from([(String::new(), read(expected)?)]);
+            (file, BTreeMap::from([(String::new(), read(actual)?)]))
+        }
+    };
+
+    for (name, expected_file) in &expected {
+        let in_file = match name.is_empty() {
+            true => String::new(),
+            false => format!(" in {}", name),
+        };
+        let Some(actual_file) = actual.get(name) else {
+            return Ok(Some(format!("Missing{}", in_file)));
+        };
+        if let Some(difference) = file_difference(expected_file, actual_file) {
+            return Ok(Some(format!("{}{}", difference, in_file)));
+        }
+    }
+    Ok(actual
+        .keys()
+        .find(|name| !expected.contains_key(*name))
+        .map(|name| format!("Unexpected file {}", name)))
+}
+
+fn file_difference(expected: &[u8], actual: &[u8]) -> Option<String> {
+    let (Ok(expected), Ok(actual)) = (std::str::from_utf8(expected), std::str::from_utf8(actual))
+    else {
+        return first_difference(expected, actual).map(|at| format!("Differs from byte {}", at));
+    };
+
+    let (mut expected_lines, mut actual_lines) = (expected.lines(), actual.lines());
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => break,
+            (expected, actual) if expected == actual => continue,
+            (expected, actual) => {
+                let show = |text: Option<&str>| match text {
+                    Some(text) => format!("`{}`", text),
+                    None => "the end".to_string(),
+                };
+                return Some(format!(
+                    "Line {}: expected {}, got {}",
+                    line,
+                    show(expected),
+                    show(actual)
+                ));
+            }
+        }
+    }
+
+    // Only the line endings differ
+    (expected != actual).then(|| "Line endings differ".to_string())
+}
+
+fn first_difference(expected: &[u8], actual: &[u8]) -> Option<usize> {
+    expected
+        .iter()
+        .zip(actual)
+        .position(|(expected, actual)| expected != actual)
+        .or_else(|| (expected.len() != actual.len()).then(|| expected.len().min(actual.len())))
+}
+
+/// Contents of every file in a zip, by name
+fn zip_files(path: &Path) -> Result<BTreeMap<String, Vec<u8>>, String> {
+    let error = |err: &dyn std::fmt::Display| format!("{}: {}", path.display(), err);
+    let file = fs::File::open(path).map_err(|err| error(&err))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|err| error(&err))?;
+
+    let mut files = BTreeMap::new();
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).map_err(|err| error(&err))?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).map_err(|err| error(&err))?;
+        files.insert(file.name().to_string(), content);
+    }
+    Ok(files)
+}
@@ -0,0 +1,475 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{
+    CompiledProgram,
+    assembler::backends::Backend,
+    debugger::parse_number,
+    emulator::{Emulator, Io, NullIo, batpu2_io::Batpu2Io},
+    lexer::token::Span,
+};
+
+pub mod golden;
+
+/// Extension of test files, holding the tests of the program with the same file stem
+pub const TEST_EXTENSION: &str = "smctest";
+
+/// Prefix of the comment lines holding tests inside a program
+pub const INLINE_PREFIX: &str = "//!";
+
+/// Instructions a test may run when it sets no `within`
+pub const DEFAULT_CYCLES: u64 = 1_000_000;
+
+#[derive(Error, Debug)]
+pub enum TestError {
+    #[error("Syntax Error: {1}")]
+    Syntax(Span, String),
+
+    /// A syntax error shown against the file it is in
+    #[error("{0}")]
+    Invalid(String),
+
+    #[error("Failed to read {0}: {1}")]
+    ReadFailed(PathBuf, std::io::Error),
+}
+
+impl TestError {
+    fn shown_in(self, path: &Path, text: &str) -> TestError {
+        match self {
+            TestError::Syntax(ref span, _) => {
+                TestError::Invalid(span.format_error(path, text, &self.to_string()))
+            }
+            err => err,
+        }
+    }
+}
+
+/// A register, or a data memory address as a number or define
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    Register(u8),
+    Memory(String),
+}
+
+/// A value a test sets or expects, as a number or define
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub location: Location,
+    pub value: String,
+    pub span: Span,
+}
+
+/// A test, written as
+///
+/// ```text
+/// test multiplies
+///   set r1 7
+///   set r2 6
+///   call .multiply
+///   within 100
+///   expect r3 42
+/// end
+/// ```
+///
+/// `call .label` runs a subroutine until it returns, `run [.label]` runs from the start or a
+/// label until `HLT`, the default being `run`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestCase {
+    pub name: String,
+    pub span: Span,
+    pub setup: Vec<Assignment>,
+    /// Label to start at, from address 0 if `None`
+    pub entry: Option<(String, Span)>,
+    /// Whether the entry is called as a subroutine rather than run until it halts
+    pub call: bool,
+    /// Most instructions the test may run
+    pub within: u64,
+    pub expectations: Vec<Assignment>,
+}
+
+/// The tests written for a program, either in a test file or in the program's own comments
+#[derive(Debug, Clone)]
+pub struct TestSuite {
+    pub program: PathBuf,
+    /// File the tests are written in
+    pub path: PathBuf,
+    pub text: String,
+    pub cases: Vec<TestCase>,
+}
+
+/// Why a test failed, pointing into the text of its suite
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    pub cycles: u64,
+    pub failures: Vec<Failure>,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl TestSuite {
+    /// The tests of `program`, from its test file and its `//!` comments
+    pub fn load(program: &Path) -> Result<Vec<TestSuite>, TestError> {
+        let mut suites = Vec::new();
+
+        let text = fs::read_to_string(program)
+            .map_err(|err| TestError::ReadFailed(program.to_path_buf(), err))?;
+        let cases = parse_tests(&text, true).map_err(|err| err.shown_in(program, &text))?;
+        if !cases.is_empty() {
+            suites.push(TestSuite {
+                program: program.to_path_buf(),
+                path: program.to_path_buf(),
+                text,
+                cases,
+            });
+        }
+
+        let path = program.with_extension(TEST_EXTENSION);
+        if path.is_file() {
+            let text = fs::read_to_string(&path)
+                .map_err(|err| TestError::ReadFailed(path.clone(), err))?;
+            suites.push(TestSuite {
+                program: program.to_path_buf(),
+                cases: parse_tests(&text, false).map_err(|err| err.shown_in(&path, &text))?,
+                path,
+                text,
+            });
+        }
+
+        Ok(suites)
+    }
+
+    /// Run every test on a fresh emulator
+    pub fn run(&self, program: &CompiledProgram) -> Vec<TestResult> {
+        self.cases
+            .iter()
+            .map(|case| match program.target {
+                Backend::BatPU2 => run_case(program, case, Batpu2Io::default()),
+                _ => run_case(program, case, NullIo),
+            })
+            .collect()
+    }
+}
+
+/// Programs under `path` with tests, or the program of `path` if it is a file.
+///
+/// Test files stand for the program with the same file stem next to them.
+pub fn discover(path: &Path) -> Result<Vec<PathBuf>, TestError> {
+    if path.is_file() {
+        if path.extension().is_some_and(|ext| ext == TEST_EXTENSION) {
+            return Ok(program_of(path).into_iter().collect());
+        }
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut programs = Vec::new();
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries =
+            fs::read_dir(&dir).map_err(|err| TestError::ReadFailed(dir.to_path_buf(), err))?;
+        let mut entries: Vec<_> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .collect();
+        entries.sort();
+
+        for entry in entries {
+            let name = entry.file_name().unwrap_or_default().to_string_lossy();
+            if entry.is_dir() {
+                if !name.starts_with('.') && name != "target" {
+                    dirs.push(entry);
+                }
+                continue;
+            }
+
+            let is_program = entry
+                .extension()
+                .is_some_and(|ext| ext == "smc" || ext == "tasm");
+            let has_tests = entry.with_extension(TEST_EXTENSION).is_file()
+                || fs::read_to_string(&entry).is_ok_and(|text| text.contains(INLINE_PREFIX));
+            if is_program && has_tests {
+                programs.push(entry);
+            }
+        }
+    }
+
+    programs.sort();
+    Ok(programs)
+}
+
+/// The program next to a test file
+fn program_of(test_file: &Path) -> Option<PathBuf> {
+    ["smc", "tasm"]
+        .iter()
+        .map(|ext| test_file.with_extension(ext))
+        .find(|path| path.is_file())
+}
+
+/// Parse the tests of a test file, or the `//!` comments of a program if `inline`
+pub fn parse_tests(text: &str, inline: bool) -> Result<Vec<TestCase>, TestError> {
+    let mut cases = Vec::new();
+    let mut current: Option<TestCase> = None;
+
+    let mut line_start = 0;
+    for line in text.split_inclusive('\n') {
+        let start = line_start;
+        line_start += line.len();
+
+        // Offset of the content within the line
+        let (offset, content) = match inline {
+            true => match line.find(INLINE_PREFIX) {
+                Some(index) if line[..index].trim().is_empty() => (
+                    index + INLINE_PREFIX.len(),
+                    &line[index + INLINE_PREFIX.len()..],
+                ),
+                _ => continue,
+            },
+            false => (0, line),
+        };
+        let content = content.split("//").next().unwrap_or_default();
+        let trimmed = content.trim_start();
+        let offset = start + offset + content.len() - trimmed.len();
+        let content = trimmed.trim_end();
+        if content.is_empty() {
+            continue;
+        }
+        let span = Span::new(offset, offset + content.len());
+
+        let words: Vec<&str> = content.split_whitespace().collect();
+        let syntax = |message: &str| TestError::Syntax(span.clone(), message.to_string());
+
+        let Some(case) = current.as_mut() else {
+            match words.as_slice() {
+                ["test", name] => {
+                    current = Some(TestCase {
+                        name: name.to_string(),
+                        span,
+                        setup: Vec::new(),
+                        entry: None,
+                        call: false,
+                        within: DEFAULT_CYCLES,
+                        expectations: Vec::new(),
+                    })
+                }
+                _ => return Err(syntax("Expected `test NAME`")),
+            }
+            continue;
+        };
+
+        match words.as_slice() {
+            ["end"] => cases.extend(current.take()),
+            ["set", location, value] => case.setup.push(Assignment {
+                location: parse_location(location).ok_or_else(|| syntax("Invalid location"))?,
+                value: value.to_string(),
+                span,
+            }),
+            ["expect", location, value] => case.expectations.push(Assignment {
+                location: parse_location(location).ok_or_else(|| syntax("Invalid location"))?,
+                value: value.to_string(),
+                span,
+            }),
+            ["call", label] | ["run", label] if label.starts_with('.') => {
+                case.entry = Some((label[1..].to_string(), span));
+                case.call = words[0] == "call";
+            }
+            ["run"] => {
+                case.entry = None;
+                case.call = false;
+            }
+            ["within", cycles] => {
+                case.within =
+                    parse_number(cycles).map_err(|_| syntax("Invalid cycle count"))? as u64
+            }
+            _ => {
+                return Err(syntax(
+                    "Expected `set`, `call .label`, `run [.label]`, `within`, `expect` or `end`",
+                ));
+            }
+        }
+    }
+
+    match current {
+        Some(case) => Err(TestError::Syntax(
+            case.span,
+            format!("Test `{}` has no `end`", case.name),
+        )),
+        None => Ok(cases),
+    }
+}
+
+/// `rN`, or `[ADDRESS]` for data memory
+fn parse_location(text: &str) -> Option<Location> {
+    if let Some(address) = text
+        .strip_prefix('[')
+        .and_then(|text| text.strip_suffix(']'))
+    {
+        return Some(Location::Memory(address.to_string()));
+    }
+
+    let register = text.strip_prefix(['r', 'R'])?.parse().ok()?;
+    Some(Location::Register(register))
+}
+
+fn run_case<I: Io>(program: &CompiledProgram, case: &TestCase, io: I) -> TestResult {
+    let mut emulator = Emulator::with_io(program.target.clone(), &program.bytes, io);
+    let mut failures = Vec::new();
+    let failure = |span: &Span, message: String| Failure {
+        span: span.clone(),
+        message,
+    };
+
+    for assignment in &case.setup {
+        let placed = value(program, &assignment.value)
+            .and_then(|value| Ok((place(&emulator, program, &assignment.location)?, value)));
+        match placed {
+            Ok((Place::Register(0), _)) if program.target == Backend::BatPU2 => {
+                failures.push(failure(&assignment.span, "r0 is always zero".to_string()))
+            }
+            Ok((Place::Register(register), value)) => emulator.registers[register] = value,
+            Ok((Place::Memory(address), value)) => emulator.memory[address] = value,
+            Err(message) => failures.push(failure(&assignment.span, message)),
+        }
+    }
+
+    // Returning from the called subroutine lands on an address past the end of the program
+    let returned = program.target.capacity();
+    if let Some((label, span)) = &case.entry {
+        match program.labels.get(label) {
+            Some(address) => emulator.pc = *address,
+            None => failures.push(failure(span, format!("Unknown label `.{}`", label))),
+        }
+    }
+    if case.call {
+        emulator.call_stack.push(returned);
+    }
+
+    while failures.is_empty() && !emulator.halted() && !(case.call && emulator.pc == returned) {
+        if emulator.cycles >= case.within {
+            let message = match case.call {
+                true => format!("Did not return within {} cycles", case.within),
+                false => format!("Did not halt within {} cycles", case.within),
+            };
+            failures.push(failure(&case.span, message));
+            continue;
+        }
+
+        let address = emulator.pc;
+        if let Err(err) = emulator.step() {
+            let at = program
+                .source_map()
+                .entry(address)
+                .and_then(|entry| {
+                    let file = program.sources.get(entry.file)?;
+                    let name = file.path.file_name()?.to_string_lossy();
+                    Some(format!(" at {}:{}", name, entry.line))
+                })
+                .unwrap_or_default();
+            failures.push(failure(&case.span, format!("{}{}", err, at)));
+        }
+    }
+
+    // Only check the results of tests that ran to the end
+    if failures.is_empty() {
+        for expectation in &case.expectations {
+            let placed = value(program, &expectation.value)
+                .and_then(|value| Ok((place(&emulator, program, &expectation.location)?, value)));
+            let (name, actual, expected) = match placed {
+                Ok((Place::Register(register), value)) => (
+                    format!("r{}", register),
+                    emulator.registers[register],
+                    value,
+                ),
+                Ok((Place::Memory(address), value)) => {
+                    (format!("[{}]", address), emulator.memory[address], value)
+                }
+                Err(message) => {
+                    failures.push(failure(&expectation.span, message));
+                    continue;
+                }
+            };
+
+            if actual != expected {
+                failures.push(failure(
+                    &expectation.span,
+                    format!("Expected {} to be {}, but it is {}", name, expected, actual),
+                ));
+            }
+        }
+    }
+
+    TestResult {
+        name: case.name.clone(),
+        cycles: emulator.cycles,
+        failures,
+    }
+}
+
+/// A number or define as a byte, negative numbers in two's complement
+fn value(program: &CompiledProgram, text: &str) -> Result<u8, String> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = match program.defines.get(text) {
+        Some(value) => *value as i64,
+        None => match parse_number(digits) {
+            Ok(value) if negative => -(value as i64),
+            Ok(value) => value as i64,
+            Err(_) => return Err(format!("Unknown define `{}`", text)),
+        },
+    };
+
+    match value {
+        -128..=255 => Ok(value as u8),
+        _ => Err(format!("Value {} does not fit in a byte", value)),
+    }
+}
+
+/// A location resolved on the target
+enum Place {
+    Register(usize),
+    Memory(usize),
+}
+
+/// Resolve a location, checking it exists on the target
+fn place<I>(
+    emulator: &Emulator<I>,
+    program: &CompiledProgram,
+    location: &Location,
+) -> Result<Place, String> {
+    match location {
+        Location::Register(register) if (*register as usize) < emulator.registers.len() => {
+            Ok(Place::Register(*register as usize))
+        }
+        Location::Register(register) => Err(format!(
+            "{} has no register r{}",
+            program.target.to_str(),
+            register
+        )),
+        Location::Memory(address) => {
+            let resolved = match program.defines.get(address) {
+                Some(value) => *value as usize,
+                None => {
+                    parse_number(address).map_err(|_| format!("Unknown define `{}`", address))?
+                }
+            };
+            match resolved < emulator.memory.len() {
+                true => Ok(Place::Memory(resolved)),
+                false => Err(format!("Address {} is outside of data memory", resolved)),
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::CompileError;
+
+/// How long to wait for an editor to finish a burst of writes before rebuilding
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Run `build` now and again whenever one of the files it read changes.
+///
+/// `build` returns the paths it read, which are watched until the next run. Only returns
+/// if watching fails.
+pub fn watch<F: FnMut() -> Vec<PathBuf>>(mut build: F) -> Result<(), CompileError> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(CompileError::WatchError)?;
+    let mut watched_dirs = HashSet::new();
+
+    loop {
+        let files: HashSet<PathBuf> = build().iter().map(|path| normalize(path)).collect();
+
+        // Editors often save by replacing the file, so watch the directories instead
+        let dirs: HashSet<PathBuf> = files
+            .iter()
+            .filter_map(|file| file.parent().map(Path::to_path_buf))
+            .collect();
+        for dir in watched_dirs.difference(&dirs) {
+            let _ = watcher.unwatch(dir);
+        }
+        for dir in dirs.difference(&watched_dirs) {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(CompileError::WatchError)?;
+        }
+        watched_dirs = dirs;
+
+        println!("Watching {} file(s) for changes...", files.len());
+
+        loop {
+            let event = receiver
+                .recv()
+                .map_err(|_| CompileError::WatchError(notify::Error::generic("watcher stopped")))?
+                .map_err(CompileError::WatchError)?;
+
+            if !matches!(event.kind, EventKind::Access(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|path| files.contains(&normalize(path)))
+            {
+                break;
+            }
+        }
+
+        while receiver.recv_timeout(DEBOUNCE).is_ok() {}
+    }
+}
+
+/// Canonicalize the directory of `path`, which still works after the file itself was removed
+fn normalize(path: &Path) -> PathBuf {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    match (fs::canonicalize(dir), path.file_name()) {
+        (Ok(dir), Some(name)) => dir.join(name),
+        _ => path.to_path_buf(),
+    }
+}
@@ -1,66 +1,6 @@
-use smc_assembler::{assembler::backends::Backend, compile, save::convert::convert_to_mc};
-use std::{fs, path::PathBuf};
-
-pub fn test_compilation(program_name: &str) {
-    use pretty_assertions::assert_eq;
-
-    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    path.push(format!("tests/batpu2/programs/{}.smc", program_name));
-
-    let mut expected_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    expected_path.push(format!("tests/batpu2/expected/{}.mc", program_name));
-
-    let source = fs::read_to_string(path).expect("Should be able to read the source file");
-    let result = compile(&source, Backend::BatPU2, false).expect("compilation should succeed");
-    let mc_output = convert_to_mc(result).expect("conversion to mc should succeed");
-
-    assert_eq!(
-        mc_output,
-        fs::read_to_string(expected_path).expect("Should be able to read the expected output")
-    );
-}
-
-#[test]
-fn compiles_connect4() {
-    test_compilation("connect4");
-}
-
-#[test]
-fn compiles_calculator() {
-    test_compilation("calculator");
-}
-
-#[test]
-fn compiles_2048() {
-    test_compilation("2048");
-}
-
-#[test]
-fn compiles_dvd() {
-    test_compilation("dvd");
-}
-
-#[test]
-fn compiles_gol() {
-    test_compilation("gol");
-}
-
-#[test]
-fn compiles_helloworld() {
-    test_compilation("helloworld");
-}
-
-#[test]
-fn compiles_maze() {
-    test_compilation("maze");
-}
-
-#[test]
-fn compiles_minesweeper() {
-    test_compilation("minesweeper");
-}
+use crate::assert_golden;
 
 #[test]
-fn compiles_tetris() {
-    test_compilation("tetris");
+fn compiles_programs() {
+    assert_golden("batpu2");
 }
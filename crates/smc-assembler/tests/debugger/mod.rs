@@ -0,0 +1,90 @@
+use pretty_assertions::assert_eq;
+use smc_assembler::{
+    CompileOptions,
+    assembler::backends::Backend,
+    compile_file,
+    debugger::{Command, Debugger, Stop},
+    emulator::NullIo,
+};
+
+use crate::{project_dir, write};
+
+fn debugger() -> Debugger<NullIo> {
+    let dir = project_dir("debugger");
+    write(
+        dir.join("count.smc"),
+        "define START 3\n  LDI r1 START\n  CAL .sub\n.loop\n  DEC r1\n  BRH ne .loop\n\
+         \x20 LDI r2 pixel_x\n  STR r2 r1\n  HLT\n.sub\n  LDI r3 7\n  RET\n",
+    );
+    let program = compile_file(
+        dir.join("count.smc"),
+        Backend::BatPU2,
+        &CompileOptions::default(),
+    )
+    .unwrap();
+    Debugger::new(program, NullIo)
+}
+
+fn execute(debugger: &mut Debugger<NullIo>, line: &str) -> String {
+    debugger.execute(line.parse::<Command>().unwrap()).unwrap()
+}
+
+#[test]
+fn stops_at_labels_and_lines() {
+    let mut debugger = debugger();
+    assert_eq!(
+        execute(&mut debugger, "break .loop"),
+        "Breakpoint 1 at 0002  count.smc:5  DEC r1"
+    );
+    // Line 4 only holds the label, so the breakpoint goes to the next line with code
+    assert_eq!(debugger.resolve_location("count.smc:4"), Ok(2));
+
+    assert_eq!(debugger.run(), Stop::Breakpoint(1));
+    assert_eq!(debugger.emulator.registers[3], 7);
+    assert_eq!(debugger.run(), Stop::Breakpoint(1));
+    assert_eq!(debugger.emulator.registers[1], 2);
+
+    execute(&mut debugger, "delete 1");
+    assert_eq!(debugger.run(), Stop::Halted);
+}
+
+#[test]
+fn steps_over_calls() {
+    let mut debugger = debugger();
+    execute(&mut debugger, "next");
+    assert_eq!(
+        execute(&mut debugger, "step"),
+        "0007  count.smc:11  LDI r3 7"
+    );
+    assert_eq!(
+        execute(&mut debugger, "bt"),
+        "#0 .sub  0007  count.smc:11  LDI r3 7\n#1 0001  count.smc:3  CAL .sub"
+    );
+    assert_eq!(debugger.frames(), vec![7, 1]);
+    assert_eq!(
+        execute(&mut debugger, "finish"),
+        "0002  count.smc:5  DEC r1"
+    );
+
+    execute(&mut debugger, "reset");
+    assert_eq!(
+        execute(&mut debugger, "next 2"),
+        "0002  count.smc:5  DEC r1"
+    );
+    assert_eq!(debugger.emulator.registers[3], 7);
+}
+
+#[test]
+fn stops_at_port_writes() {
+    let mut debugger = debugger();
+    execute(&mut debugger, "watch pixel_x");
+    assert_eq!(
+        execute(&mut debugger, "continue"),
+        "Watchpoint 1: wrote pixel_x 0\n0006  count.smc:9  HLT"
+    );
+    assert!(execute(&mut debugger, "ports").starts_with("240 pixel_x             0\n"));
+    assert_eq!(execute(&mut debugger, "x 0 3"), "00: 00 00 00");
+    assert!(
+        execute(&mut debugger, "r").starts_with("r0    0 0x00    r1    0 0x00    r2  240 0xf0")
+    );
+}
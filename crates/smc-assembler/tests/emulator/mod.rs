@@ -0,0 +1,252 @@
+use pretty_assertions::assert_eq;
+use smc_assembler::{
+    CompileOptions,
+    assembler::backends::Backend,
+    compile, compile_file,
+    emulator::{
+        Access, AccessKind, Emulator, EmulatorError, NullIo,
+        batpu2_io::Batpu2Io,
+        profiler::{Estimate, Profiler, worst_case_cycles},
+        trace::{TraceFormat, TraceStep, Tracer, first_divergence, parse_trace},
+    },
+};
+
+use crate::{project_dir, write};
+
+fn run(source: &str, target: Backend) -> Emulator {
+    let bytes = compile(source, target.clone(), false).expect("compilation should succeed");
+    let mut emulator = Emulator::new(target, &bytes);
+    while !emulator.halted() {
+        emulator.step().expect("program should run");
+    }
+    emulator
+}
+
+#[test]
+fn runs_batpu2_programs() {
+    // Multiply 7 by 6 with repeated addition in a subroutine
+    let emulator = run(
+        "  LDI r1 7\n  LDI r2 6\n  CAL .multiply\n  LDI r4 20\n  STR r4 r3 -1\n  HLT\n\
+         .multiply\n  LDI r3 0\n.loop\n  ADD r3 r1 r3\n  DEC r2\n  BRH ne .loop\n  RET\n",
+        Backend::BatPU2,
+    );
+
+    assert_eq!(emulator.registers[3], 42);
+    assert_eq!(emulator.memory[19], 42);
+    assert!(emulator.call_stack.is_empty());
+    assert_eq!(emulator.cycles, 3 + 1 + 6 * 3 + 1 + 3);
+}
+
+#[test]
+fn sets_batpu2_flags() {
+    let emulator = run("  LDI r1 200\n  ADI r1 100\n  HLT\n", Backend::BatPU2);
+    assert_eq!(emulator.registers[1], 44);
+    assert!(emulator.flags.carry && !emulator.flags.zero);
+
+    // `CMP` subtracts into r0, which stays zero
+    let emulator = run("  LDI r1 5\n  CMP r1 r1\n  HLT\n", Backend::BatPU2);
+    assert_eq!(emulator.registers[0], 0);
+    assert!(emulator.flags.carry && emulator.flags.zero);
+}
+
+#[test]
+fn maps_batpu2_ports() {
+    let bytes = compile(
+        "  LDI r1 pixel_x\n  LDI r2 9\n  STR r1 r2\n  LOD r1 r3 7\n  HLT\n",
+        Backend::BatPU2,
+        false,
+    )
+    .unwrap();
+    let mut emulator: Emulator<NullIo> = Emulator::new(Backend::BatPU2, &bytes);
+
+    let accesses: Vec<Access> = (0..5)
+        .flat_map(|_| emulator.step().unwrap().accesses)
+        .collect();
+    assert_eq!(
+        accesses,
+        [
+            Access {
+                kind: AccessKind::Write,
+                address: 240,
+                value: 9,
+                port: true,
+            },
+            Access {
+                kind: AccessKind::Read,
+                address: 247,
+                value: 0,
+                port: true,
+            },
+        ]
+    );
+    assert_eq!(emulator.step(), Err(EmulatorError::Halted));
+}
+
+#[test]
+fn runs_tau_programs() {
+    // Count r0 down from 3 in the right bank, skipping over the two byte `JMP` when done
+    let emulator = run(
+        "LDI R0 3\nLDI R1 7\nBKR\n.loop\nDEC R0\nSKP 0\nJMP .loop\nMST R1 R0\nBKL\nMLD R2 R0\nHLT\n",
+        Backend::TauAnalyzersNone,
+    );
+
+    assert_eq!(emulator.registers, [0, 7, 0, 0]);
+    assert_eq!(emulator.memory[256], 7);
+    assert_eq!(emulator.bank, 0);
+}
+
+#[test]
+fn runs_example_programs() {
+    for program in ["helloworld", "dvd", "gol", "2048"] {
+        let program = compile_file(
+            format!("./tests/batpu2/programs/{}.smc", program),
+            Backend::BatPU2,
+            &CompileOptions::default(),
+        )
+        .unwrap();
+        let mut emulator: Emulator<NullIo> = Emulator::new(Backend::BatPU2, &program.bytes);
+
+        for _ in 0..100_000 {
+            match emulator.step() {
+                Ok(_) | Err(EmulatorError::Halted) => {}
+                Err(error) => panic!("{}", error),
+            }
+        }
+    }
+}
+
+#[test]
+fn profiles_cycles() {
+    let dir = project_dir("profile");
+    write(
+        dir.join("frames.smc"),
+        "  LDI r1 3\n  LDI r2 buffer_screen\n.frame\n  CAL .wait\n  STR r2 r0\n  DEC r1\n  \
+         BRH ne .frame\n  HLT\n.wait\n  LDI r3 4\n.spin\n  DEC r3\n  BRH ne .spin\n  RET\n\
+         .clear\n  LDI r3 0\n  CAL .nothing\n  RET\n.nothing\n  RET\n  CAL .clear\n",
+    );
+    let program = compile_file(
+        dir.join("frames.smc"),
+        Backend::BatPU2,
+        &CompileOptions::default(),
+    )
+    .unwrap();
+
+    let mut emulator = Emulator::with_io(Backend::BatPU2, &program.bytes, Batpu2Io::default());
+    let mut profiler = Profiler::new(Backend::BatPU2);
+    while !emulator.halted() {
+        let step = emulator.step().unwrap();
+        profiler.record(&step, &emulator);
+    }
+    let profile = profiler.profile(&program);
+
+    assert_eq!(profile.cycles, emulator.cycles);
+    assert_eq!(profile.labels[0].name, ".spin");
+    assert_eq!(profile.labels[0].cycles, 3 * (4 * 2 + 1));
+    let wait = &profile.subroutines[0];
+    assert_eq!(
+        (wait.name.as_str(), wait.calls, wait.cycles),
+        (".wait", 3, 3 * 10)
+    );
+    assert_eq!(profile.mix[0].mnemonic, "ADI");
+    // Frames end at the store, after the setup or the branch back and the wait
+    assert_eq!(profile.frames, vec![2 + 1 + 10 + 1; 3]);
+
+    assert_eq!(
+        worst_case_cycles(&program),
+        vec![
+            Estimate {
+                name: ".wait".to_string(),
+                address: 7,
+                cycles: None,
+            },
+            Estimate {
+                name: ".clear".to_string(),
+                address: 11,
+                cycles: Some(4),
+            },
+            Estimate {
+                name: ".nothing".to_string(),
+                address: 14,
+                cycles: Some(1),
+            },
+        ]
+    );
+}
+
+#[test]
+fn costs_tau_instructions_by_size() {
+    let op = |source: &str| {
+        let program = compile(source, Backend::TauAnalyzersNone, false).unwrap();
+        let emulator: Emulator = Emulator::new(Backend::TauAnalyzersNone, &program);
+        Backend::TauAnalyzersNone.cycle_cost(emulator.current().unwrap())
+    };
+    assert_eq!(op("LDI R1 5\n"), 2);
+    assert_eq!(op("CLR R1\n"), 2);
+    assert_eq!(op("INC R1\n"), 1);
+}
+
+fn trace(source: &str) -> Vec<TraceStep> {
+    let bytes = compile(source, Backend::BatPU2, false).unwrap();
+    let mut emulator = Emulator::with_io(
+        Backend::BatPU2,
+        &bytes,
+        Batpu2Io::new(3, Default::default()),
+    );
+    let mut tracer = Tracer::default();
+    let mut steps = Vec::new();
+    while !emulator.halted() {
+        let step = emulator.step().unwrap();
+        steps.push(tracer.record(&step, &emulator));
+    }
+    steps
+}
+
+#[test]
+fn traces_steps() {
+    let steps = trace("  LDI r1 rng\n  LOD r1 r2\n  STR r0 r2 7\n  STR r1 r2 -1\n  HLT\n");
+    let text: Vec<String> = steps.iter().map(TraceStep::to_string).collect();
+    assert_eq!(text[0], "0000 LDI r1 254 ; r1=254");
+    assert!(text[1].starts_with("0001 LOD r1 r2 ; r2="), "{}", text[1]);
+    assert!(text[1].contains(" <254="), "{}", text[1]);
+    assert!(
+        text[2].starts_with("0002 STR r0 r2 7 ; [7]="),
+        "{}",
+        text[2]
+    );
+    assert!(
+        text[3].starts_with("0003 STR r1 r2 -1 ; >253="),
+        "{}",
+        text[3]
+    );
+
+    // Both formats read back the same steps
+    let json: Vec<String> = steps.iter().map(TraceStep::to_json).collect();
+    assert_eq!(
+        parse_trace(&json.join("\n"), TraceFormat::Json).unwrap(),
+        steps
+    );
+    assert_eq!(
+        parse_trace(&format!("# seed 3\n{}", text.join("\n")), TraceFormat::Text).unwrap(),
+        steps
+    );
+}
+
+#[test]
+fn finds_first_divergence() {
+    let expected = trace("  LDI r1 5\n  ADI r1 1\n  STR r0 r1 3\n  HLT\n");
+    let actual = trace("  LDI r1 5\n  ADI r1 2\n  STR r0 r1 3\n  HLT\n");
+    assert_eq!(first_divergence(&expected, &expected), None);
+
+    let divergence = first_divergence(&expected, &actual).unwrap();
+    assert_eq!(divergence.index, 1);
+    assert_eq!(
+        divergence.differences,
+        vec!["instruction `ADI r1 1` != `ADI r1 2`", "registers differ"]
+    );
+
+    // Traces from other tools may leave out the instructions
+    let recorded = parse_trace("0 ; r1=5\n1 ; r1=6\n2 ; [3]=6", TraceFormat::Text).unwrap();
+    let divergence = first_divergence(&recorded, &expected).unwrap();
+    assert_eq!(divergence.index, 3);
+    assert_eq!(divergence.differences, vec!["the first trace ended"]);
+}
@@ -0,0 +1,82 @@
+use smc_assembler::{
+    assembler::backends::Backend,
+    compile,
+    formatter::{FormatOptions, format_source},
+    lexer::Lexer,
+};
+use std::{fs, path::PathBuf};
+
+/// Formatting must be idempotent and must not change the assembled program
+pub fn test_format_roundtrip(directory: &str, program_name: &str, target: Backend) {
+    use pretty_assertions::assert_eq;
+
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push(format!("tests/{}/programs/{}", directory, program_name));
+
+    let source = fs::read_to_string(path).expect("Should be able to read the source file");
+    let options = FormatOptions::default();
+    let formatted = format_source(&source, &options);
+
+    assert_eq!(source.lines().count(), formatted.lines().count());
+    assert_eq!(formatted, format_source(&formatted, &options));
+    assert_eq!(
+        compile(&source, target.clone(), false).expect("compilation should succeed"),
+        compile(&formatted, target, false).expect("formatted compilation should succeed")
+    );
+}
+
+#[test]
+fn formats_mixed_style() {
+    use pretty_assertions::assert_eq;
+
+    let source = "\
+define LEFT 1
+   .loop   // main loop
+ldi R1 , LEFT // load
+    brh EQ .loop
+/* block
+   comment */
+  ADD r1 r2 r3 # done
+LDI r14 \"t\"
+";
+
+    let expected = "\
+define LEFT 1
+.loop // main loop
+  LDI r1 LEFT // load
+  BRH eq .loop
+/* block
+   comment */
+  ADD r1 r2 r3 # done
+  LDI r14 \"t\"
+";
+
+    assert_eq!(format_source(source, &FormatOptions::default()), expected);
+}
+
+#[test]
+fn keeps_char_literals_whole() {
+    let source = "LDI r1 'A'";
+    let span = Lexer::new(source).nth(2).unwrap().unwrap().span;
+
+    assert_eq!(span.snippet(source), "'A'");
+    assert_eq!(
+        format_source(source, &FormatOptions::default()),
+        "  LDI r1 'A'"
+    );
+}
+
+#[test]
+fn formats_2048() {
+    test_format_roundtrip("batpu2", "2048.smc", Backend::BatPU2);
+}
+
+#[test]
+fn formats_tetris() {
+    test_format_roundtrip("batpu2", "tetris.smc", Backend::BatPU2);
+}
+
+#[test]
+fn formats_tau_ball() {
+    test_format_roundtrip("tau", "ball.tasm", Backend::TauAnalyzersNone);
+}
@@ -0,0 +1,109 @@
+use smc_assembler::{
+    assembler::{AssemblerError, LabelMap, backends::Backend},
+    compile,
+    lexer::token::{Condition, Operation, Register, Span},
+    parser::{
+        DefineMap, OperandKind, operand_kinds,
+        operations::{Address, OperationWithArgs},
+    },
+};
+use strum::VariantArray;
+
+/// Every lowering offered for an unsupported operation must assemble on the same backend
+pub fn test_lowerings(target: Backend, operations: Vec<OperationWithArgs>) {
+    let defines = DefineMap::new();
+    let labels = LabelMap::new();
+    let span = Span::new(0, 0);
+
+    for op in operations {
+        assert!(
+            matches!(
+                target.assemble_operation(&defines, &labels, op.clone(), span.clone()),
+                Err(AssemblerError::UnsupportedOperation(..))
+            ),
+            "{} should be unsupported",
+            op
+        );
+
+        let lowered = target
+            .lower_unsupported(&op)
+            .unwrap_or_else(|| panic!("{} should have a lowering", op));
+
+        for lowered_op in lowered {
+            target
+                .assemble_operation(&defines, &labels, lowered_op.clone(), span.clone())
+                .unwrap_or_else(|err| panic!("{} lowered to {}: {}", op, lowered_op, err));
+        }
+    }
+}
+
+#[test]
+fn lowers_batpu2() {
+    use OperationWithArgs::*;
+
+    let (r1, r2) = (Register(1), Register(2));
+    test_lowerings(
+        Backend::BatPU2,
+        vec![
+            Add2(r1, r2),
+            Sub2(r1, r2),
+            And2(r1, r2),
+            Xor2(r1, r2),
+            Or2(r1, r2),
+            Cpy2(r1, r2),
+            Rsh1(r1),
+            Inv1(r1),
+            Clr1(r1),
+        ],
+    );
+}
+
+#[test]
+fn lowers_tau() {
+    use OperationWithArgs::*;
+
+    let (r1, r2, r3) = (Register(1), Register(2), Register(3));
+    test_lowerings(
+        Backend::TauAnalyzersNone,
+        vec![
+            Nop,
+            Add3(r1, r2, r3),
+            Sub3(r1, r2, r1),
+            And3(r1, r2, r2),
+            Xor3(r1, r2, r3),
+            Mov2(r1, r2),
+            Rsh2(r1, r2),
+            Not2(r1, r1),
+            Lsh2(r1, r2),
+            Brh(Condition::Equal, Address::Value(4)),
+            Brh(Condition::NotEqual, Address::Value(4)),
+        ],
+    );
+}
+
+/// `Backend::supports` must agree with what assembles, so completion offers only those
+#[test]
+fn supports_what_assembles() {
+    for target in Backend::VARIANTS {
+        for op in Operation::VARIANTS {
+            let operands: Vec<&str> = operand_kinds(op)
+                .iter()
+                .map(|kind| match kind {
+                    OperandKind::Register => "r1",
+                    OperandKind::Immediate => "1",
+                    OperandKind::Address => ".start",
+                    OperandKind::Condition => "eq",
+                    OperandKind::Offset => "0",
+                    OperandKind::Skip => "0",
+                })
+                .collect();
+
+            // Operations such as `ADD` take fewer operands on some targets
+            let assembles = (0..=operands.len()).any(|count| {
+                let line = [&[op.mnemonic()], &operands[..count]].concat().join(" ");
+                compile(&format!(".start\n{}", line), target.clone(), false).is_ok()
+            });
+            assert_eq!(target.supports(op), assembles, "{:?} on {:?}", op, target);
+        }
+    }
+}
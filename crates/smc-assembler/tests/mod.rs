@@ -1,2 +1,49 @@
 pub mod batpu2;
+pub mod debugger;
+pub mod emulator;
+pub mod format;
+pub mod lowering;
+pub mod output;
+pub mod project;
+pub mod roundtrip;
+pub mod schematic;
+pub mod screen;
 pub mod tau;
+pub mod testing;
+
+use std::{env, fs, path::PathBuf, process};
+
+use smc_assembler::testing::golden::{GoldenOptions, Outcome, check_golden};
+
+/// A fresh directory for a test to write its project into
+pub fn project_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("smc-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("temp dir should be writable");
+    dir
+}
+
+pub fn write(path: PathBuf, text: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, text).unwrap();
+}
+
+/// Check the outputs of the programs in `tests/<dir>` against `tests/<dir>/expected`.
+///
+/// Update them with `smc-assembler check-golden tests/<dir> --bless`.
+pub fn assert_golden(dir: &str) {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(dir);
+    let results = check_golden(&dir, &GoldenOptions::default()).unwrap();
+    assert!(!results.is_empty(), "{} holds no programs", dir.display());
+
+    let failures: Vec<String> = results
+        .into_iter()
+        .filter_map(|result| match result.outcome {
+            Outcome::Failed(message) => Some(format!("{}: {}", result.expected.display(), message)),
+            _ => None,
+        })
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,155 @@
+use std::fs;
+
+use pretty_assertions::assert_eq;
+use smc_assembler::{
+    CompileError, CompileOptions,
+    assembler::{AssemblerError, backends::Backend},
+    compile, compile_file, compile_to_file,
+    save::{Endianness, SaveOptions},
+    sources::map::SourceMap,
+};
+
+use crate::{project_dir, write};
+
+#[test]
+fn writes_every_output_from_one_compile() {
+    let dir = project_dir("outputs");
+    write(
+        dir.join("count.smc"),
+        "define START 3\n  LDI r1 START\n.loop\n  DEC r1 // count down\n  BRH ne .loop\n  HLT\n",
+    );
+
+    let outputs = [dir.join("count.mc"), dir.join("count.lst")];
+    compile_to_file(
+        dir.join("count.smc"),
+        &outputs,
+        Backend::BatPU2,
+        &CompileOptions::default(),
+        &SaveOptions::default(),
+    )
+    .expect("compilation should succeed");
+
+    assert_eq!(fs::read_to_string(&outputs[0]).unwrap().lines().count(), 4);
+    assert_eq!(
+        fs::read_to_string(&outputs[1]).unwrap(),
+        "; batpu2-mattbatwings-none
+0000  81 03  count.smc:2  LDI r1 START
+      .loop
+0001  91 ff  count.smc:4  DEC r1  ; ADI r1 -1
+0002  b4 01  count.smc:5  BRH ne .loop
+0003  10 00  count.smc:6  HLT
+"
+    );
+}
+
+#[test]
+fn writes_memory_images() {
+    let dir = project_dir("memory-images");
+    write(dir.join("program.smc"), "LDI r1 3\nHLT\n");
+
+    let outputs =
+        ["bin", "hex", "img", "memb", "memh"].map(|ext| dir.join(format!("program.{ext}")));
+    compile_to_file(
+        dir.join("program.smc"),
+        &outputs,
+        Backend::BatPU2,
+        &CompileOptions::default(),
+        &SaveOptions {
+            format: None,
+            endianness: Endianness::Little,
+            ..Default::default()
+        },
+    )
+    .expect("compilation should succeed");
+
+    let read = |i: usize| fs::read_to_string(&outputs[i]).unwrap();
+    assert_eq!(fs::read(&outputs[0]).unwrap(), [0x03, 0x81, 0x00, 0x10]);
+    assert_eq!(read(1), ":040000008103100068\n:00000001FF\n");
+    assert_eq!(read(2), "v2.0 raw\n8103 1000\n");
+    assert_eq!(read(3), "1000000100000011\n0001000000000000\n");
+    assert_eq!(read(4), "8103\n1000\n");
+}
+
+#[test]
+fn rejects_programs_larger_than_memory() {
+    let source = "NOP\n".repeat(1024) + "HLT\nHLT\n";
+    let errors = match compile(&source, Backend::BatPU2, false) {
+        Err(CompileError::AssembleError(errors)) => errors,
+        other => panic!("expected the program to be too large, got {:?}", other),
+    };
+
+    match errors.as_slice() {
+        [AssemblerError::ProgramTooLarge(span, 1026, 1024)] => {
+            assert_eq!(span.location(&source), (1025, 1))
+        }
+        other => panic!("expected a single ProgramTooLarge error, got {:?}", other),
+    }
+
+    // Tau instructions with an immediate take two bytes, so the last one straddles the end
+    let source = "HLT\n".repeat(1023) + "LDI r1 1\n";
+    let errors = match compile(&source, Backend::TauAnalyzersNone, false) {
+        Err(CompileError::AssembleError(errors)) => errors,
+        other => panic!("expected the program to be too large, got {:?}", other),
+    };
+
+    match errors.as_slice() {
+        [AssemblerError::ProgramTooLarge(span, 1025, 1024)] => {
+            assert_eq!(span.location(&source), (1024, 1))
+        }
+        other => panic!("expected a single ProgramTooLarge error, got {:?}", other),
+    }
+}
+
+#[test]
+fn reports_memory_usage() {
+    let dir = project_dir("size-report");
+    write(dir.join("program.smc"), "LDI r1 3\nHLT\n");
+
+    let program = compile_file(
+        dir.join("program.smc"),
+        Backend::BatPU2,
+        &CompileOptions::default(),
+    )
+    .expect("compilation should succeed");
+
+    assert_eq!(
+        program.size_report(),
+        "instruction memory: 2 of 1024 words used, 1022 free"
+    );
+}
+
+#[test]
+fn maps_addresses_to_source() {
+    let dir = project_dir("source-map");
+    write(dir.join("lib.tasm"), "\n  CLR r1\n");
+    write(
+        dir.join("main.tasm"),
+        "  LDI r0 5\ninclude \"lib.tasm\"\n  CMP r0 r1\n",
+    );
+
+    let output = dir.join("main.map");
+    compile_to_file(
+        dir.join("main.tasm"),
+        &[&output],
+        Backend::TauAnalyzersNone,
+        &CompileOptions::default(),
+        &SaveOptions::default(),
+    )
+    .expect("compilation should succeed");
+    let map = SourceMap::from_json(&fs::read_to_string(output).unwrap()).unwrap();
+
+    assert_eq!(map.target, "tau-analyzers-none");
+    assert_eq!(map.files.len(), 2);
+    assert_eq!(
+        map.entries
+            .iter()
+            .map(|entry| (entry.address, entry.size, entry.file, entry.line))
+            .collect::<Vec<_>>(),
+        [(0, 2, 0, 1), (2, 2, 1, 2), (4, 1, 0, 3)]
+    );
+    // Both bytes of `LDI` map to it, `CLR` is encoded as `ANI`
+    assert_eq!(map.entry(1).unwrap().operation, "LDI r0 5");
+    assert_eq!(map.entries[1].expanded.as_deref(), Some("ANI r1 0"));
+    assert_eq!(map.entries[2].expanded, None);
+    assert_eq!(map.line_address(0, 2), Some(4));
+}
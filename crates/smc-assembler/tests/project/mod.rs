@@ -0,0 +1,253 @@
+use smc_assembler::{
+    CompileOptions,
+    assembler::backends::Backend,
+    compile, compile_file,
+    lexer::{LexerError, token::Token},
+    parser::DefineMap,
+    project::{MANIFEST_FILE_NAME, Manifest, target_pragma},
+    save::{SaveOptions, convert::convert_to_mc, memory::Format, save_program_if_changed},
+    sources::Sources,
+};
+use std::{fs, path::Path};
+
+use crate::{project_dir, write};
+
+#[test]
+fn reads_target_pragma() {
+    let source = "LDI r1 2\n// smc-target: tau-analyzers-none\nHLT\n";
+    let (name, span) = target_pragma(source).expect("pragma should be found");
+
+    assert_eq!(name, "tau-analyzers-none");
+    assert_eq!(span.snippet(source), "tau-analyzers-none");
+    assert_eq!(Backend::from_name(name), Some(Backend::TauAnalyzersNone));
+
+    assert!(target_pragma("define smc-target: 1\n").is_none());
+}
+
+#[test]
+fn selects_backend_rule() {
+    let manifest: Manifest = toml::from_str(
+        r#"
+        [[backends]]
+        files = "tau/**/*.tasm"
+        target = "tau-analyzers-none"
+
+        [[backends]]
+        files = "*.smc"
+        target = "batpu2-mattbatwings-none"
+        "#,
+    )
+    .expect("manifest should parse");
+
+    let root = Path::new("/project");
+    let target = |path: &str| {
+        manifest
+            .backend_rule(root, Path::new(path))
+            .expect("globs should be valid")
+            .map(|rule| rule.target.as_str())
+    };
+
+    assert_eq!(
+        target("/project/tau/games/ball.tasm"),
+        Some("tau-analyzers-none")
+    );
+    assert_eq!(
+        target("/project/games/tetris.smc"),
+        Some("batpu2-mattbatwings-none")
+    );
+    assert_eq!(target("/project/ball.tasm"), None);
+}
+
+#[test]
+fn includes_files() {
+    let dir = project_dir("includes");
+    write(
+        dir.join("main.smc"),
+        "include \"lib/consts.smc\"\ninclude 'lib/consts.smc'\nLDI r1 VALUE\nCAL .helper\nHLT\ninclude \"helper.smc\"\n",
+    );
+    write(dir.join("lib/consts.smc"), "define VALUE 5\n");
+    write(dir.join("inc/helper.smc"), ".helper\n  ADI r1 1\n  RET\n");
+
+    let options = CompileOptions {
+        include_dirs: vec![dir.join("inc")],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        compile_file(dir.join("main.smc"), Backend::BatPU2, &options)
+            .expect("includes resolve")
+            .bytes,
+        compile(
+            "define VALUE 5\nLDI r1 VALUE\nCAL .helper\nHLT\n.helper\nADI r1 1\nRET\n",
+            Backend::BatPU2,
+            false
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn rejects_recursive_include() {
+    let dir = project_dir("recursive-include");
+    write(dir.join("a.smc"), "include \"b.smc\"\nHLT\n");
+    write(
+        dir.join("b.smc"),
+        "include \"a.smc\"\ninclude \"missing.smc\"\n",
+    );
+
+    let (sources, tokens) = Sources::load(dir.join("a.smc"), &[]).unwrap();
+    let errors: Vec<_> = tokens
+        .into_iter()
+        .filter_map(|token| match token {
+            Err(LexerError::InvalidInclude(span, path, _)) => Some((span.source(), path)),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(sources.files.len(), 2);
+    assert_eq!(
+        errors,
+        [(1, "a.smc".to_string()), (1, "missing.smc".to_string())]
+    );
+}
+
+#[test]
+fn maps_included_spans_to_the_include() {
+    let dir = project_dir("include-sites");
+    write(dir.join("main.smc"), "HLT\ninclude \"a\"\n");
+    write(dir.join("inc/a"), "LDI r1 \"B\"\n");
+    write(
+        dir.join(MANIFEST_FILE_NAME),
+        r#"
+        [[targets]]
+        entry = "main.smc"
+        backend = "batpu2-mattbatwings-none"
+        outputs = ["main.mc"]
+        include = ["inc"]
+        "#,
+    );
+
+    let manifest = Manifest::load(dir.join(MANIFEST_FILE_NAME)).unwrap();
+    let (sources, tokens) =
+        Sources::load(dir.join("main.smc"), &manifest.include_dirs(&dir)).unwrap();
+    let string = tokens
+        .iter()
+        .flatten()
+        .find(|token| token.token == Token::String("B".to_string()))
+        .expect("a one character include path resolves and `\"B\"` lexes as a string");
+
+    assert_eq!(string.span.source(), 1);
+    assert_eq!(
+        sources
+            .span_in_entry(&string.span)
+            .snippet(&sources.files[0].text),
+        "include \"a\""
+    );
+    assert_eq!(
+        compile("LDI r1 \"B\"", Backend::BatPU2, false).unwrap(),
+        compile("LDI r1 'B'", Backend::BatPU2, false).unwrap()
+    );
+}
+
+#[test]
+fn builds_manifest_targets() {
+    let dir = project_dir("build");
+    write(
+        dir.join("games/count.smc"),
+        "define STEP 1\nLDI r1 STEP\nHLT\n",
+    );
+    write(
+        dir.join(MANIFEST_FILE_NAME),
+        r#"
+        [[targets]]
+        entry = "games/count.smc"
+        backend = "batpu2-mattbatwings-none"
+        outputs = ["build/count.mc"]
+        format = "batpu2-instruction-memory"
+        defines = { STEP = 2 }
+        "#,
+    );
+
+    let manifest = Manifest::load(dir.join(MANIFEST_FILE_NAME)).expect("manifest should parse");
+    let target = &manifest.targets[0];
+    assert_eq!(target.name(), "count");
+
+    target
+        .build(&dir, &DefineMap::from([("STEP".to_string(), 3.0)]))
+        .expect("target should build");
+
+    let expected = compile("LDI r1 3\nHLT\n", Backend::BatPU2, false).unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.join("build/count.mc")).unwrap(),
+        convert_to_mc(expected).unwrap()
+    );
+}
+
+#[test]
+fn builds_patches_from_manifest() {
+    let dir = project_dir("build-patch");
+    write(dir.join("count.smc"), "LDI r1 3\nHLT\n");
+    write(
+        dir.join("built.mc"),
+        &convert_to_mc(compile("LDI r1 2\nHLT\n", Backend::BatPU2, false).unwrap()).unwrap(),
+    );
+    write(
+        dir.join(MANIFEST_FILE_NAME),
+        r#"
+        [[targets]]
+        entry = "count.smc"
+        backend = "batpu2-mattbatwings-none"
+        outputs = ["patch.mcfunction"]
+        format = "batpu2-instruction-memory"
+        diff_from = "built.mc"
+        "#,
+    );
+
+    let manifest = Manifest::load(dir.join(MANIFEST_FILE_NAME)).expect("manifest should parse");
+    manifest.targets[0]
+        .build(&dir, &DefineMap::new())
+        .expect("target should build");
+
+    // Only the lowest bit of the immediate differs
+    let patch = fs::read_to_string(dir.join("patch.mcfunction")).unwrap();
+    assert_eq!(patch.lines().count(), 1, "{}", patch);
+    assert!(patch.contains("minecraft:repeater"));
+}
+
+#[test]
+fn skips_unchanged_outputs() {
+    let dir = project_dir("output-cache");
+    let options = SaveOptions {
+        format: Some(Format::Batpu2InstructionMemory),
+        ..Default::default()
+    };
+
+    write(dir.join("program.smc"), "LDI r1 1\n");
+    let program = |source: &str| {
+        fs::write(dir.join("program.smc"), source).unwrap();
+        compile_file(
+            dir.join("program.smc"),
+            Backend::BatPU2,
+            &CompileOptions::default(),
+        )
+        .unwrap()
+    };
+
+    // Schematics are written differently every time, so they are compared by their program
+    for output in [dir.join("program.mc"), dir.join("program.schem")] {
+        let save = |source: &str| save_program_if_changed(&output, &program(source), &options);
+
+        assert!(save("LDI r1 1\n").unwrap());
+        fs::remove_file(&output).unwrap();
+
+        assert!(save("LDI r1 1 // comment\n").unwrap());
+        assert!(output.exists(), "a deleted output is written again");
+
+        let written = fs::read(&output).unwrap();
+        assert!(!save("LDI r1 1\n").unwrap(), "{}", output.display());
+        assert_eq!(fs::read(&output).unwrap(), written);
+
+        assert!(save("LDI r1 2\n").unwrap());
+        assert_ne!(fs::read(&output).unwrap(), written);
+    }
+}
@@ -0,0 +1,197 @@
+use smc_assembler::{
+    assembler::backends::Backend,
+    compile,
+    disassembler::{disassemble, to_source},
+    lexer::{Lexer, LexerError, token::Register},
+    parser::operations::{Immediate, OperationWithArgs, SkipFlag},
+};
+
+/// SplitMix64, enough to pick programs reproducibly without a dependency
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len() as u64) as usize]
+    }
+}
+
+fn batpu2_instruction(rng: &mut Rng, labels: usize) -> String {
+    let mut r = || format!("r{}", rng.below(16));
+    let (a, b, c) = (r(), r(), r());
+    let label = format!(".l{}", rng.below(labels as u64));
+    let immediate = rng.below(256);
+    let offset = rng.below(16) as i64 - 8;
+
+    match rng.below(12) {
+        0 => rng.pick(&["NOP", "HLT", "RET"]).to_string(),
+        1..=3 => format!(
+            "{} {} {} {}",
+            rng.pick(&["ADD", "SUB", "NOR", "AND", "XOR"]),
+            a,
+            b,
+            c
+        ),
+        4 => format!("RSH {} {}", a, b),
+        5 | 6 => format!("{} {} {}", rng.pick(&["LDI", "ADI"]), a, immediate),
+        7 => format!("{} {}", rng.pick(&["JMP", "CAL"]), label),
+        8 => format!("BRH {} {}", rng.pick(&["eq", "ne", "ge", "lt"]), label),
+        9 => format!("{} {} {} {}", rng.pick(&["LOD", "STR"]), a, b, offset),
+        10 => format!(
+            "{} {} {}",
+            rng.pick(&["CMP", "MOV", "LSH", "NOT", "NEG"]),
+            a,
+            b
+        ),
+        _ => format!("{} {}", rng.pick(&["INC", "DEC"]), a),
+    }
+}
+
+fn tau_instruction(rng: &mut Rng, labels: usize) -> String {
+    let mut r = || format!("R{}", rng.below(4));
+    let (a, b) = (r(), r());
+    let label = format!(".l{}", rng.below(labels as u64));
+    let immediate = rng.below(256);
+
+    match rng.below(8) {
+        0 => rng.pick(&["BKL", "BKR", "RET", "HLT"]).to_string(),
+        1 | 2 => format!(
+            "{} {} {}",
+            rng.pick(&[
+                "ADD", "SUB", "XOR", "AND", "OR", "CMP", "CPY", "ADC", "MLD", "MST", "PLD", "PST",
+            ]),
+            a,
+            b
+        ),
+        3 => format!("{} {}", rng.pick(&["RSH", "INV", "INC", "DEC"]), a),
+        4 | 5 => format!(
+            "{} {} {}",
+            rng.pick(&["LDI", "ADI", "CPI", "ANI"]),
+            a,
+            immediate
+        ),
+        6 => format!("{} {}", rng.pick(&["JMP", "CAL"]), label),
+        _ => format!("SKP {}", rng.pick(&["!", "0", "!0", "-", "!-", ""])),
+    }
+}
+
+/// A program of random instructions, with labels at random lines
+fn random_program(rng: &mut Rng, instruction: fn(&mut Rng, usize) -> String) -> String {
+    let length = 1 + rng.below(40) as usize;
+    let labels = 1 + rng.below(4) as usize;
+    let mut lines: Vec<String> = (0..length).map(|_| instruction(rng, labels)).collect();
+
+    for label in (0..labels).rev() {
+        let at = rng.below(lines.len() as u64 + 1) as usize;
+        lines.insert(at, format!(".l{}", label));
+    }
+    lines.join("\n")
+}
+
+fn assert_round_trips(target: Backend, instruction: fn(&mut Rng, usize) -> String) {
+    let mut rng = Rng(0x5eed);
+    for _ in 0..300 {
+        let program = random_program(&mut rng, instruction);
+        let bytes = compile(&program, target.clone(), false)
+            .unwrap_or_else(|err| panic!("{}\n\n{}", err, program));
+
+        let disassembled = to_source(&disassemble(&target, &bytes)).unwrap();
+        let reassembled = compile(&disassembled, target.clone(), false)
+            .unwrap_or_else(|err| panic!("{}\n\n{}\n\nfrom\n\n{}", err, disassembled, program));
+        assert_eq!(
+            reassembled, bytes,
+            "{}\n\nfrom\n\n{}",
+            disassembled, program
+        );
+    }
+}
+
+#[test]
+fn batpu2_programs_round_trip() {
+    assert_round_trips(Backend::BatPU2, batpu2_instruction);
+}
+
+#[test]
+fn tau_programs_round_trip() {
+    assert_round_trips(Backend::TauAnalyzersNone, tau_instruction);
+}
+
+#[test]
+fn reports_non_ascii_characters_whole() {
+    let source = "LDI r1 é\nHLT";
+    let errors: Vec<LexerError> = Lexer::new(source).filter_map(Result::err).collect();
+
+    let [LexerError::UnexpectedCharacter(span, 'é')] = errors.as_slice() else {
+        panic!("{:?}", errors);
+    };
+    assert_eq!(span.snippet(source), "é");
+    assert!(
+        span.format_error("main.smc", source, "Unexpected")
+            .contains('é')
+    );
+}
+
+#[test]
+fn parses_skips_without_operands() {
+    let bytes = compile("SKP\nINC R1\nSKP -", Backend::TauAnalyzersNone, false).unwrap();
+    let ops: Vec<String> = disassemble(&Backend::TauAnalyzersNone, &bytes)
+        .iter()
+        .map(|instruction| instruction.op.as_ref().unwrap().to_string())
+        .collect();
+    assert_eq!(ops, ["SKP", "INC r1", "SKP -"]);
+}
+
+fn tau_ops(source: &str) -> Vec<OperationWithArgs> {
+    let bytes = compile(source, Backend::TauAnalyzersNone, false).unwrap();
+    disassemble(&Backend::TauAnalyzersNone, &bytes)
+        .into_iter()
+        .map(|instruction| instruction.op.unwrap())
+        .collect()
+}
+
+#[test]
+fn bare_skip_always_skips() {
+    // The instruction after a bare SKP is its own, not read as a skip flag
+    assert_eq!(
+        tau_ops("SKP\nINC R1"),
+        [
+            OperationWithArgs::Skp(SkipFlag::Always),
+            OperationWithArgs::Inc1(Register(1))
+        ]
+    );
+}
+
+#[test]
+fn lone_minus_skips_if_negative() {
+    assert_eq!(
+        tau_ops("SKP -\nHLT"),
+        [
+            OperationWithArgs::Skp(SkipFlag::IfNegative),
+            OperationWithArgs::Hlt
+        ]
+    );
+    // Also at the very end of the input, where it used to be an invalid number
+    assert_eq!(
+        tau_ops("SKP -"),
+        [OperationWithArgs::Skp(SkipFlag::IfNegative)]
+    );
+    // A minus sign directly before a number is still part of it
+    assert_eq!(
+        tau_ops("LDI R1 -5"),
+        [OperationWithArgs::Ldi2(
+            Register(1),
+            Immediate::Value(-5i8 as u8 as i128)
+        )]
+    );
+}
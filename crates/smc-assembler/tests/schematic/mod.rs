@@ -0,0 +1,302 @@
+use std::{collections::HashMap, fs::File};
+
+use fastnbt::Value;
+use flate2::read::GzDecoder;
+use pretty_assertions::assert_eq;
+use smc_assembler::{
+    CompileError, CompileOptions,
+    assembler::backends::Backend,
+    compile, compile_to_file,
+    disassembler::{disassemble, to_source},
+    save::{
+        SaveOptions, load_program,
+        memory::Format,
+        schematic::{Mirror, Rotation, SchematicOptions},
+    },
+};
+
+use crate::{project_dir, write};
+
+fn read_nbt(path: &std::path::Path) -> HashMap<String, Value> {
+    fastnbt::from_reader(GzDecoder::new(File::open(path).unwrap())).unwrap()
+}
+
+fn compound<'a>(nbt: &'a HashMap<String, Value>, key: &str) -> &'a HashMap<String, Value> {
+    match nbt.get(key) {
+        Some(Value::Compound(compound)) => compound,
+        other => panic!("expected compound `{key}`, got {other:?}"),
+    }
+}
+
+#[test]
+fn turns_blocks_with_the_region() {
+    let block = mc_schem::Block::from_id("minecraft:repeater[facing=east]").unwrap();
+    let transform = |rotation, mirror| {
+        let options = SchematicOptions {
+            offset: [0, 5, 0],
+            rotation,
+            mirror,
+            ..Default::default()
+        };
+        let (pos, block) = options.transform(([2, 0, 1], block.clone()));
+        (pos, block.attributes["facing"].clone())
+    };
+
+    assert_eq!(
+        transform(Rotation::Clockwise90, Mirror::None),
+        ([-1, 5, 2], "south".to_string())
+    );
+    assert_eq!(
+        transform(Rotation::None, Mirror::X),
+        ([-2, 5, 1], "west".to_string())
+    );
+    assert_eq!(
+        transform(Rotation::Clockwise270, Mirror::Z),
+        ([-1, 5, -2], "north".to_string())
+    );
+}
+
+#[test]
+fn records_paste_offset_and_metadata() {
+    let dir = project_dir("schematic-placement");
+    write(dir.join("program.smc"), "LDI r1 3\nHLT\n");
+
+    let options = SchematicOptions {
+        offset: [10, 0, -3],
+        region_name: Some("rom".to_string()),
+        author: Some("tester".to_string()),
+        ..Default::default()
+    };
+    let placed = Format::Batpu2InstructionMemory
+        .make_schematic(vec![0x81, 0x03, 0x10, 0x00], None, &options)
+        .unwrap();
+    let default = Format::Batpu2InstructionMemory
+        .make_schematic(
+            vec![0x81, 0x03, 0x10, 0x00],
+            None,
+            &SchematicOptions::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        placed.origin,
+        [
+            default.origin[0] + 10,
+            default.origin[1],
+            default.origin[2] - 3
+        ]
+    );
+
+    let outputs = [dir.join("program.schem"), dir.join("program.litematic")];
+    compile_to_file(
+        dir.join("program.smc"),
+        &outputs,
+        Backend::BatPU2,
+        &CompileOptions::default(),
+        &SaveOptions {
+            format: Some(Format::Batpu2InstructionMemory),
+            schematic: options,
+            ..Default::default()
+        },
+    )
+    .expect("compilation should succeed");
+
+    let origin = placed.origin.to_vec();
+    let schem = read_nbt(&outputs[0]);
+    let (root, offset) = match schem.get("Schematic") {
+        Some(Value::Compound(root)) => (root, root.get("Offset")),
+        _ => {
+            let metadata = compound(&schem, "Metadata");
+            let we_offset = ["X", "Y", "Z"]
+                .map(|axis| match metadata.get(&format!("WEOffset{axis}")) {
+                    Some(Value::Int(value)) => *value,
+                    other => panic!("expected WEOffset{axis}, got {other:?}"),
+                })
+                .to_vec();
+            assert_eq!(we_offset, origin);
+            (&schem, None)
+        }
+    };
+    if let Some(Value::IntArray(offset)) = offset {
+        assert_eq!(offset.to_vec(), origin);
+    }
+    assert_eq!(
+        compound(root, "Metadata").get("Author"),
+        Some(&Value::String("tester".to_string()))
+    );
+
+    let litematic = read_nbt(&outputs[1]);
+    let region = compound(compound(&litematic, "Regions"), "rom");
+    let position = compound(region, "Position");
+    assert_eq!(
+        ["x", "y", "z"].map(|axis| position[axis].clone()).to_vec(),
+        origin.into_iter().map(Value::Int).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn reads_program_back() {
+    let dir = project_dir("schematic-read-back");
+    let source =
+        ".top\n  LDI r1 3\n.loop\n  DEC r1\n  BRH ne .loop\n  CAL .top\n  LOD r1 r2 -3\n  HLT\n";
+    write(dir.join("program.smc"), source);
+    let bytes = compile(source, Backend::BatPU2, false).unwrap();
+
+    let options = SchematicOptions {
+        offset: [3, -7, 12],
+        rotation: Rotation::Clockwise90,
+        mirror: Mirror::Z,
+        ..Default::default()
+    };
+    for extension in ["schem", "litematic", "nbt"] {
+        let output = dir.join(format!("program.{extension}"));
+        compile_to_file(
+            dir.join("program.smc"),
+            &[&output],
+            Backend::BatPU2,
+            &CompileOptions::default(),
+            &SaveOptions {
+                format: Some(Format::Batpu2InstructionMemory),
+                schematic: options.clone(),
+                ..Default::default()
+            },
+        )
+        .expect("compilation should succeed");
+
+        let read = Format::Batpu2InstructionMemory
+            .read_schematic_file(&output, &options)
+            .unwrap();
+        assert_eq!(read, bytes, "reading back {extension}");
+    }
+
+    let unrotated = Format::Batpu2InstructionMemory
+        .read_schematic_file(dir.join("program.schem"), &SchematicOptions::default());
+    assert!(unrotated.is_err());
+
+    let unsupported =
+        Format::ArcMemoryHexSerial.read_schematic_file(dir.join("program.schem"), &options);
+    assert!(matches!(
+        unsupported,
+        Err(CompileError::UnsupportedFormat(ref name, "extraction")) if name == "arc-memory-hex-serial"
+    ));
+
+    let disassembled = to_source(&disassemble(&Backend::BatPU2, &bytes)).unwrap();
+    assert_eq!(
+        disassembled,
+        ".label_0\n  LDI r1 3\n.label_1\n  ADI r1 -1\n  BRH ne .label_1\n  CAL .label_0\n  LOD r1 r2 -3\n  HLT\n"
+    );
+    assert_eq!(
+        compile(&disassembled, Backend::BatPU2, false).unwrap(),
+        bytes
+    );
+}
+
+#[test]
+fn writes_only_changed_blocks() {
+    let dir = project_dir("schematic-patch");
+    write(dir.join("old.smc"), "LDI r1 3\nHLT\n");
+    write(dir.join("new.smc"), "LDI r1 2\nHLT\n");
+
+    let compile = |input: &str, outputs: &[std::path::PathBuf], options: &SaveOptions| {
+        compile_to_file(
+            dir.join(input),
+            outputs,
+            Backend::BatPU2,
+            &CompileOptions::default(),
+            options,
+        )
+        .expect("compilation should succeed")
+    };
+
+    let mut options = SaveOptions {
+        format: Some(Format::Batpu2InstructionMemory),
+        ..Default::default()
+    };
+    compile(
+        "old.smc",
+        &[dir.join("old.mc"), dir.join("old.nbt")],
+        &options,
+    );
+    let full = std::fs::read_to_string(dir.join("old.mc")).unwrap();
+    assert_eq!(full, "1000000100000011\n0001000000000000\n");
+
+    options.previous = Some(load_program(dir.join("old.nbt"), &options).unwrap());
+    assert_eq!(
+        options.previous,
+        Some(load_program(dir.join("old.mc"), &options).unwrap())
+    );
+    compile("new.smc", &[dir.join("patch.mcfunction")], &options);
+
+    // Only the lowest bit of the immediate changed
+    assert_eq!(
+        std::fs::read_to_string(dir.join("patch.mcfunction")).unwrap(),
+        "setblock ~-4 ~-15 ~2 minecraft:purple_wool\n"
+    );
+}
+
+#[test]
+fn writes_datapack_at_origin() {
+    let dir = project_dir("schematic-datapack");
+    write(dir.join("program.smc"), "LDI r1 3\nHLT\n");
+
+    let options = SaveOptions {
+        format: Some(Format::Batpu2InstructionMemory),
+        schematic: SchematicOptions {
+            origin: Some([100, 64, -20]),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    compile_to_file(
+        dir.join("program.smc"),
+        &[dir.join("My Program.zip")],
+        Backend::BatPU2,
+        &CompileOptions::default(),
+        &options,
+    )
+    .unwrap();
+
+    let mut pack = zip::ZipArchive::new(File::open(dir.join("My Program.zip")).unwrap()).unwrap();
+    let mut read = |name: &str| {
+        let mut content = String::new();
+        std::io::Read::read_to_string(&mut pack.by_name(name).unwrap(), &mut content).unwrap();
+        content
+    };
+
+    assert!(read("pack.mcmeta").contains("\"pack_format\": 48"));
+    let function = read("data/my_program/function/place.mcfunction");
+    assert_eq!(function.lines().count(), 1024 * 16);
+    // Highest bit of the lower byte of the first word, 3 has it clear
+    assert_eq!(
+        function.lines().next(),
+        Some("setblock 96 63 -18 minecraft:purple_wool")
+    );
+}
+
+#[test]
+fn escapes_datapack_description() {
+    let dir = project_dir("schematic-datapack-description");
+    write(dir.join("program.smc"), "HLT\n");
+
+    let description = "Say \"hi\" to Zoë \\ 世界";
+    compile_to_file(
+        dir.join("program.smc"),
+        &[dir.join("program.zip")],
+        Backend::BatPU2,
+        &CompileOptions::default(),
+        &SaveOptions {
+            format: Some(Format::Batpu2InstructionMemory),
+            schematic: SchematicOptions {
+                description: Some(description.to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let mut pack = zip::ZipArchive::new(File::open(dir.join("program.zip")).unwrap()).unwrap();
+    let mcmeta: serde_json::Value = serde_json::from_reader(pack.by_name("pack.mcmeta").unwrap())
+        .expect("pack.mcmeta should be valid JSON");
+    assert_eq!(mcmeta["pack"]["description"], description);
+    assert_eq!(mcmeta["pack"]["pack_format"], 48);
+}
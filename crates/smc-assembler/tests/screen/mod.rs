@@ -0,0 +1,127 @@
+use std::{fs, path::PathBuf};
+
+use pretty_assertions::assert_eq;
+use smc_assembler::{
+    assembler::backends::Backend,
+    compile,
+    emulator::{
+        Emulator,
+        batpu2_io::{Batpu2Io, Frame},
+        input::{InputEvent, InputScript, InputScriptError, InputTime},
+    },
+};
+
+/// Run a BatPU-2 test program until it halts or shows `frames` frames
+fn run(program: &str, frames: usize) -> Batpu2Io {
+    run_with(program, Batpu2Io::default(), u64::MAX, frames)
+}
+
+fn run_with(program: &str, io: Batpu2Io, cycles: u64, frames: usize) -> Batpu2Io {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join(format!("tests/batpu2/programs/{}.smc", program));
+    let source = fs::read_to_string(path).expect("Should be able to read the source file");
+    let bytes = compile(&source, Backend::BatPU2, false).expect("compilation should succeed");
+
+    let mut emulator = Emulator::with_io(Backend::BatPU2, &bytes, io);
+    while !emulator.halted() && emulator.cycles < cycles && emulator.io.frames < frames {
+        emulator.step().expect("program should run");
+    }
+    emulator.io
+}
+
+/// Compare the displays with `expected/<name>.txt`
+fn assert_screen(io: &Batpu2Io, name: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join(format!("tests/screen/expected/{}.txt", name));
+    assert_eq!(
+        io.to_text(),
+        fs::read_to_string(path).expect("Should be able to read the expected screen")
+    );
+}
+
+#[test]
+fn shows_characters() {
+    let io = run("helloworld", 1);
+    assert_eq!(io.chars_text(), "HELLOWORLD");
+    assert_eq!(io.frames, 0);
+}
+
+#[test]
+fn renders_dvd() {
+    assert_screen(&run("dvd", 20), "dvd");
+}
+
+#[test]
+fn renders_game_of_life() {
+    let io = run("gol", 20);
+    assert_eq!(io.number_text(), "19");
+    assert_screen(&io, "gol");
+}
+
+#[test]
+fn writes_png() {
+    let mut frame = Frame::default();
+    frame.set(0, 0, true);
+
+    let png = frame.to_png(2).unwrap();
+    let mut reader = png::Decoder::new(std::io::Cursor::new(png))
+        .read_info()
+        .unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+    reader.next_frame(&mut pixels).unwrap();
+
+    assert_eq!((reader.info().width, reader.info().height), (64, 64));
+    // The bottom left pixel is lit, the top left one is not
+    assert_eq!(pixels[63 * 64], 0xff);
+    assert_eq!(pixels[62 * 64 + 1], 0xff);
+    assert_eq!(pixels[0], 0x20);
+}
+
+#[test]
+fn parses_input_scripts() {
+    let script: InputScript = "# comment\nframe 3 right A\n\ncycle 500 0x81\nframe 4\n"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        script.events,
+        [
+            InputEvent {
+                at: InputTime::Frame(3),
+                buttons: 0b0010_0100
+            },
+            InputEvent {
+                at: InputTime::Cycle(500),
+                buttons: 0x81
+            },
+            InputEvent {
+                at: InputTime::Frame(4),
+                buttons: 0
+            },
+        ]
+    );
+
+    assert_eq!(
+        "frame 1 jump".parse::<InputScript>(),
+        Err(InputScriptError::UnknownButton(1, "jump".to_string()))
+    );
+    assert_eq!(
+        "\nright".parse::<InputScript>(),
+        Err(InputScriptError::ExpectedTime(2))
+    );
+}
+
+#[test]
+fn replays_controller_input() {
+    let play = |seed| {
+        let script = "cycle 200000 left\ncycle 210000\ncycle 300000 up\ncycle 310000\n"
+            .parse()
+            .unwrap();
+        run_with("2048", Batpu2Io::new(seed, script), 500_000, usize::MAX)
+    };
+
+    // Both moves draw a frame, and the same seed places the same tiles
+    let io = play(7);
+    assert_eq!(io.frames, 6);
+    assert_eq!(io.screen, play(7).screen);
+    assert_ne!(io.screen, play(1).screen);
+}
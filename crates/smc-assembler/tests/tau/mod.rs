@@ -1,32 +1,24 @@
-use smc_assembler::{assembler::backends::Backend, compile, save::convert::convert_to_tau};
-use std::{fs, path::PathBuf};
+use smc_assembler::{assembler::backends::Backend, compile};
 
-pub fn test_compilation(program_name: &str) {
-    use pretty_assertions::assert_eq;
-
-    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    path.push(format!("tests/tau/programs/{}.tasm", program_name));
-
-    let mut expected_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    expected_path.push(format!("tests/tau/expected/{}.tau", program_name));
-
-    let source = fs::read_to_string(path).expect("Should be able to read the source file");
-    let result =
-        compile(&source, Backend::TauAnalyzersNone, false).expect("compilation should succeed");
-    let mc_output = convert_to_tau(result).expect("conversion to mc should succeed");
-
-    assert_eq!(
-        mc_output,
-        fs::read_to_string(expected_path).expect("Should be able to read the expected output")
-    );
-}
+use crate::assert_golden;
 
 #[test]
-fn compiles_ball() {
-    test_compilation("ball");
+fn compiles_programs() {
+    assert_golden("tau");
 }
 
 #[test]
-fn compiles_tetris() {
-    test_compilation("tetris");
+fn lays_out_clr_as_its_expansion() {
+    // CLR is encoded as a two byte `ANI r 0`, so the label after it is at address 2
+    let bytes = compile(
+        "CLR R1\n.after\nJMP .after",
+        Backend::TauAnalyzersNone,
+        false,
+    )
+    .unwrap();
+
+    assert_eq!(
+        bytes,
+        compile("ANI R1 0\nJMP 2", Backend::TauAnalyzersNone, false).unwrap()
+    );
 }
@@ -0,0 +1,186 @@
+use std::path::Path;
+
+use pretty_assertions::assert_eq;
+use smc_assembler::{
+    CompileOptions,
+    assembler::backends::Backend,
+    compile_file,
+    testing::{
+        TestError, TestResult, TestSuite, discover,
+        golden::{GoldenOptions, Outcome, check_golden},
+    },
+};
+
+use crate::{project_dir, write};
+
+const MULTIPLY: &str = "\
+define RESULT 10
+  LDI r1 3
+  LDI r2 4
+  CAL .multiply
+  LDI r4 RESULT
+  STR r4 r3
+  HLT
+
+// Multiply r1 by r2 into r3
+.multiply
+  LDI r3 0
+.loop
+  CMP r2 r0
+  BRH eq .done
+  ADD r3 r1 r3
+  DEC r2
+  JMP .loop
+.done
+  RET
+";
+
+fn run(dir: &Path) -> Vec<(TestSuite, Vec<TestResult>)> {
+    let program = compile_file(
+        dir.join("multiply.smc"),
+        Backend::BatPU2,
+        &CompileOptions::default(),
+    )
+    .unwrap();
+
+    TestSuite::load(&dir.join("multiply.smc"))
+        .unwrap()
+        .into_iter()
+        .map(|suite| {
+            let results = suite.run(&program);
+            (suite, results)
+        })
+        .collect()
+}
+
+#[test]
+fn runs_test_files_and_inline_tests() {
+    let dir = project_dir("testing-pass");
+    write(
+        dir.join("multiply.smc"),
+        &format!(
+            "//! test program\n//!   expect [RESULT] 12\n//! end\n{}",
+            MULTIPLY
+        ),
+    );
+    write(
+        dir.join("multiply.smctest"),
+        "test multiplies\n  set r1 7\n  set r2 6\n  call .multiply  // until it returns\n  \
+         within 100\n  expect r3 42\nend\n\ntest by_zero\n  set r1 7\n  call .multiply\n  \
+         expect r3 0\nend\n",
+    );
+    write(dir.join("untested.smc"), "HLT\n");
+
+    assert_eq!(discover(&dir).unwrap(), vec![dir.join("multiply.smc")]);
+    assert_eq!(
+        discover(&dir.join("multiply.smctest")).unwrap(),
+        vec![dir.join("multiply.smc")]
+    );
+
+    let suites = run(&dir);
+    let names: Vec<_> = suites
+        .iter()
+        .flat_map(|(_, results)| results.iter().map(|result| result.name.as_str()))
+        .collect();
+    assert_eq!(names, vec!["program", "multiplies", "by_zero"]);
+    for (_, results) in &suites {
+        for result in results {
+            assert_eq!(result.failures, vec![], "{} should pass", result.name);
+        }
+    }
+}
+
+#[test]
+fn reports_failures_at_their_line() {
+    let dir = project_dir("testing-fail");
+    write(dir.join("multiply.smc"), MULTIPLY);
+    write(
+        dir.join("multiply.smctest"),
+        "test wrong\n  set r1 2\n  set r2 2\n  call .multiply\n  expect r3 5\nend\n\n\
+         test slow\n  set r1 1\n  set r2 200\n  call .multiply\n  within 50\nend\n",
+    );
+
+    let (suite, results) = run(&dir).remove(0);
+    let messages: Vec<_> = results
+        .iter()
+        .map(|result| {
+            let failure = &result.failures[0];
+            (
+                failure.span.location(&suite.text).0,
+                failure.message.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            (5, "Expected r3 to be 5, but it is 4"),
+            (8, "Did not return within 50 cycles"),
+        ]
+    );
+}
+
+#[test]
+fn rejects_invalid_tests() {
+    let dir = project_dir("testing-invalid");
+    write(dir.join("multiply.smc"), MULTIPLY);
+    write(
+        dir.join("multiply.smctest"),
+        "test broken\n  expect r3\nend\n",
+    );
+
+    let Err(TestError::Invalid(report)) = TestSuite::load(&dir.join("multiply.smc")) else {
+        panic!("the test file should be rejected");
+    };
+    assert!(report.contains("multiply.smctest:2:3"), "{}", report);
+    assert!(
+        report.contains("Syntax Error: Expected `set`"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn blesses_and_checks_golden_outputs() {
+    let dir = project_dir("testing-golden");
+    write(dir.join("programs/multiply.smc"), MULTIPLY);
+    write(dir.join("programs/halt.tasm"), "HLT\n");
+    write(dir.join("expected/multiply.schem"), "");
+
+    let check = |bless| {
+        let options = GoldenOptions {
+            bless,
+            ..Default::default()
+        };
+        check_golden(&dir, &options)
+            .unwrap()
+            .into_iter()
+            .map(|result| {
+                let name = result.expected.file_name().unwrap().to_owned();
+                (name.to_string_lossy().to_string(), result.outcome)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Programs without outputs get their machine code
+    assert_eq!(
+        check(true),
+        vec![
+            ("halt.tau".to_string(), Outcome::Blessed),
+            ("multiply.schem".to_string(), Outcome::Blessed),
+        ]
+    );
+    assert_eq!(
+        check(false),
+        vec![
+            ("halt.tau".to_string(), Outcome::Matched),
+            ("multiply.schem".to_string(), Outcome::Matched),
+        ]
+    );
+
+    write(dir.join("programs/halt.tasm"), "LDI R0 1\nHLT\n");
+    let Outcome::Failed(message) = &check(false)[0].1 else {
+        panic!("the changed program should not match");
+    };
+    assert!(message.starts_with("Line 1: expected"), "{}", message);
+}
@@ -0,0 +1,46 @@
+use std::{
+    io::{self, BufReader},
+    sync::mpsc::{self, TryRecvError},
+    thread,
+};
+
+use anyhow::Result;
+use smc_dap::{protocol::read_message, session::Session};
+
+fn main() -> Result<()> {
+    // Requests are read on their own thread, so `pause` arrives while the program runs
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(io::stdin().lock());
+        while let Ok(Some(request)) = read_message(&mut reader) {
+            if sender.send(request).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = Session::new(io::stdout().lock());
+    loop {
+        let request = if session.running() {
+            match receiver.try_recv() {
+                Ok(request) => Some(request),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(request) => Some(request),
+                Err(_) => break,
+            }
+        };
+
+        if let Some(request) = request
+            && !session.handle(request)?
+        {
+            break;
+        }
+        session.run_slice()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,533 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use smc_assembler::{
+    CompileOptions,
+    assembler::backends::Backend,
+    compile_file_reporting,
+    debugger::{Debugger, Stop},
+    emulator::{Io, NullIo, batpu2_io::Batpu2Io, input::InputScript},
+    project,
+};
+
+use crate::protocol::{Request, Writer};
+
+/// Instructions run between checks for new requests, such as `pause`
+const SLICE: u64 = 100_000;
+
+const THREAD_ID: i64 = 1;
+
+const REGISTERS: i64 = 1;
+const FLAGS: i64 = 2;
+const PORTS: i64 = 3;
+const MEMORY: i64 = 4;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LaunchArguments {
+    program: PathBuf,
+    /// Backend name, picked like the LSP does if omitted
+    target: Option<String>,
+    #[serde(default)]
+    defines: HashMap<String, f64>,
+    #[serde(default)]
+    include: Vec<PathBuf>,
+    #[serde(default)]
+    stop_on_entry: bool,
+    /// Seed of the numbers read from `rng` on the BatPU-2
+    #[serde(default)]
+    seed: u64,
+}
+
+/// The devices behind the ports of the program's target
+#[derive(Debug)]
+enum Devices {
+    None(NullIo),
+    Batpu2(Box<Batpu2Io>),
+}
+
+impl Io for Devices {
+    fn read(&mut self, port: u8) -> u8 {
+        match self {
+            Devices::None(io) => io.read(port),
+            Devices::Batpu2(io) => io.read(port),
+        }
+    }
+
+    fn write(&mut self, port: u8, value: u8) {
+        match self {
+            Devices::None(io) => io.write(port, value),
+            Devices::Batpu2(io) => io.write(port, value),
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        match self {
+            Devices::None(io) => io.tick(cycles),
+            Devices::Batpu2(io) => io.tick(cycles),
+        }
+    }
+
+    fn reset(&mut self) {
+        match self {
+            Devices::None(io) => io.reset(),
+            Devices::Batpu2(io) => io.reset(),
+        }
+    }
+
+    fn display(&self) -> Option<String> {
+        match self {
+            Devices::None(io) => io.display(),
+            Devices::Batpu2(io) => io.display(),
+        }
+    }
+}
+
+/// How a resumed program is run until it pauses by itself
+#[derive(Debug, Clone, Copy)]
+enum Run {
+    Continue,
+    /// Over the subroutine called at the call stack depth, returning to the address
+    Over(usize, usize),
+    /// Out of the subroutine running at the call stack depth
+    Out(usize),
+}
+
+/// State of a debugging session with one client
+pub struct Session<W> {
+    writer: Writer<W>,
+    debugger: Option<Debugger<Devices>>,
+    /// Canonical path of every source file of the program, by source id
+    paths: Vec<PathBuf>,
+    stop_on_entry: bool,
+    /// Breakpoint ids set through `setBreakpoints`, by canonical source path
+    line_breakpoints: HashMap<PathBuf, Vec<usize>>,
+    function_breakpoints: Vec<usize>,
+    running: Option<Run>,
+    /// Whether the client was told the program halted
+    halted: bool,
+}
+
+impl<W: io::Write> Session<W> {
+    pub fn new(output: W) -> Self {
+        Session {
+            writer: Writer::new(output),
+            debugger: None,
+            paths: Vec::new(),
+            stop_on_entry: false,
+            line_breakpoints: HashMap::new(),
+            function_breakpoints: Vec::new(),
+            running: None,
+            halted: false,
+        }
+    }
+
+    /// End the session, returning the output responses and events were written to
+    pub fn into_output(self) -> W {
+        self.writer.into_inner()
+    }
+
+    /// Whether the program is running, so requests should only be polled for
+    pub fn running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Answer a request, returning `false` once the session is over
+    pub fn handle(&mut self, request: Request) -> io::Result<bool> {
+        let result = match request.command.as_str() {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsFunctionBreakpoints": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => self.launch(&request.arguments),
+            "setBreakpoints" => self.set_breakpoints(&request.arguments),
+            "setFunctionBreakpoints" => self.set_function_breakpoints(&request.arguments),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => Ok(Value::Null),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Flags", "variablesReference": FLAGS, "expensive": false },
+                { "name": "Ports", "variablesReference": PORTS, "expensive": false },
+                { "name": "Memory", "variablesReference": MEMORY, "expensive": true },
+            ] })),
+            "variables" => self.variables(&request.arguments),
+            "continue" => Ok(json!({ "allThreadsContinued": true })),
+            "next" | "stepIn" | "stepOut" | "pause" => Ok(Value::Null),
+            "disconnect" | "terminate" => {
+                self.writer.respond(&request, Value::Null)?;
+                return Ok(false);
+            }
+            _ => Err(format!("Unsupported request `{}`", request.command)),
+        };
+
+        match result {
+            Ok(body) => self.writer.respond(&request, body)?,
+            Err(message) => {
+                self.writer.fail(&request, &message)?;
+                return Ok(true);
+            }
+        }
+
+        // Events follow the response of the request causing them
+        match request.command.as_str() {
+            "launch" => self.writer.event("initialized", Value::Null)?,
+            "configurationDone" if self.stop_on_entry => self.stopped("entry", None)?,
+            "configurationDone" | "continue" => self.running = Some(Run::Continue),
+            "next" => self.next()?,
+            "stepIn" => {
+                if let Some(debugger) = &mut self.debugger {
+                    let stop = debugger.step(1);
+                    self.report(stop)?;
+                }
+            }
+            "stepOut" => {
+                if let Some(debugger) = &self.debugger {
+                    self.running = Some(Run::Out(debugger.emulator.call_stack.len()));
+                }
+            }
+            "pause" if self.running.take().is_some() => self.stopped("pause", None)?,
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    /// Run the program for a while, telling the client if it paused
+    pub fn run_slice(&mut self) -> io::Result<()> {
+        let (Some(run), Some(debugger)) = (self.running, &mut self.debugger) else {
+            return Ok(());
+        };
+
+        debugger.max_cycles = SLICE;
+        let stop = match run {
+            Run::Continue => debugger.run(),
+            Run::Over(depth, return_address) => debugger.run_until(|emulator| {
+                emulator.call_stack.len() == depth && emulator.pc == return_address
+            }),
+            Run::Out(depth) => debugger.run_until(|emulator| emulator.call_stack.len() < depth),
+        };
+
+        if stop != Stop::Limit {
+            self.running = None;
+            self.report(stop)?;
+        }
+        Ok(())
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let arguments: LaunchArguments =
+            serde_json::from_value(arguments.clone()).map_err(|err| err.to_string())?;
+
+        let target = select_backend(&arguments.program, arguments.target.as_deref())?;
+        let options = CompileOptions {
+            defines: arguments.defines.into_iter().collect(),
+            include_dirs: arguments.include,
+            debug_artifacts: false,
+        };
+
+        let mut errors = String::new();
+        let program = compile_file_reporting(&arguments.program, target, &options, |report| {
+            errors = report.to_string()
+        });
+        let program = match program {
+            Ok(program) => program,
+            Err(err) => {
+                let _ = self
+                    .writer
+                    .event("output", json!({ "category": "stderr", "output": errors }));
+                return Err(format!("{}: {}", arguments.program.display(), err));
+            }
+        };
+
+        self.paths = program
+            .sources
+            .files
+            .iter()
+            .map(|file| canonical(&file.path))
+            .collect();
+        self.stop_on_entry = arguments.stop_on_entry;
+        let devices = match program.target {
+            Backend::BatPU2 => Devices::Batpu2(Box::new(Batpu2Io::new(
+                arguments.seed,
+                InputScript::default(),
+            ))),
+            _ => Devices::None(NullIo),
+        };
+        self.debugger = Some(Debugger::new(program, devices));
+
+        Ok(Value::Null)
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let path = canonical(Path::new(
+            arguments["source"]["path"].as_str().unwrap_or_default(),
+        ));
+        let lines: Vec<usize> = arguments["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+            .map(|line| line as usize)
+            .collect();
+
+        let Some(debugger) = &mut self.debugger else {
+            return Ok(json!({ "breakpoints": lines.iter().map(|line| json!({
+                "verified": false,
+                "line": line,
+                "message": "No program launched",
+            })).collect::<Vec<_>>() }));
+        };
+
+        for id in self.line_breakpoints.remove(&path).unwrap_or_default() {
+            let _ = debugger.remove(id);
+        }
+
+        let source = self.paths.iter().position(|source| *source == path);
+        let mut ids = Vec::new();
+        let mut breakpoints = Vec::new();
+        for line in lines {
+            let resolved = source.and_then(|source| {
+                let address = debugger.line_address(source, line)?;
+                let (_, line) = debugger.source_line(address)?;
+                Some((address, line))
+            });
+
+            breakpoints.push(match resolved {
+                Some((address, actual)) => {
+                    let id = debugger.add_breakpoint_at(address, &format!("{}", line));
+                    ids.push(id);
+                    json!({ "id": id, "verified": true, "line": actual })
+                }
+                None => json!({
+                    "verified": false,
+                    "line": line,
+                    "message": match source {
+                        Some(_) => "No code at or after this line",
+                        None => "File is not part of the program",
+                    },
+                }),
+            });
+        }
+        self.line_breakpoints.insert(path, ids);
+
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// Breakpoints on labels, named with or without their `.`
+    fn set_function_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let Some(debugger) = &mut self.debugger else {
+            return Err("No program launched".to_string());
+        };
+
+        for id in self.function_breakpoints.drain(..) {
+            let _ = debugger.remove(id);
+        }
+
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+            let name = breakpoint["name"].as_str().unwrap_or_default();
+            let label = format!(".{}", name.trim_start_matches('.'));
+            breakpoints.push(match debugger.add_breakpoint(&label) {
+                Ok(id) => {
+                    self.function_breakpoints.push(id);
+                    json!({ "id": id, "verified": true })
+                }
+                Err(err) => json!({ "verified": false, "message": err.to_string() }),
+            });
+        }
+
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn stack_trace(&self) -> Result<Value, String> {
+        let debugger = self.debugger.as_ref().ok_or("No program launched")?;
+        let program = debugger.program();
+
+        let frames: Vec<Value> = debugger
+            .frames()
+            .into_iter()
+            .enumerate()
+            .map(|(id, address)| {
+                let name = match debugger.label_before(address) {
+                    Some(label) => format!(".{}", label),
+                    None => format!("{:04x}", address),
+                };
+                let mut frame = json!({
+                    "id": id,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("{}", address),
+                });
+
+                if let Some((source, line)) = debugger.source_line(address) {
+                    let path = &program.sources.files[source].path;
+                    frame["line"] = json!(line);
+                    frame["column"] = json!(1);
+                    frame["source"] = json!({
+                        "name": path.file_name().map(|name| name.to_string_lossy()),
+                        "path": self.paths[source],
+                    });
+                }
+                frame
+            })
+            .collect();
+
+        Ok(json!({ "totalFrames": frames.len(), "stackFrames": frames }))
+    }
+
+    fn variables(&self, arguments: &Value) -> Result<Value, String> {
+        let debugger = self.debugger.as_ref().ok_or("No program launched")?;
+        let emulator = &debugger.emulator;
+        let byte = |value: u8| format!("{} (0x{:02x})", value, value);
+
+        let variables: Vec<(String, String)> = match arguments["variablesReference"].as_i64() {
+            Some(REGISTERS) => emulator
+                .registers
+                .iter()
+                .enumerate()
+                .map(|(i, value)| (format!("r{}", i), byte(*value)))
+                .collect(),
+            Some(FLAGS) => {
+                let flags = emulator.flags;
+                let mut variables = vec![
+                    ("zero".to_string(), flags.zero.to_string()),
+                    ("carry".to_string(), flags.carry.to_string()),
+                ];
+                if *emulator.target() == Backend::TauAnalyzersNone {
+                    variables.push(("negative".to_string(), flags.negative.to_string()));
+                    variables.push(("bank".to_string(), emulator.bank.to_string()));
+                }
+                variables
+            }
+            Some(PORTS) => {
+                let names = debugger.port_names();
+                (0..=u8::MAX)
+                    .filter_map(|port| {
+                        let name: Vec<_> = names
+                            .iter()
+                            .filter(|(_, named)| *named == port)
+                            .map(|(name, _)| *name)
+                            .collect();
+                        let value = debugger.port(port);
+                        if name.is_empty() && value.is_none() {
+                            return None;
+                        }
+
+                        let name = match name.is_empty() {
+                            true => port.to_string(),
+                            false => format!("{} ({})", name.join(", "), port),
+                        };
+                        Some((name, value.map_or("-".to_string(), byte)))
+                    })
+                    .collect()
+            }
+            Some(MEMORY) => emulator.memory[emulator.bank * 256..][..256]
+                .iter()
+                .enumerate()
+                .map(|(address, value)| (format!("0x{:02x}", address), byte(*value)))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(json!({ "variables": variables
+            .into_iter()
+            .map(|(name, value)| json!({ "name": name, "value": value, "variablesReference": 0 }))
+            .collect::<Vec<_>>() }))
+    }
+
+    fn next(&mut self) -> io::Result<()> {
+        let Some(debugger) = &mut self.debugger else {
+            return Ok(());
+        };
+
+        match debugger.call_return() {
+            Some((depth, return_address)) => self.running = Some(Run::Over(depth, return_address)),
+            None => {
+                let stop = debugger.step(1);
+                self.report(stop)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Tell the client why the program paused
+    fn report(&mut self, stop: Stop) -> io::Result<()> {
+        match stop {
+            Stop::Stepped | Stop::Limit => self.stopped("step", None),
+            Stop::Breakpoint(id) => self.stopped("breakpoint", Some(id)),
+            Stop::Watchpoint(id, _) => self.stopped("data breakpoint", Some(id)),
+            Stop::Error(error) => {
+                self.writer.event(
+                    "stopped",
+                    json!({
+                        "reason": "exception",
+                        "text": error.to_string(),
+                        "threadId": THREAD_ID,
+                        "allThreadsStopped": true,
+                    }),
+                )?;
+                self.writer.event(
+                    "output",
+                    json!({ "category": "stderr", "output": format!("{}\n", error) }),
+                )
+            }
+            // Pause on the `HLT` so the final state can be inspected, end once resumed
+            Stop::Halted if !self.halted => {
+                self.halted = true;
+                self.stopped("halt", None)
+            }
+            Stop::Halted => {
+                let cycles = self.debugger.as_ref().map_or(0, |d| d.emulator.cycles);
+                self.writer.event(
+                    "output",
+                    json!({ "category": "console", "output": format!("Program halted after {} cycles\n", cycles) }),
+                )?;
+                self.writer.event("exited", json!({ "exitCode": 0 }))?;
+                self.writer.event("terminated", Value::Null)
+            }
+        }
+    }
+
+    fn stopped(&mut self, reason: &str, breakpoint: Option<usize>) -> io::Result<()> {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(id) = breakpoint {
+            body["hitBreakpointIds"] = json!([id]);
+        }
+        self.writer.event("stopped", body)
+    }
+}
+
+/// The backend given by name, else the one picked for the file like the LSP does
+fn select_backend(program: &Path, name: Option<&str>) -> Result<Backend, String> {
+    if let Some(name) = name {
+        return Backend::from_name(name).ok_or_else(|| format!("Unknown backend `{}`", name));
+    }
+
+    let source =
+        fs::read_to_string(program).map_err(|err| format!("{}: {}", program.display(), err))?;
+    project::select_backend(program, &source)
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| {
+            format!(
+                "No backend selected for {}, set `target` in the launch configuration",
+                program.display()
+            )
+        })
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
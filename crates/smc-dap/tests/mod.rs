@@ -0,0 +1,16 @@
+pub mod session;
+
+use std::{env, fs, path::PathBuf, process};
+
+/// A fresh directory for a test to write its program into
+pub fn project_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("smc-dap-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("temp dir should be writable");
+    dir
+}
+
+pub fn write(path: PathBuf, text: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, text).unwrap();
+}
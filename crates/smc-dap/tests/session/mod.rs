@@ -0,0 +1,156 @@
+use serde_json::{Value, json};
+use smc_assembler::emulator::{Io, batpu2_io::Batpu2Io, input::InputScript};
+use smc_dap::{protocol::Request, session::Session};
+
+use crate::{project_dir, write};
+
+/// Sends requests to a session writing into a buffer, numbering them like a client
+struct Client {
+    session: Session<Vec<u8>>,
+    seq: i64,
+}
+
+impl Client {
+    fn new() -> Self {
+        Client {
+            session: Session::new(Vec::new()),
+            seq: 0,
+        }
+    }
+
+    fn send(&mut self, command: &str, arguments: Value) {
+        self.seq += 1;
+        let request = Request {
+            seq: self.seq,
+            command: command.to_string(),
+            arguments,
+        };
+        assert!(self.session.handle(request).unwrap());
+    }
+
+    /// Run the program until it pauses, as the adapter does between requests
+    fn run(&mut self) {
+        while self.session.running() {
+            self.session.run_slice().unwrap();
+        }
+    }
+
+    /// Every message written, in order
+    fn messages(self) -> Vec<Value> {
+        let output = String::from_utf8(self.session.into_output()).unwrap();
+        let mut messages = Vec::new();
+        let mut rest = output.as_str();
+        while let Some(header) = rest.strip_prefix("Content-Length: ") {
+            let (length, body) = header.split_once("\r\n\r\n").unwrap();
+            let length: usize = length.parse().unwrap();
+            messages.push(serde_json::from_str(&body[..length]).unwrap());
+            rest = &body[length..];
+        }
+        assert!(rest.is_empty(), "unframed output: {:?}", rest);
+        messages
+    }
+}
+
+fn response<'a>(messages: &'a [Value], command: &str) -> &'a Value {
+    messages
+        .iter()
+        .find(|message| message["type"] == "response" && message["command"] == command)
+        .unwrap_or_else(|| panic!("no `{}` response in {:#?}", command, messages))
+}
+
+fn events(messages: &[Value]) -> Vec<&str> {
+    messages
+        .iter()
+        .filter(|message| message["type"] == "event")
+        .map(|message| message["event"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn stops_at_breakpoints_and_shows_state() {
+    let dir = project_dir("session");
+    let program = dir.join("random.smc");
+    write(
+        program.clone(),
+        ".start\n  LDI r1 rng\n  LOD r1 r2\n  HLT\n",
+    );
+
+    let mut client = Client::new();
+    client.send("initialize", json!({ "adapterID": "smc" }));
+    client.send(
+        "launch",
+        json!({ "program": program, "target": "batpu2-mattbatwings-none", "seed": 7 }),
+    );
+    client.send(
+        "setBreakpoints",
+        json!({ "source": { "path": program }, "breakpoints": [{ "line": 4 }, { "line": 9 }] }),
+    );
+    client.send("configurationDone", Value::Null);
+    client.run();
+    client.send("stackTrace", json!({ "threadId": 1 }));
+    client.send("variables", json!({ "variablesReference": 1 }));
+
+    let messages = client.messages();
+    assert!(
+        messages
+            .iter()
+            .filter(|message| message["type"] == "response")
+            .all(|message| message["success"] == true),
+        "{:#?}",
+        messages
+    );
+    assert_eq!(
+        response(&messages, "initialize")["body"]["supportsConfigurationDoneRequest"],
+        true
+    );
+    assert_eq!(events(&messages), ["initialized", "stopped"]);
+
+    let breakpoints = &response(&messages, "setBreakpoints")["body"]["breakpoints"];
+    assert_eq!(breakpoints[0]["verified"], true);
+    assert_eq!(breakpoints[0]["line"], 4);
+    assert_eq!(breakpoints[1]["verified"], false);
+
+    let stopped = messages
+        .iter()
+        .find(|message| message["event"] == "stopped")
+        .unwrap();
+    assert_eq!(stopped["body"]["reason"], "breakpoint");
+    assert_eq!(
+        stopped["body"]["hitBreakpointIds"],
+        json!([breakpoints[0]["id"]])
+    );
+
+    let frames = &response(&messages, "stackTrace")["body"]["stackFrames"];
+    assert_eq!(frames.as_array().unwrap().len(), 1);
+    assert_eq!(frames[0]["name"], ".start");
+    assert_eq!(frames[0]["line"], 4);
+    assert_eq!(frames[0]["source"]["name"], "random.smc");
+
+    // `rng` is read from the BatPU-2 devices, not a port that always reads zero
+    let mut devices = Batpu2Io::new(7, InputScript::default());
+    let random = devices.read(254);
+    assert_ne!(random, 0);
+
+    let variables = &response(&messages, "variables")["body"]["variables"];
+    assert_eq!(variables[2]["name"], "r2");
+    assert_eq!(
+        variables[2]["value"],
+        format!("{} (0x{:02x})", random, random)
+    );
+}
+
+#[test]
+fn fails_requests_before_launch() {
+    let mut client = Client::new();
+    client.send("stackTrace", json!({ "threadId": 1 }));
+    client.send("launch", json!({ "program": "missing.smc" }));
+
+    let messages = client.messages();
+    assert_eq!(response(&messages, "stackTrace")["success"], false);
+    assert_eq!(
+        response(&messages, "stackTrace")["message"],
+        "No program launched"
+    );
+    assert_eq!(response(&messages, "launch")["success"], false);
+    assert!(events(&messages).is_empty());
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+
+use smc_assembler::assembler::AssemblerError;
+use smc_assembler::lexer::Lexer;
+use smc_assembler::lexer::token::Span;
+use tower_lsp::lsp_types::*;
+
+use crate::{Definitions, offset_to_position, position_to_offset, span_to_range};
+
+/// Quick fixes for the diagnostics under `range`, plus refactorings of the selection
+pub fn code_actions(
+    uri: &Url,
+    definitions: &Definitions,
+    range: Range,
+) -> Vec<CodeActionOrCommand> {
+    let source = &definitions.source_text;
+    let start = position_to_offset(range.start, source).unwrap_or(0);
+    let end = position_to_offset(range.end, source).unwrap_or(source.len());
+
+    let mut actions = Vec::new();
+
+    for error in &definitions.errors {
+        let span = error.span();
+        if span.start() > end || span.end() < start {
+            continue;
+        }
+
+        match error {
+            AssemblerError::DefineNotFound(_, name) => {
+                let offset = define_insert_offset(definitions);
+                actions.push(quick_fix(
+                    uri,
+                    format!("Create define `{}`", name),
+                    vec![insert(source, offset, format!("define {} 0\n", name))],
+                    true,
+                ));
+            }
+            AssemblerError::LabelNotFound(_, name) => {
+                let separator = if source.ends_with('\n') { "" } else { "\n" };
+                actions.push(quick_fix(
+                    uri,
+                    format!("Create label `.{}`", name),
+                    vec![insert(
+                        source,
+                        source.len(),
+                        format!("{}\n.{}\n", separator, name),
+                    )],
+                    true,
+                ));
+            }
+            AssemblerError::ImmediateOutOfRange(span, value) => {
+                let number = match last_number_span(span, source) {
+                    Some(number) => number,
+                    None => continue,
+                };
+
+                let wrapped = value.rem_euclid(256);
+                let clamped = (*value).clamp(-128, 255);
+                for (title, replacement) in [
+                    (format!("Wrap immediate to {}", wrapped), wrapped),
+                    (format!("Clamp immediate to {}", clamped), clamped),
+                ] {
+                    actions.push(quick_fix(
+                        uri,
+                        title,
+                        vec![replace(source, &number, replacement.to_string())],
+                        false,
+                    ));
+                }
+            }
+            AssemblerError::UnsupportedOperation(span, op) => {
+                let lowered = match definitions.backend.lower_unsupported(op) {
+                    Some(lowered) => lowered,
+                    None => continue,
+                };
+
+                let indent = line_indent(source, span.start());
+                let text = lowered
+                    .iter()
+                    .map(|op| op.to_string())
+                    .collect::<Vec<_>>()
+                    .join(&format!("\n{}", indent));
+                let title = lowered
+                    .iter()
+                    .map(|op| op.to_string())
+                    .collect::<Vec<_>>()
+                    .join("; ");
+
+                actions.push(quick_fix(
+                    uri,
+                    format!("Replace with `{}`", title),
+                    vec![replace(source, span, text)],
+                    true,
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(action) = extract_subroutine(uri, definitions, start, end) {
+        actions.push(action);
+    }
+
+    actions
+}
+
+/// Move the selected lines into a new subroutine at the end of the file, called with CAL
+fn extract_subroutine(
+    uri: &Url,
+    definitions: &Definitions,
+    start: usize,
+    end: usize,
+) -> Option<CodeActionOrCommand> {
+    let source = &definitions.source_text;
+    if start >= end {
+        return None;
+    }
+
+    // Extend the selection to whole lines, not counting a line the selection merely ends at
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let end = if end > line_start && source[..end].ends_with('\n') {
+        end - 1
+    } else {
+        end
+    };
+    let line_end = source[end..]
+        .find('\n')
+        .map_or(source.len(), |i| end + i + 1);
+
+    let contains = |span: &Span| span.start() >= line_start && span.start() < line_end;
+
+    if !definitions
+        .instructions
+        .iter()
+        .any(|instruction| contains(&instruction.span))
+    {
+        return None;
+    }
+
+    // Jumping into the middle of the extracted code would break once it is moved
+    if definitions.label_spans.values().any(contains) {
+        return None;
+    }
+
+    let name = (0..)
+        .map(|i| match i {
+            0 => "subroutine".to_string(),
+            i => format!("subroutine_{}", i),
+        })
+        .find(|name| !definitions.label_spans.contains_key(name))?;
+
+    let indent = line_indent(source, line_start);
+    let mut body = source[line_start..line_end].to_string();
+    if !body.ends_with('\n') {
+        body.push('\n');
+    }
+
+    let separator = if source.ends_with('\n') { "" } else { "\n" };
+    let edits = vec![
+        replace(
+            source,
+            &Span::new(line_start, line_end),
+            format!("{}CAL .{}\n", indent, name),
+        ),
+        insert(
+            source,
+            source.len(),
+            format!("{}\n.{}\n{}{}RET\n", separator, name, body, indent),
+        ),
+    ];
+
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("Extract into subroutine `.{}`", name),
+        kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+        edit: Some(workspace_edit(uri, edits)),
+        ..Default::default()
+    }))
+}
+
+fn quick_fix(
+    uri: &Url,
+    title: String,
+    edits: Vec<TextEdit>,
+    is_preferred: bool,
+) -> CodeActionOrCommand {
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title,
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(workspace_edit(uri, edits)),
+        is_preferred: Some(is_preferred),
+        ..Default::default()
+    })
+}
+
+fn workspace_edit(uri: &Url, edits: Vec<TextEdit>) -> WorkspaceEdit {
+    WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), edits)])),
+        ..Default::default()
+    }
+}
+
+fn insert(source: &str, offset: usize, text: String) -> TextEdit {
+    let position = offset_to_position(offset, source);
+    TextEdit::new(Range::new(position, position), text)
+}
+
+fn replace(source: &str, span: &Span, text: String) -> TextEdit {
+    TextEdit::new(span_to_range(span, source), text)
+}
+
+/// New defines go on the line after the last existing define, or at the top of the file
+fn define_insert_offset(definitions: &Definitions) -> usize {
+    let source = &definitions.source_text;
+    definitions
+        .define_spans
+        .values()
+        .map(|span| span.end())
+        .max()
+        .map_or(0, |end| {
+            source[end..]
+                .find('\n')
+                .map_or(source.len(), |i| end + i + 1)
+        })
+}
+
+/// The leading whitespace of the line containing `offset`
+fn line_indent(source: &str, offset: usize) -> &str {
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = &source[line_start..];
+    let indent_len = line.len() - line.trim_start_matches([' ', '\t']).len();
+    &line[..indent_len]
+}
+
+/// The span of the last number literal inside `span`, relative to the whole source
+fn last_number_span(span: &Span, source: &str) -> Option<Span> {
+    let snippet = span.snippet(source);
+    Lexer::new(snippet)
+        .map_while(|token| token.ok())
+        .filter(|token| token.token.as_number().is_some())
+        .last()
+        .map(|token| {
+            Span::new(
+                span.start() + token.span.start(),
+                span.start() + token.span.end(),
+            )
+        })
+}
@@ -0,0 +1,207 @@
+use smc_assembler::lexer::Lexer;
+use smc_assembler::lexer::token::{Keyword, Operation, Token};
+use smc_assembler::parser::{OperandKind, operand_kinds};
+use strum::VariantArray;
+use tower_lsp::lsp_types::*;
+
+use crate::{Definitions, offset_to_position};
+
+/// What the token under the cursor is expected to be
+#[derive(Debug)]
+enum CompletionContext {
+    /// Start of a line, where an instruction begins
+    Mnemonic,
+    /// An operand slot of the given operation
+    Operand(OperandKind),
+    /// Inside a comment, a define, or past the last operand
+    Nothing,
+}
+
+/// Build the completion items for the cursor at `offset`
+pub fn complete(definitions: &Definitions, offset: usize) -> Vec<CompletionItem> {
+    let source = &definitions.source_text;
+    let offset = offset.min(source.len());
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = &source[line_start..offset];
+
+    // The word being typed is replaced as a whole by the completion
+    let partial_start = line
+        .rfind(|c: char| c.is_ascii_whitespace() || c == ',')
+        .map_or(0, |i| i + 1);
+    let partial = &line[partial_start..];
+
+    let range = Range::new(
+        offset_to_position(line_start + partial_start, source),
+        offset_to_position(offset, source),
+    );
+
+    let context = completion_context(&line[..partial_start]);
+
+    let edit = |text: String| Some(CompletionTextEdit::Edit(TextEdit::new(range, text)));
+
+    match context {
+        CompletionContext::Nothing => vec![],
+        CompletionContext::Mnemonic => Operation::VARIANTS
+            .iter()
+            .filter(|op| definitions.backend.supports(op))
+            .map(|op| {
+                let mnemonic = cased(op.mnemonic(), prefers_uppercase(partial, true));
+                CompletionItem {
+                    label: mnemonic.clone(),
+                    kind: Some(CompletionItemKind::KEYWORD),
+                    detail: Some(signature(op)),
+                    text_edit: edit(mnemonic),
+                    ..Default::default()
+                }
+            })
+            .collect(),
+        CompletionContext::Operand(OperandKind::Register) => {
+            let prefix = if partial.starts_with('R') { "R" } else { "r" };
+            (0..definitions.backend.register_count())
+                .map(|i| {
+                    let register = format!("{prefix}{i}");
+                    CompletionItem {
+                        label: register.clone(),
+                        kind: Some(CompletionItemKind::VARIABLE),
+                        detail: Some(format!("register {i}")),
+                        text_edit: edit(register),
+                        ..Default::default()
+                    }
+                })
+                .collect()
+        }
+        CompletionContext::Operand(OperandKind::Address) => definitions
+            .labels
+            .iter()
+            .map(|(name, address)| CompletionItem {
+                label: format!(".{}", name),
+                kind: Some(CompletionItemKind::FUNCTION),
+                detail: Some(format!("@ {}", address)),
+                text_edit: edit(format!(".{}", name)),
+                ..Default::default()
+            })
+            .collect(),
+        CompletionContext::Operand(OperandKind::Immediate | OperandKind::Offset) => definitions
+            .defines
+            .iter()
+            .map(|(name, value)| CompletionItem {
+                label: name.clone(),
+                kind: Some(CompletionItemKind::CONSTANT),
+                detail: Some(format!("= {}", value)),
+                text_edit: edit(name.clone()),
+                ..Default::default()
+            })
+            .collect(),
+        CompletionContext::Operand(OperandKind::Condition) => keyword_items(
+            &[
+                ("eq", "branch if equal (zero flag set)"),
+                ("ne", "branch if not equal (zero flag clear)"),
+                ("ge", "branch if greater or equal (carry flag set)"),
+                ("lt", "branch if less than (carry flag clear)"),
+            ],
+            prefers_uppercase(partial, false),
+            edit,
+        ),
+        CompletionContext::Operand(OperandKind::Skip) => keyword_items(
+            &[
+                ("0", "skip if zero"),
+                ("!0", "skip if not zero"),
+                ("-", "skip if negative"),
+                ("!-", "skip if not negative"),
+                ("!", "never skip"),
+            ],
+            prefers_uppercase(partial, false),
+            edit,
+        ),
+    }
+}
+
+/// Work out what is expected next from the completed tokens before the cursor
+fn completion_context(line: &str) -> CompletionContext {
+    if line.contains("//") || line.contains('#') || line.contains("/*") {
+        return CompletionContext::Nothing;
+    }
+
+    let mut tokens = Vec::new();
+    for token in Lexer::new(line) {
+        match token {
+            Ok(token) => match token.token {
+                Token::Eof => break,
+                Token::Comma => {}
+                token => tokens.push(token),
+            },
+            Err(_) => return CompletionContext::Nothing,
+        }
+    }
+
+    // Labels may share a line with the instruction that follows them
+    let mut tokens = tokens
+        .into_iter()
+        .skip_while(|token| matches!(token, Token::Label(_)));
+
+    match tokens.next() {
+        None => CompletionContext::Mnemonic,
+        Some(Token::Keyword(Keyword::Operation(op))) => {
+            match operand_kinds(&op).get(tokens.count()) {
+                Some(kind) => CompletionContext::Operand(*kind),
+                None => CompletionContext::Nothing,
+            }
+        }
+        Some(_) => CompletionContext::Nothing,
+    }
+}
+
+fn keyword_items(
+    keywords: &[(&str, &str)],
+    uppercase: bool,
+    edit: impl Fn(String) -> Option<CompletionTextEdit>,
+) -> Vec<CompletionItem> {
+    keywords
+        .iter()
+        .map(|(keyword, detail)| {
+            let keyword = cased(keyword, uppercase);
+            CompletionItem {
+                label: keyword.clone(),
+                kind: Some(CompletionItemKind::KEYWORD),
+                detail: Some(detail.to_string()),
+                text_edit: edit(keyword),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Follow the casing of what the user has typed so far
+fn prefers_uppercase(partial: &str, default: bool) -> bool {
+    if partial.chars().any(|c| c.is_ascii_lowercase()) {
+        false
+    } else if partial.chars().any(|c| c.is_ascii_uppercase()) {
+        true
+    } else {
+        default
+    }
+}
+
+fn cased(text: &str, uppercase: bool) -> String {
+    if uppercase {
+        text.to_ascii_uppercase()
+    } else {
+        text.to_string()
+    }
+}
+
+/// A short usage line for an operation, e.g. `LDI reg imm`
+fn signature(op: &Operation) -> String {
+    let mut signature = op.mnemonic().to_ascii_uppercase();
+    for kind in operand_kinds(op) {
+        signature.push_str(match kind {
+            OperandKind::Register => " reg",
+            OperandKind::Immediate => " imm",
+            OperandKind::Address => " addr",
+            OperandKind::Condition => " cond",
+            OperandKind::Offset => " [offset]",
+            OperandKind::Skip => " [flag]",
+        });
+    }
+    signature
+}
@@ -0,0 +1,85 @@
+use tower_lsp::lsp_types::*;
+
+use crate::settings::InlayHintSettings;
+use crate::{Definitions, offset_to_position, position_to_offset};
+
+/// Build the inlay hints for the instructions and define references inside `range`
+pub fn inlay_hints(
+    definitions: &Definitions,
+    settings: &InlayHintSettings,
+    range: Range,
+) -> Vec<InlayHint> {
+    let source = &definitions.source_text;
+    let start = position_to_offset(range.start, source).unwrap_or(0);
+    let end = position_to_offset(range.end, source).unwrap_or(source.len());
+    let in_range = |offset: usize| offset >= start && offset <= end;
+
+    let mut hints = Vec::new();
+
+    for instruction in &definitions.instructions {
+        if !in_range(instruction.span.start()) {
+            continue;
+        }
+
+        if settings.addresses {
+            hints.push(InlayHint {
+                position: offset_to_position(instruction.span.start(), source),
+                label: InlayHintLabel::String(format!("{}:", instruction.address)),
+                kind: None,
+                text_edits: None,
+                tooltip: Some(InlayHintTooltip::String(format!(
+                    "Address {} (0x{:X})",
+                    instruction.address, instruction.address
+                ))),
+                padding_left: None,
+                padding_right: Some(true),
+                data: None,
+            });
+        }
+
+        if settings.encoding && !instruction.bytes.is_empty() {
+            let encoded = instruction
+                .bytes
+                .iter()
+                .map(|byte| format!("{:08b}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            hints.push(InlayHint {
+                position: offset_to_position(instruction.span.end(), source),
+                label: InlayHintLabel::String(encoded),
+                kind: None,
+                text_edits: None,
+                tooltip: None,
+                padding_left: Some(true),
+                padding_right: None,
+                data: None,
+            });
+        }
+    }
+
+    if settings.define_values {
+        for (name, spans) in &definitions.define_references {
+            let value = match definitions.defines.get(name) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            for span in spans.iter().filter(|span| in_range(span.start())) {
+                hints.push(InlayHint {
+                    position: offset_to_position(span.end(), source),
+                    label: InlayHintLabel::String(format!("= {}", value)),
+                    kind: Some(InlayHintKind::PARAMETER),
+                    text_edits: None,
+                    tooltip: None,
+                    padding_left: Some(true),
+                    padding_right: None,
+                    data: None,
+                });
+            }
+        }
+    }
+
+    hints.sort_by_key(|hint| (hint.position.line, hint.position.character));
+    hints
+}
@@ -0,0 +1,173 @@
+use smc_assembler::assembler::backends::Backend as SmcBackend;
+use smc_assembler::assembler::{
+    AssembledInstruction, Assembler, AssemblerError, AssemblerResult, LabelMap,
+};
+use smc_assembler::lexer::Lexer;
+use smc_assembler::lexer::token::Span;
+use smc_assembler::parser::{DefineMap, DefineSpanMap, LabelSpanMap, Parser, ReferenceMap};
+use smc_assembler::sources::Sources;
+use std::path::Path;
+use tower_lsp::lsp_types::*;
+
+pub mod code_actions;
+pub mod completion;
+pub mod inlay_hints;
+pub mod settings;
+pub mod target;
+
+#[derive(Debug)]
+pub struct Definitions {
+    pub uri: String,
+    pub defines: DefineMap,
+    pub labels: LabelMap,
+    pub define_spans: DefineSpanMap,
+    pub label_spans: LabelSpanMap,
+    pub define_references: ReferenceMap,
+    pub label_references: ReferenceMap,
+    pub instructions: Vec<AssembledInstruction>,
+    pub errors: Vec<AssemblerError>,
+    pub source_text: String,
+    pub backend: SmcBackend,
+}
+
+impl Definitions {
+    /// Assemble a document with `backend`, returning what is known about it and its diagnostics.
+    ///
+    /// Includes are resolved relative to `path`, a document without one can't include files.
+    pub fn analyze(
+        uri: String,
+        path: Option<&Path>,
+        text: &str,
+        backend: SmcBackend,
+    ) -> (Self, Vec<Diagnostic>) {
+        let (sources, tokens) = match path {
+            Some(path) => Sources::from_text(path, text.to_string(), &target::include_dirs(path)),
+            None => (Sources::default(), Lexer::new(text).collect()),
+        };
+        let parsed = Parser::new(tokens).parse();
+        let mut result = Assembler::new(backend.clone(), parsed).assemble();
+
+        // Errors in included files can't be shown there, so they are reported on the `include`
+        let included_diagnostics: Vec<_> = match &result.result {
+            Err(errors) => errors
+                .iter()
+                .filter_map(|err| included_diagnostic(err, &sources, text))
+                .collect(),
+            Ok(_) => Vec::new(),
+        };
+        retain_document_spans(&mut result);
+
+        let errors = result.result.err().unwrap_or_default();
+        let diagnostics = errors
+            .iter()
+            .map(|err| Diagnostic {
+                range: span_to_range(err.span(), text),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("smc-assembler".to_string()),
+                message: err.to_string(),
+                ..Default::default()
+            })
+            .chain(included_diagnostics)
+            .collect();
+
+        let definitions = Definitions {
+            uri,
+            defines: result.defines,
+            labels: result.labels,
+            define_spans: result.define_spans,
+            label_spans: result.label_spans,
+            define_references: result.define_references,
+            label_references: result.label_references,
+            instructions: result.instructions,
+            errors,
+            source_text: text.to_string(),
+            backend,
+        };
+        (definitions, diagnostics)
+    }
+}
+
+/// Drop errors and symbol locations inside included files, their spans point into other files
+fn retain_document_spans(result: &mut AssemblerResult) {
+    let in_document = |span: &Span| span.source() == 0;
+
+    if let Err(errors) = &mut result.result {
+        errors.retain(|err| in_document(err.span()));
+    }
+    result.define_spans.retain(|_, span| in_document(span));
+    result.label_spans.retain(|_, span| in_document(span));
+    for spans in result
+        .define_references
+        .values_mut()
+        .chain(result.label_references.values_mut())
+    {
+        spans.retain(in_document);
+    }
+    result
+        .instructions
+        .retain(|instruction| in_document(&instruction.span));
+}
+
+/// A diagnostic on the `include` line of the document for an error inside an included file
+fn included_diagnostic(err: &AssemblerError, sources: &Sources, text: &str) -> Option<Diagnostic> {
+    let span = err.span();
+    let file = sources.get(span.source()).filter(|_| span.source() != 0)?;
+    let range = span_to_range(span, &file.text);
+
+    Some(Diagnostic {
+        range: span_to_range(sources.span_in_entry(span), text),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("smc-assembler".to_string()),
+        message: format!(
+            "In {}:{}: {}",
+            file.path.display(),
+            range.start.line + 1,
+            err
+        ),
+        related_information: Url::from_file_path(&file.path).ok().map(|uri| {
+            vec![DiagnosticRelatedInformation {
+                location: Location { uri, range },
+                message: err.to_string(),
+            }]
+        }),
+        ..Default::default()
+    })
+}
+
+/// Convert a Span to an LSP Range
+pub fn span_to_range(span: &Span, source: &str) -> Range {
+    let start = offset_to_position(span.start(), source);
+    let end = offset_to_position(span.end(), source);
+    Range::new(start, end)
+}
+
+/// Convert an LSP Position to a byte offset in the source text
+pub fn position_to_offset(position: Position, source: &str) -> Option<usize> {
+    let mut offset = 0;
+    for (line_num, line) in source.lines().enumerate() {
+        if line_num == position.line as usize {
+            let char_offset = position.character as usize;
+            return Some(offset + char_offset.min(line.len()));
+        }
+        offset += line.len() + 1; // +1 for newline
+    }
+    Some(source.len())
+}
+
+/// Convert a byte offset to an LSP Position
+pub fn offset_to_position(offset: usize, source: &str) -> Position {
+    let mut line = 0u32;
+    let mut col = 0u32;
+    for (i, ch) in source.char_indices() {
+        if i >= offset {
+            break;
+        }
+        if ch == '\n' {
+            line += 1;
+            col = 0;
+        } else {
+            col += 1;
+        }
+    }
+    Position::new(line, col)
+}
@@ -1,35 +1,43 @@
-use smc_assembler::assembler::backends::Backend as SmcBackend;
-use smc_assembler::assembler::{Assembler, AssemblerError, LabelMap};
-use smc_assembler::lexer::token::Span;
-use smc_assembler::lexer::{Lexer, LexerError};
-use smc_assembler::parser::{
-    DefineMap, DefineSpanMap, LabelSpanMap, Parser, ParserError, ReferenceMap,
+use smc_assembler::formatter::{FormatOptions, format_source};
+use smc_lsp::settings::Settings;
+use smc_lsp::{
+    Definitions, code_actions, completion, inlay_hints, offset_to_position, position_to_offset,
+    span_to_range, target,
 };
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::sync::RwLock;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
 
-#[derive(Debug)]
-struct Definitions {
-    defines: DefineMap,
-    labels: LabelMap,
-    define_spans: DefineSpanMap,
-    label_spans: LabelSpanMap,
-    define_references: ReferenceMap,
-    label_references: ReferenceMap,
-    source_text: String,
-}
-
 #[derive(Debug)]
 struct Backend {
     client: Client,
-    definitions: RwLock<Option<Definitions>>,
+    /// Open documents by URI
+    definitions: RwLock<HashMap<String, Definitions>>,
+    settings: RwLock<Settings>,
+    supports_configuration: AtomicBool,
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let mut settings = self.read_settings(params.initialization_options).await;
+        settings.root = params
+            .root_uri
+            .as_ref()
+            .and_then(|uri| uri.to_file_path().ok());
+        *self.settings.write().await = settings;
+
+        let supports_configuration = params
+            .capabilities
+            .workspace
+            .and_then(|workspace| workspace.configuration)
+            .unwrap_or(false);
+        self.supports_configuration
+            .store(supports_configuration, Ordering::Relaxed);
+
         Ok(InitializeResult {
             server_info: None,
             capabilities: ServerCapabilities {
@@ -44,11 +52,22 @@ impl LanguageServer for Backend {
                     completion_item: Some(CompletionOptionsCompletionItem {
                         label_details_support: Some(true),
                     }),
-                    ..Default::default()
                 }),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
                 definition_provider: Some(OneOf::Left(true)),
                 references_provider: Some(OneOf::Left(true)),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR_EXTRACT,
+                        ]),
+                        ..Default::default()
+                    },
+                )),
                 diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
                     DiagnosticOptions {
                         identifier: None,
@@ -63,11 +82,31 @@ impl LanguageServer for Backend {
     }
 
     async fn initialized(&self, _: InitializedParams) {
+        self.refresh_configuration().await;
+
         self.client
             .log_message(MessageType::INFO, "server initialized!")
             .await;
     }
 
+    async fn did_change_configuration(&self, _: DidChangeConfigurationParams) {
+        self.refresh_configuration().await;
+
+        // Re-check the open documents, their backend may have changed
+        let documents: Vec<_> = self
+            .definitions
+            .read()
+            .await
+            .values()
+            .map(|d| (d.uri.clone(), d.source_text.clone()))
+            .collect();
+
+        for (uri, text) in documents {
+            self.on_change(TextDocumentChange { uri, text: &text })
+                .await;
+        }
+    }
+
     async fn shutdown(&self) -> Result<()> {
         self.client
             .log_message(MessageType::INFO, "Shutdown initiated")
@@ -91,6 +130,13 @@ impl LanguageServer for Backend {
         .await;
     }
 
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        self.definitions
+            .write()
+            .await
+            .remove(params.text_document.uri.as_str());
+    }
+
     async fn goto_definition(
         &self,
         params: GotoDefinitionParams,
@@ -103,7 +149,7 @@ impl LanguageServer for Backend {
         let position = params.text_document_position_params.position;
 
         let definitions = self.definitions.read().await;
-        let definitions = match definitions.as_ref() {
+        let definitions = match definitions.get(uri.as_str()) {
             Some(d) => d,
             None => return Ok(None),
         };
@@ -116,13 +162,14 @@ impl LanguageServer for Backend {
         // Check if cursor is on a define reference -> go to define definition
         for (name, ref_spans) in &definitions.define_references {
             for ref_span in ref_spans {
-                if offset >= ref_span.start() && offset <= ref_span.end() {
-                    if let Some(def_span) = definitions.define_spans.get(name) {
-                        let range = span_to_range(def_span, &definitions.source_text);
-                        return Ok(Some(GotoDefinitionResponse::Scalar(Location::new(
-                            uri, range,
-                        ))));
-                    }
+                if offset >= ref_span.start()
+                    && offset <= ref_span.end()
+                    && let Some(def_span) = definitions.define_spans.get(name)
+                {
+                    let range = span_to_range(def_span, &definitions.source_text);
+                    return Ok(Some(GotoDefinitionResponse::Scalar(Location::new(
+                        uri, range,
+                    ))));
                 }
             }
         }
@@ -130,13 +177,14 @@ impl LanguageServer for Backend {
         // Check if cursor is on a label reference -> go to label definition
         for (name, ref_spans) in &definitions.label_references {
             for ref_span in ref_spans {
-                if offset >= ref_span.start() && offset <= ref_span.end() {
-                    if let Some(def_span) = definitions.label_spans.get(name) {
-                        let range = span_to_range(def_span, &definitions.source_text);
-                        return Ok(Some(GotoDefinitionResponse::Scalar(Location::new(
-                            uri, range,
-                        ))));
-                    }
+                if offset >= ref_span.start()
+                    && offset <= ref_span.end()
+                    && let Some(def_span) = definitions.label_spans.get(name)
+                {
+                    let range = span_to_range(def_span, &definitions.source_text);
+                    return Ok(Some(GotoDefinitionResponse::Scalar(Location::new(
+                        uri, range,
+                    ))));
                 }
             }
         }
@@ -150,7 +198,7 @@ impl LanguageServer for Backend {
         let include_declaration = params.context.include_declaration;
 
         let definitions = self.definitions.read().await;
-        let definitions = match definitions.as_ref() {
+        let definitions = match definitions.get(uri.as_str()) {
             Some(d) => d,
             None => return Ok(None),
         };
@@ -171,11 +219,11 @@ impl LanguageServer for Backend {
 
         // Check if it's a define
         if definitions.define_spans.contains_key(&symbol_name) {
-            if include_declaration {
-                if let Some(def_span) = definitions.define_spans.get(&symbol_name) {
-                    let range = span_to_range(def_span, &definitions.source_text);
-                    locations.push(Location::new(uri.clone(), range));
-                }
+            if include_declaration
+                && let Some(def_span) = definitions.define_spans.get(&symbol_name)
+            {
+                let range = span_to_range(def_span, &definitions.source_text);
+                locations.push(Location::new(uri.clone(), range));
             }
             if let Some(ref_spans) = definitions.define_references.get(&symbol_name) {
                 for ref_span in ref_spans {
@@ -187,11 +235,10 @@ impl LanguageServer for Backend {
 
         // Check if it's a label
         if definitions.label_spans.contains_key(&symbol_name) {
-            if include_declaration {
-                if let Some(def_span) = definitions.label_spans.get(&symbol_name) {
-                    let range = span_to_range(def_span, &definitions.source_text);
-                    locations.push(Location::new(uri.clone(), range));
-                }
+            if include_declaration && let Some(def_span) = definitions.label_spans.get(&symbol_name)
+            {
+                let range = span_to_range(def_span, &definitions.source_text);
+                locations.push(Location::new(uri.clone(), range));
             }
             if let Some(ref_spans) = definitions.label_references.get(&symbol_name) {
                 for ref_span in ref_spans {
@@ -209,10 +256,11 @@ impl LanguageServer for Backend {
     }
 
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let uri = &params.text_document_position_params.text_document.uri;
         let position = params.text_document_position_params.position;
 
         let definitions = self.definitions.read().await;
-        let definitions = match definitions.as_ref() {
+        let definitions = match definitions.get(uri.as_str()) {
             Some(d) => d,
             None => return Ok(None),
         };
@@ -256,209 +304,209 @@ impl LanguageServer for Backend {
         Ok(None)
     }
 
-    async fn completion(&self, _: CompletionParams) -> Result<Option<CompletionResponse>> {
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
         let definitions = self.definitions.read().await;
-        let definitions = match definitions.as_ref() {
+        let definitions = match definitions.get(params.text_document.uri.as_str()) {
             Some(d) => d,
-            None => return Ok(Some(CompletionResponse::Array(vec![]))),
+            None => return Ok(None),
         };
 
-        let mut items = Vec::new();
-
-        // Add defines to completion
-        for (name, value) in &definitions.defines {
-            items.push(CompletionItem {
-                label: name.clone(),
-                kind: Some(CompletionItemKind::CONSTANT),
-                detail: Some(format!("= {}", value)),
-                insert_text: Some(name.clone()),
-                ..Default::default()
-            });
+        let source = &definitions.source_text;
+        let formatted = format_source(source, &format_options(&params.options));
+        if &formatted == source {
+            return Ok(Some(vec![]));
         }
 
-        // Add labels to completion
-        for (name, address) in &definitions.labels {
-            items.push(CompletionItem {
-                label: format!(".{}", name),
-                kind: Some(CompletionItemKind::FUNCTION),
-                detail: Some(format!("@ {}", address)),
-                insert_text: Some(format!(".{}", name)),
-                ..Default::default()
-            });
-        }
+        let range = Range::new(
+            Position::new(0, 0),
+            offset_to_position(source.len(), source),
+        );
+        Ok(Some(vec![TextEdit::new(range, formatted)]))
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let definitions = self.definitions.read().await;
+        let definitions = match definitions.get(params.text_document.uri.as_str()) {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+
+        // The formatter keeps lines one to one, so the requested lines can be cut out of the result
+        let source = &definitions.source_text;
+        let formatted = format_source(source, &format_options(&params.options));
+        let start = params.range.start.line as usize;
+        let end = params.range.end.line as usize;
+
+        let edits = source
+            .lines()
+            .zip(formatted.lines())
+            .enumerate()
+            .skip(start)
+            .take(end.saturating_sub(start) + 1)
+            .filter(|(_, (original, formatted))| original != formatted)
+            .map(|(line, (original, formatted))| {
+                let range = Range::new(
+                    Position::new(line as u32, 0),
+                    Position::new(line as u32, original.chars().count() as u32),
+                );
+                TextEdit::new(range, formatted.to_string())
+            })
+            .collect();
 
-        Ok(Some(CompletionResponse::Array(items)))
+        Ok(Some(edits))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let definitions = self.definitions.read().await;
+        let definitions = match definitions.get(params.text_document.uri.as_str()) {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+
+        let settings = self.settings.read().await;
+        Ok(Some(inlay_hints::inlay_hints(
+            definitions,
+            &settings.inlay_hints,
+            params.range,
+        )))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let definitions = self.definitions.read().await;
+        let definitions = match definitions.get(params.text_document.uri.as_str()) {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+
+        Ok(Some(code_actions::code_actions(
+            &params.text_document.uri,
+            definitions,
+            params.range,
+        )))
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let uri = &params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+
+        let definitions = self.definitions.read().await;
+        let definitions = match definitions.get(uri.as_str()) {
+            Some(d) => d,
+            None => return Ok(Some(CompletionResponse::Array(vec![]))),
+        };
+
+        let offset = match position_to_offset(position, &definitions.source_text) {
+            Some(o) => o,
+            None => return Ok(Some(CompletionResponse::Array(vec![]))),
+        };
+
+        Ok(Some(CompletionResponse::Array(completion::complete(
+            definitions,
+            offset,
+        ))))
     }
 }
 
 impl Backend {
-    async fn on_change(&self, item: TextDocumentChange<'_>) {
-        let backend = (|| {
-            let url = Url::parse(&item.uri).ok()?;
-            let path = url.to_file_path().ok()?;
-            let ext = path.extension()?;
-
-            if ext.eq_ignore_ascii_case("tasm") {
-                Some(SmcBackend::TauAnalyzersNone)
-            } else if ext.eq_ignore_ascii_case("smc") {
-                Some(SmcBackend::BatPU2)
-            } else {
-                None
-            }
-        })();
+    /// Pull the `smc` section of the client configuration, if the client supports it
+    async fn refresh_configuration(&self) {
+        if !self.supports_configuration.load(Ordering::Relaxed) {
+            return;
+        }
 
-        let backend = match backend {
-            Some(backend) => backend,
-            None => return,
+        let items = vec![ConfigurationItem {
+            scope_uri: None,
+            section: Some("smc".to_string()),
+        }];
+
+        let value = match self.client.configuration(items).await {
+            Ok(values) => values.into_iter().next().filter(|value| !value.is_null()),
+            Err(_) => None,
         };
 
-        let tokens: Vec<_> = Lexer::new(&item.text).into_iter().collect();
-        let parsed = Parser::new(tokens).parse();
-        let assembler_result = Assembler::new(backend, parsed).assemble();
-
-        // Extract the result before moving assembler_result
-        // We need to destructure or take ownership of the result
-        let (
-            result,
-            defines,
-            labels,
-            define_spans,
-            label_spans,
-            define_references,
-            label_references,
-        ) = (
-            assembler_result.result,
-            assembler_result.defines,
-            assembler_result.labels,
-            assembler_result.define_spans,
-            assembler_result.label_spans,
-            assembler_result.define_references,
-            assembler_result.label_references,
-        );
+        if let Some(value) = value {
+            let new_settings = self.read_settings(Some(value)).await;
+            let mut settings = self.settings.write().await;
+            let root = settings.root.take();
+            *settings = new_settings;
+            settings.root = root;
+        }
+    }
 
-        // Store definitions (even if there are errors, we still want navigation to work)
-        {
-            let mut definitions = self.definitions.write().await;
-            *definitions = Some(Definitions {
-                defines,
-                labels,
-                define_spans,
-                label_spans,
-                define_references,
-                label_references,
-                source_text: item.text.to_string(),
-            });
+    /// Read the settings sent by the client, warning and falling back to the defaults if they
+    /// are invalid
+    async fn read_settings(&self, value: Option<serde_json::Value>) -> Settings {
+        match Settings::from_value(value) {
+            Ok(settings) => settings,
+            Err(err) => {
+                self.client
+                    .show_message(
+                        MessageType::WARNING,
+                        format!("Invalid smc settings, using the defaults: {}", err),
+                    )
+                    .await;
+                Settings::default()
+            }
         }
+    }
 
+    async fn on_change(&self, item: TextDocumentChange<'_>) {
         let uri =
             Url::parse(&item.uri).unwrap_or_else(|_| Url::from_directory_path(&item.uri).unwrap());
+        let path = uri.to_file_path().ok();
 
-        let errors = match result {
-            Ok(_) => {
-                // Clear diagnostics on success
-                self.client.publish_diagnostics(uri, vec![], None).await;
-                return;
-            }
-            Err(errs) => errs,
+        let backend = {
+            let settings = self.settings.read().await;
+            target::resolve_backend(path.as_deref(), item.text, &settings)
         };
 
-        let diagnostics: Vec<_> = errors
-            .into_iter()
-            .map(|err| {
-                let span = extract_span_from_error(&err);
-                let range = span_to_range(span, item.text);
+        let backend = match backend {
+            Ok(backend) => backend,
+            Err(err) => {
+                self.definitions.write().await.remove(&item.uri);
 
-                Diagnostic {
-                    range,
+                let diagnostic = Diagnostic {
+                    range: span_to_range(&err.span, item.text),
                     severity: Some(DiagnosticSeverity::ERROR),
-                    code: None,
-                    code_description: None,
                     source: Some("smc-assembler".to_string()),
-                    message: err.to_string(),
-                    related_information: None,
-                    tags: None,
-                    data: None,
-                }
-            })
-            .collect();
+                    message: err.message,
+                    ..Default::default()
+                };
+                self.client
+                    .publish_diagnostics(uri, vec![diagnostic], None)
+                    .await;
+                return;
+            }
+        };
+
+        // Definitions are kept even with errors, so navigation still works
+        let (definitions, diagnostics) =
+            Definitions::analyze(item.uri.clone(), path.as_deref(), item.text, backend);
+        self.definitions
+            .write()
+            .await
+            .insert(item.uri.clone(), definitions);
 
+        // An empty list clears the diagnostics on success
         self.client
             .publish_diagnostics(uri, diagnostics, None)
             .await;
     }
 }
 
-struct TextDocumentChange<'a> {
-    uri: String,
-    text: &'a str,
-}
-
-/// Extract the span from an assembler error
-fn extract_span_from_error(err: &AssemblerError) -> &Span {
-    match err {
-        AssemblerError::DefineNotFound(span, _) => span,
-        AssemblerError::LabelNotFound(span, _) => span,
-        AssemblerError::ParserError(parser_error) => match parser_error {
-            ParserError::SyntaxError(lexer_error) => match lexer_error {
-                LexerError::InvalidNumber(span, _) => span,
-                LexerError::UnexpectedCharacter(span, _) => span,
-                LexerError::ExpectedCharacter(span, _) => span,
-                LexerError::UnknownCondition(span, _) => span,
-                LexerError::InvalidOffset(span, _) => span,
-                LexerError::InvalidIsaCode(span, _) => span,
-                LexerError::InvalidRegisterNumber(span, _) => span,
-            },
-            ParserError::DuplicateDefine(span, _) => span,
-            ParserError::DuplicateLabel(span, _) => span,
-            ParserError::ExpectedButReceived(span, _, _) => span,
-            ParserError::UnexpectedEof(span) => span,
-            ParserError::InvalidSkip(span, _) => span,
-        },
-        AssemblerError::UnsupportedOperation(span, _) => span,
-        AssemblerError::InvalidRegister(span, _) => span,
-        AssemblerError::AddressOutOfRange(span, _) => span,
-        AssemblerError::OffsetOutOfRange(span, _) => span,
-        AssemblerError::InvalidCondition(span, _) => span,
-        AssemblerError::ImmediateOutOfRange(span, _) => span,
-    }
-}
-
-/// Convert a Span to an LSP Range
-fn span_to_range(span: &Span, source: &str) -> Range {
-    let start = offset_to_position(span.start(), source);
-    let end = offset_to_position(span.end(), source);
-    Range::new(start, end)
-}
-
-/// Convert an LSP Position to a byte offset in the source text
-fn position_to_offset(position: Position, source: &str) -> Option<usize> {
-    let mut offset = 0;
-    for (line_num, line) in source.lines().enumerate() {
-        if line_num == position.line as usize {
-            let char_offset = position.character as usize;
-            return Some(offset + char_offset.min(line.len()));
-        }
-        offset += line.len() + 1; // +1 for newline
+/// The formatter options matching the editor's
+fn format_options(options: &FormattingOptions) -> FormatOptions {
+    FormatOptions {
+        indent: options.tab_size as usize,
     }
-    Some(source.len())
 }
 
-/// Convert a byte offset to an LSP Position
-fn offset_to_position(offset: usize, source: &str) -> Position {
-    let mut line = 0u32;
-    let mut col = 0u32;
-    for (i, ch) in source.char_indices() {
-        if i >= offset {
-            break;
-        }
-        if ch == '\n' {
-            line += 1;
-            col = 0;
-        } else {
-            col += 1;
-        }
-    }
-    Position::new(line, col)
+struct TextDocumentChange<'a> {
+    uri: String,
+    text: &'a str,
 }
 
 /// Find the symbol name at a given byte offset
@@ -505,7 +553,9 @@ async fn main() {
 
     let (service, socket) = LspService::new(|client| Backend {
         client,
-        definitions: RwLock::new(None),
+        definitions: RwLock::new(HashMap::new()),
+        settings: RwLock::new(Settings::default()),
+        supports_configuration: AtomicBool::new(false),
     });
     Server::new(stdin, stdout, socket).serve(service).await;
 }
@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use smc_assembler::project::BackendRule;
+
+/// Client supplied settings, read from `initializationOptions` and the `smc` configuration section
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub inlay_hints: InlayHintSettings,
+    /// Backend selection for files matching a glob, relative to the workspace root
+    pub backends: Vec<BackendRule>,
+    /// Workspace root, taken from the initialize request rather than the settings
+    #[serde(skip)]
+    pub root: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct InlayHintSettings {
+    /// Show the address of every instruction
+    pub addresses: bool,
+    /// Show the value each define operand resolves to
+    pub define_values: bool,
+    /// Show the encoded machine word of every instruction
+    pub encoding: bool,
+}
+
+impl Default for InlayHintSettings {
+    fn default() -> Self {
+        Self {
+            addresses: true,
+            define_values: true,
+            encoding: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Read the settings sent by the client, the defaults if it sent none
+    pub fn from_value(value: Option<serde_json::Value>) -> Result<Self, serde_json::Error> {
+        match value {
+            Some(value) => serde_json::from_value(value),
+            None => Ok(Self::default()),
+        }
+    }
+}
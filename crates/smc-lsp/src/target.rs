@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+
+use smc_assembler::CompileError;
+use smc_assembler::assembler::backends::Backend as SmcBackend;
+use smc_assembler::lexer::token::Span;
+use smc_assembler::project::{
+    BackendRule, Manifest, TARGET_PRAGMA, first_matching_rule, select_backend, target_pragma,
+};
+use strum::VariantArray;
+
+use crate::settings::Settings;
+
+/// Why no backend could be selected, pointing at the offending pragma if there is one
+#[derive(Debug)]
+pub struct TargetError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl TargetError {
+    fn new(message: String) -> Self {
+        Self {
+            message,
+            span: Span::new(0, 0),
+        }
+    }
+}
+
+/// Select the backend for a document.
+///
+/// An in-file pragma wins over the client settings, which win over the closest `smc.toml`,
+/// which wins over the file extension. Apart from the settings, this is
+/// [`select_backend`].
+pub fn resolve_backend(
+    path: Option<&Path>,
+    source: &str,
+    settings: &Settings,
+) -> Result<SmcBackend, TargetError> {
+    let pragma = target_pragma(source);
+
+    if let (None, Some(path)) = (&pragma, path) {
+        let root = settings
+            .root
+            .as_deref()
+            .or(path.parent())
+            .unwrap_or(Path::new(""));
+        let rule = first_matching_rule(&settings.backends, root, path)
+            .map_err(|err| TargetError::new(err.to_string()))?;
+        if let Some(rule) = rule {
+            return from_rule(rule);
+        }
+    }
+
+    // Without a path only the pragma can select a backend
+    let selected = select_backend(path.unwrap_or(Path::new("")), source).map_err(|err| {
+        if let Some((name, span)) = pragma {
+            return TargetError {
+                message: unknown_backend(name),
+                span,
+            };
+        }
+
+        // Past the pragma, every error comes from the manifest
+        let message = match err {
+            CompileError::UnknownBackend(name) => unknown_backend(&name),
+            err => err.to_string(),
+        };
+        let manifest = path.and_then(Path::parent).and_then(Manifest::find);
+        TargetError::new(format!(
+            "{}: {}",
+            manifest.unwrap_or_default().display(),
+            message
+        ))
+    })?;
+
+    selected.ok_or_else(|| TargetError::new(no_backend()))
+}
+
+/// The include directories of the closest `smc.toml`, none if there is none or it is invalid
+pub fn include_dirs(path: &Path) -> Vec<PathBuf> {
+    let Some(manifest_path) = path.parent().and_then(Manifest::find) else {
+        return Vec::new();
+    };
+    let root = manifest_path.parent().unwrap_or(Path::new(""));
+    Manifest::load(&manifest_path)
+        .map(|manifest| manifest.include_dirs(root))
+        .unwrap_or_default()
+}
+
+fn from_rule(rule: &BackendRule) -> Result<SmcBackend, TargetError> {
+    SmcBackend::from_name(&rule.target).ok_or_else(|| {
+        TargetError::new(format!(
+            "{} (selected for `{}`)",
+            unknown_backend(&rule.target),
+            rule.files
+        ))
+    })
+}
+
+fn backend_names() -> String {
+    SmcBackend::VARIANTS
+        .iter()
+        .map(|backend| format!("`{}`", backend.to_str()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn unknown_backend(name: &str) -> String {
+    format!(
+        "Unknown backend `{}`, expected one of {}",
+        name,
+        backend_names()
+    )
+}
+
+fn no_backend() -> String {
+    format!(
+        "No backend selected for this file, add a `// {} <backend>` comment or a `backends` rule to smc.toml. Available backends: {}",
+        TARGET_PRAGMA,
+        backend_names()
+    )
+}
@@ -0,0 +1,106 @@
+use serde_json::json;
+use smc_assembler::{assembler::backends::Backend, project::BackendRule};
+use smc_lsp::{settings::Settings, target::resolve_backend};
+use std::path::Path;
+
+use crate::{project_dir, write};
+
+const PRAGMA: &str = "// smc-target: tau-analyzers-none\nHLT\n";
+
+fn rule(files: &str, target: &str) -> Settings {
+    Settings {
+        backends: vec![BackendRule {
+            files: files.to_string(),
+            target: target.to_string(),
+        }],
+        ..Default::default()
+    }
+}
+
+fn manifest(dir: &Path, target: &str) {
+    write(
+        dir.join("smc.toml"),
+        &format!("[[backends]]\nfiles = \"*.smc\"\ntarget = \"{}\"\n", target),
+    );
+}
+
+#[test]
+fn prefers_pragma_then_settings_then_manifest_then_extension() {
+    let dir = project_dir("precedence");
+    manifest(&dir, "tau-analyzers-none");
+    let file = dir.join("game.smc");
+    let resolve = |source: &str, settings: &Settings| {
+        resolve_backend(Some(&file), source, settings).expect("a backend should be selected")
+    };
+    let batpu2 = rule("*.smc", "batpu2-mattbatwings-none");
+
+    assert_eq!(resolve(PRAGMA, &batpu2), Backend::TauAnalyzersNone);
+    assert_eq!(resolve("HLT\n", &batpu2), Backend::BatPU2);
+    assert_eq!(
+        resolve("HLT\n", &Settings::default()),
+        Backend::TauAnalyzersNone
+    );
+
+    let plain = project_dir("extension");
+    let resolve =
+        |name: &str| resolve_backend(Some(&plain.join(name)), "HLT\n", &Settings::default());
+    assert_eq!(resolve("game.smc").unwrap(), Backend::BatPU2);
+    assert_eq!(resolve("game.tasm").unwrap(), Backend::TauAnalyzersNone);
+    assert!(
+        resolve("game.asm")
+            .unwrap_err()
+            .message
+            .starts_with("No backend selected")
+    );
+
+    // Unsaved documents only have their pragma
+    let untitled = |source: &str| resolve_backend(None, source, &batpu2);
+    assert_eq!(untitled(PRAGMA).unwrap(), Backend::TauAnalyzersNone);
+    assert!(untitled("HLT\n").is_err());
+}
+
+#[test]
+fn reports_unknown_backends() {
+    let source = "HLT // smc-target: z80\n";
+    let err = resolve_backend(None, source, &Settings::default()).unwrap_err();
+    assert_eq!(err.span.snippet(source), "z80");
+    assert!(
+        err.message.starts_with("Unknown backend `z80`"),
+        "{}",
+        err.message
+    );
+
+    let dir = project_dir("unknown");
+    let file = dir.join("game.smc");
+    let err = resolve_backend(Some(&file), "HLT\n", &rule("*.smc", "z80")).unwrap_err();
+    assert!(
+        err.message.starts_with("Unknown backend `z80`") && err.message.contains("`*.smc`"),
+        "{}",
+        err.message
+    );
+
+    manifest(&dir, "z80");
+    let err = resolve_backend(Some(&file), "HLT\n", &Settings::default()).unwrap_err();
+    assert_eq!(
+        err.message,
+        format!(
+            "{}: {}",
+            dir.join("smc.toml").display(),
+            resolve_backend(None, "// smc-target: z80", &Settings::default())
+                .unwrap_err()
+                .message
+        )
+    );
+}
+
+#[test]
+fn rejects_invalid_settings() {
+    let settings = Settings::from_value(None).unwrap();
+    assert!(settings.inlay_hints.addresses && settings.backends.is_empty());
+
+    let settings = Settings::from_value(Some(json!({ "inlayHints": { "encoding": true } })));
+    let settings = settings.unwrap();
+    assert!(settings.inlay_hints.encoding && settings.inlay_hints.addresses);
+
+    assert!(Settings::from_value(Some(json!({ "backends": "*.smc" }))).is_err());
+}
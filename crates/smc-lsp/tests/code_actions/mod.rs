@@ -0,0 +1,130 @@
+use smc_assembler::assembler::backends::Backend;
+use smc_lsp::{code_actions::code_actions, position_to_offset};
+use tower_lsp::lsp_types::{CodeAction, CodeActionOrCommand, Position, Range, Url};
+
+use crate::analyze;
+
+/// The code actions for the lines `first..=last` of `source`
+fn actions(source: &str, backend: Backend, first: u32, last: u32) -> Vec<CodeAction> {
+    let uri = Url::parse("untitled:test").unwrap();
+    let range = Range::new(Position::new(first, 0), Position::new(last + 1, 0));
+
+    code_actions(&uri, &analyze(source, backend), range)
+        .into_iter()
+        .map(|action| match action {
+            CodeActionOrCommand::CodeAction(action) => action,
+            CodeActionOrCommand::Command(command) => panic!("unexpected command {:?}", command),
+        })
+        .collect()
+}
+
+/// `source` after the edits of the action titled `title` on the lines `first..=last`
+fn apply(source: &str, backend: Backend, first: u32, last: u32, title: &str) -> String {
+    let actions = actions(source, backend, first, last);
+    let Some(action) = actions.iter().find(|action| action.title == title) else {
+        let titles: Vec<_> = actions.iter().map(|action| &action.title).collect();
+        panic!("no action `{}` in {:?}", title, titles);
+    };
+
+    let changes = action.edit.as_ref().unwrap().changes.as_ref().unwrap();
+    let mut edits: Vec<_> = changes.values().flatten().collect();
+    assert_eq!(changes.len(), 1, "edits should only touch the document");
+
+    // Applied from the end, so earlier offsets stay valid
+    edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+    let mut result = source.to_string();
+    for edit in edits.into_iter().rev() {
+        let start = position_to_offset(edit.range.start, source).unwrap();
+        let end = position_to_offset(edit.range.end, source).unwrap();
+        result.replace_range(start..end, &edit.new_text);
+    }
+    result
+}
+
+#[test]
+fn wraps_or_clamps_out_of_range_immediates() {
+    let source = "LDI r1 300\nHLT\n";
+
+    assert_eq!(
+        apply(source, Backend::BatPU2, 0, 0, "Wrap immediate to 44"),
+        "LDI r1 44\nHLT\n"
+    );
+    assert_eq!(
+        apply(source, Backend::BatPU2, 0, 0, "Clamp immediate to 255"),
+        "LDI r1 255\nHLT\n"
+    );
+}
+
+#[test]
+fn creates_missing_defines_and_labels() {
+    assert_eq!(
+        apply(
+            "define A 1\nLDI r1 B\n",
+            Backend::BatPU2,
+            1,
+            1,
+            "Create define `B`"
+        ),
+        "define A 1\ndefine B 0\nLDI r1 B\n"
+    );
+    assert_eq!(
+        apply(
+            "JMP .end",
+            Backend::TauAnalyzersNone,
+            0,
+            0,
+            "Create label `.end`"
+        ),
+        "JMP .end\n\n.end\n"
+    );
+}
+
+#[test]
+fn replaces_unsupported_operations_with_their_lowering() {
+    // Every line of the lowering keeps the indent of the operation
+    assert_eq!(
+        apply(
+            "  OR r1 r2\n  HLT\n",
+            Backend::BatPU2,
+            0,
+            0,
+            "Replace with `NOR r1 r2 r1; NOR r1 r0 r1`"
+        ),
+        "  NOR r1 r2 r1\n  NOR r1 r0 r1\n  HLT\n"
+    );
+    // MOV copies its first register into its second
+    assert_eq!(
+        apply(
+            "MOV R1 R2\n",
+            Backend::TauAnalyzersNone,
+            0,
+            0,
+            "Replace with `CPY r2 r1`"
+        ),
+        "CPY r2 r1\n"
+    );
+}
+
+#[test]
+fn extracts_selected_lines_into_a_subroutine() {
+    let source = "LDI r1 1\nADI r1 2\nHLT\n";
+
+    assert_eq!(
+        apply(
+            source,
+            Backend::BatPU2,
+            0,
+            1,
+            "Extract into subroutine `.subroutine`"
+        ),
+        "CAL .subroutine\nHLT\n\n.subroutine\nLDI r1 1\nADI r1 2\nRET\n"
+    );
+
+    // Code jumped into can't be moved
+    let source = "LDI r1 1\n.loop\nJMP .loop\n";
+    assert!(
+        actions(source, Backend::BatPU2, 0, 2)
+            .iter()
+            .all(|action| !action.title.starts_with("Extract"))
+    );
+}
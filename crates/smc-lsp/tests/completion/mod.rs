@@ -0,0 +1,87 @@
+use smc_assembler::assembler::backends::Backend;
+use smc_lsp::completion::complete;
+
+use crate::{CURSOR, analyze};
+
+/// Labels of the items completed at the cursor in `source`, sorted
+fn labels(source: &str, backend: Backend) -> Vec<String> {
+    let offset = source.find(CURSOR).expect("source should have a cursor");
+    let definitions = analyze(&source.replace(CURSOR, ""), backend);
+
+    let mut labels: Vec<_> = complete(&definitions, offset)
+        .into_iter()
+        .map(|item| item.label)
+        .collect();
+    labels.sort();
+    labels
+}
+
+#[test]
+fn completes_mnemonics_the_backend_supports() {
+    let batpu2 = labels("|", Backend::BatPU2);
+    assert!(batpu2.contains(&"BRH".to_string()));
+    assert!(!batpu2.contains(&"SKP".to_string()));
+
+    let tau = labels("|", Backend::TauAnalyzersNone);
+    assert!(tau.contains(&"SKP".to_string()));
+    assert!(!tau.contains(&"BRH".to_string()));
+
+    // After a label on the same line, and following the casing typed so far
+    assert!(labels(".loop ld|", Backend::BatPU2).contains(&"ldi".to_string()));
+}
+
+#[test]
+fn completes_registers_of_the_backend() {
+    let batpu2 = labels("ADD |", Backend::BatPU2);
+    assert_eq!(batpu2.len(), 16);
+    assert!(batpu2.contains(&"r15".to_string()));
+
+    assert_eq!(
+        labels("ADD R1 |", Backend::TauAnalyzersNone),
+        ["r0", "r1", "r2", "r3"]
+    );
+}
+
+#[test]
+fn completes_labels_in_address_slots() {
+    // The operand of an unfinished jump would take a label on the next line, so it is last
+    let source = "define SPEED 3\n.start\nHLT\n.end\nJMP |";
+
+    for backend in [Backend::BatPU2, Backend::TauAnalyzersNone] {
+        assert_eq!(labels(source, backend), [".end", ".start"]);
+    }
+}
+
+#[test]
+fn completes_defines_in_immediate_and_offset_slots() {
+    let source = "define SPEED 3\n.start\nLDI r1 |\n";
+    assert_eq!(labels(source, Backend::TauAnalyzersNone), ["SPEED"]);
+
+    // The BatPU-2 also defines the ports of its devices
+    let batpu2 = labels(source, Backend::BatPU2);
+    assert!(batpu2.contains(&"SPEED".to_string()));
+    assert!(batpu2.contains(&"rng".to_string()));
+    assert!(!batpu2.contains(&".start".to_string()));
+
+    let offset = labels("define SPEED 3\nLOD r1 r2 |\n", Backend::BatPU2);
+    assert!(offset.contains(&"SPEED".to_string()));
+}
+
+#[test]
+fn completes_conditions_and_skip_flags() {
+    assert_eq!(labels("BRH |", Backend::BatPU2), ["eq", "ge", "lt", "ne"]);
+    assert_eq!(labels("BRH N|", Backend::BatPU2), ["EQ", "GE", "LT", "NE"]);
+
+    assert_eq!(
+        labels("SKP |", Backend::TauAnalyzersNone),
+        ["!", "!-", "!0", "-", "0"]
+    );
+}
+
+#[test]
+fn completes_nothing_in_comments_or_past_the_operands() {
+    for backend in [Backend::BatPU2, Backend::TauAnalyzersNone] {
+        assert!(labels("LDI r1 1 // |", backend.clone()).is_empty());
+        assert!(labels("HLT |", backend).is_empty());
+    }
+}
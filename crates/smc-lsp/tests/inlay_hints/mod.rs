@@ -0,0 +1,119 @@
+use smc_assembler::assembler::backends::Backend;
+use smc_lsp::{inlay_hints::inlay_hints, settings::InlayHintSettings};
+use tower_lsp::lsp_types::{InlayHintLabel, Position, Range};
+
+use crate::analyze;
+
+const SOURCE: &str = "define STEP 3\nLDI r1 STEP\nADI r1 1\nHLT\n";
+
+/// The whole of every source in these tests
+const ALL: Range = Range {
+    start: Position {
+        line: 0,
+        character: 0,
+    },
+    end: Position {
+        line: 100,
+        character: 0,
+    },
+};
+
+/// Line, column and label of every hint in `range`
+fn hints(
+    source: &str,
+    backend: Backend,
+    settings: &InlayHintSettings,
+    range: Range,
+) -> Vec<(u32, u32, String)> {
+    inlay_hints(&analyze(source, backend), settings, range)
+        .into_iter()
+        .map(|hint| {
+            let InlayHintLabel::String(label) = hint.label else {
+                panic!("hint labels should be plain strings");
+            };
+            (hint.position.line, hint.position.character, label)
+        })
+        .collect()
+}
+
+fn only(addresses: bool, define_values: bool, encoding: bool) -> InlayHintSettings {
+    InlayHintSettings {
+        addresses,
+        define_values,
+        encoding,
+    }
+}
+
+fn hint(line: u32, character: u32, label: &str) -> (u32, u32, String) {
+    (line, character, label.to_string())
+}
+
+#[test]
+fn shows_addresses_of_instructions() {
+    let settings = only(true, false, false);
+    assert_eq!(
+        hints(SOURCE, Backend::BatPU2, &settings, ALL),
+        [hint(1, 0, "0:"), hint(2, 0, "1:"), hint(3, 0, "2:")]
+    );
+
+    // Tau addresses count bytes, and CLR takes two
+    assert_eq!(
+        hints("CLR R1\nHLT\n", Backend::TauAnalyzersNone, &settings, ALL),
+        [hint(0, 0, "0:"), hint(1, 0, "2:")]
+    );
+}
+
+#[test]
+fn shows_values_of_defines() {
+    assert_eq!(
+        hints(SOURCE, Backend::BatPU2, &only(false, true, false), ALL),
+        [hint(1, 11, "= 3")]
+    );
+}
+
+#[test]
+fn shows_encodings_when_enabled() {
+    // Off by default
+    assert_eq!(
+        hints(SOURCE, Backend::BatPU2, &InlayHintSettings::default(), ALL),
+        [
+            hint(1, 0, "0:"),
+            hint(1, 11, "= 3"),
+            hint(2, 0, "1:"),
+            hint(3, 0, "2:"),
+        ]
+    );
+
+    assert_eq!(
+        hints(SOURCE, Backend::BatPU2, &only(false, false, true), ALL),
+        [
+            hint(1, 11, "10000001 00000011"),
+            hint(2, 8, "10010001 00000001"),
+            hint(3, 3, "00010000 00000000"),
+        ]
+    );
+}
+
+#[test]
+fn shows_only_hints_in_the_range() {
+    let line = |line| Range::new(Position::new(line, 0), Position::new(line, 20));
+
+    assert_eq!(
+        hints(
+            SOURCE,
+            Backend::BatPU2,
+            &InlayHintSettings::default(),
+            line(1)
+        ),
+        [hint(1, 0, "0:"), hint(1, 11, "= 3")]
+    );
+    assert_eq!(
+        hints(
+            SOURCE,
+            Backend::BatPU2,
+            &InlayHintSettings::default(),
+            line(3)
+        ),
+        [hint(3, 0, "2:")]
+    );
+}
@@ -0,0 +1,29 @@
+pub mod backends;
+pub mod code_actions;
+pub mod completion;
+pub mod inlay_hints;
+
+use smc_assembler::assembler::backends::Backend;
+use smc_lsp::Definitions;
+use std::{env, fs, path::PathBuf, process};
+
+/// Marks the cursor in test sources
+pub const CURSOR: &str = "|";
+
+/// Assemble `source` as an unsaved document
+pub fn analyze(source: &str, backend: Backend) -> Definitions {
+    Definitions::analyze("untitled:test".to_string(), None, source, backend).0
+}
+
+/// A fresh directory for a test to write its files into
+pub fn project_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("smc-lsp-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("temp dir should be writable");
+    dir
+}
+
+pub fn write(path: PathBuf, text: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, text).unwrap();
+}
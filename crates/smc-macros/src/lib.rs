@@ -146,8 +146,7 @@ fn generate_trie_match(
 
             // If this node is also terminal, we need to try the longer match first,
             // then fall back to this terminal if the longer match fails
-            let arm_body = if node.terminal.is_some() {
-                let idx = node.terminal.unwrap();
+            let arm_body = if let Some(idx) = node.terminal {
                 let fallback_expr = expressions[idx];
                 let fallback_len = keywords[idx].len();
                 let fallback_with_check = generate_terminal_with_boundary_check(